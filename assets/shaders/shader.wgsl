@@ -6,16 +6,40 @@ struct CameraUniform {
 [[group(0), binding(0)]]
 var<uniform> camera: CameraUniform;
 
+struct ModelUniform {
+	model_matrix: mat4x4<f32>;
+	normal_matrix: mat4x4<f32>;
+};
+[[group(2), binding(0)]]
+var<uniform> transform: ModelUniform;
+
 struct VertexInput {
 	[[location(0)]] position: vec3<f32>;
 	[[location(1)]] color: vec3<f32>;
 	[[location(2)]] uv: vec2<f32>;
+	[[location(3)]] normal: vec3<f32>;
+	[[location(4)]] tangent: vec4<f32>;
+};
+
+struct InstanceInput {
+	[[location(8)]] model_matrix_0: vec4<f32>;
+	[[location(9)]] model_matrix_1: vec4<f32>;
+	[[location(10)]] model_matrix_2: vec4<f32>;
+	[[location(11)]] model_matrix_3: vec4<f32>;
+	[[location(12)]] normal_matrix_0: vec3<f32>;
+	[[location(13)]] normal_matrix_1: vec3<f32>;
+	[[location(14)]] normal_matrix_2: vec3<f32>;
+	[[location(15)]] color: vec4<f32>;
 };
 
 struct VertexOutput {
 	[[builtin(position)]] clip_position: vec4<f32>;
 	[[location(0)]] color: vec3<f32>;
 	[[location(1)]] uv: vec2<f32>;
+	[[location(2)]] tint: vec4<f32>;
+	[[location(3)]] world_normal: vec3<f32>;
+	// w is the handedness, bitangent = w * cross(normal, tangent)
+	[[location(4)]] world_tangent: vec4<f32>;
 };
 
 [[stage(vertex)]]
@@ -25,20 +49,74 @@ fn vs_main(
 	var out: VertexOutput;
 	out.color = model.color;
 	out.uv = model.uv;
-	out.clip_position = camera.view_projection_matrix * vec4<f32>(model.position, 1.0);
+	out.tint = vec4<f32>(1.0, 1.0, 1.0, 1.0);
+	out.world_normal = normalize((transform.normal_matrix * vec4<f32>(model.normal, 0.0)).xyz);
+	out.world_tangent = vec4<f32>(
+		normalize((transform.model_matrix * vec4<f32>(model.tangent.xyz, 0.0)).xyz),
+		model.tangent.w,
+	);
+	let world_position = transform.model_matrix * vec4<f32>(model.position, 1.0);
+	out.clip_position = camera.view_projection_matrix * world_position;
+	return out;
+}
+
+[[stage(vertex)]]
+fn vs_instanced(
+	model: VertexInput,
+	instance: InstanceInput,
+) -> VertexOutput {
+	let instance_matrix = mat4x4<f32>(
+		instance.model_matrix_0,
+		instance.model_matrix_1,
+		instance.model_matrix_2,
+		instance.model_matrix_3,
+	);
+	let instance_normal_matrix = mat3x3<f32>(
+		instance.normal_matrix_0,
+		instance.normal_matrix_1,
+		instance.normal_matrix_2,
+	);
+	let model_matrix = transform.model_matrix * instance_matrix;
+
+	var out: VertexOutput;
+	out.color = model.color;
+	out.uv = model.uv;
+	out.tint = instance.color;
+	let instance_normal = instance_normal_matrix * model.normal;
+	out.world_normal = normalize((transform.normal_matrix * vec4<f32>(instance_normal, 0.0)).xyz);
+	out.world_tangent = vec4<f32>(
+		normalize((model_matrix * vec4<f32>(model.tangent.xyz, 0.0)).xyz),
+		model.tangent.w,
+	);
+	let world_position = model_matrix * vec4<f32>(model.position, 1.0);
+	out.clip_position = camera.view_projection_matrix * world_position;
 	return out;
 }
 
 
 // Fragment shader
+struct MaterialUniform {
+	base_color: vec4<f32>;
+	roughness: f32;
+};
 [[group(1), binding(0)]]
-var diffuse_texture: texture_2d<f32>;
+var base_color_texture: texture_2d<f32>;
 [[group(1), binding(1)]]
-var diffuse_sampler: sampler;
+var base_color_sampler: sampler;
+[[group(1), binding(2)]]
+var normal_texture: texture_2d<f32>;
+[[group(1), binding(3)]]
+var normal_sampler: sampler;
+[[group(1), binding(4)]]
+var roughness_texture: texture_2d<f32>;
+[[group(1), binding(5)]]
+var roughness_sampler: sampler;
+[[group(1), binding(6)]]
+var<uniform> material: MaterialUniform;
 
 [[stage(fragment)]]
 fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
-    var diffuse = textureSample(diffuse_texture, diffuse_sampler, in.uv);
-	return diffuse;
+    var diffuse = textureSample(base_color_texture, base_color_sampler, in.uv);
+	return diffuse * material.base_color * in.tint;
 	// return vec4<f32>(in.color, 1.0);
 }
\ No newline at end of file
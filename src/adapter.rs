@@ -0,0 +1,334 @@
+// File: adapter.rs
+
+use wgpu::DeviceType;
+
+use std::fmt;
+
+/// How `select_adapter` picks between the adapters of an instance.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AdapterSelection {
+	/// Discrete GPUs first, then integrated, virtual and cpu adapters.
+	PreferDiscrete,
+	/// Integrated GPUs first, then discrete, virtual and cpu adapters.
+	PreferIntegrated,
+	/// Only software (cpu) adapters such as lavapipe or WARP.
+	SoftwareFallback,
+	/// The first adapter whose name contains this, ignoring case.
+	NameContains(String),
+	/// The adapter at this position in `Instance::enumerate_adapters`.
+	Index(usize),
+}
+impl AdapterSelection {
+	/// `WGPU_ADAPTER_NAME` selects an adapter by name, like `wgpu::util` does.
+	pub fn from_env() -> Option<Self> {
+		std::env::var("WGPU_ADAPTER_NAME")
+			.ok()
+			.filter(|name| !name.is_empty())
+			.map(AdapterSelection::NameContains)
+	}
+	pub fn from_power_preference(power_preference: wgpu::PowerPreference) -> Self {
+		match power_preference {
+			wgpu::PowerPreference::LowPower => AdapterSelection::PreferIntegrated,
+			wgpu::PowerPreference::HighPerformance => AdapterSelection::PreferDiscrete,
+		}
+	}
+	fn device_type_rank(&self, device_type: DeviceType) -> Option<u8> {
+		let order: &[DeviceType] = match self {
+			AdapterSelection::PreferDiscrete => &[
+				DeviceType::DiscreteGpu,
+				DeviceType::IntegratedGpu,
+				DeviceType::VirtualGpu,
+				DeviceType::Other,
+				DeviceType::Cpu,
+			],
+			AdapterSelection::PreferIntegrated => &[
+				DeviceType::IntegratedGpu,
+				DeviceType::DiscreteGpu,
+				DeviceType::VirtualGpu,
+				DeviceType::Other,
+				DeviceType::Cpu,
+			],
+			AdapterSelection::SoftwareFallback => &[DeviceType::Cpu],
+			AdapterSelection::NameContains(_) | AdapterSelection::Index(_) => return Some(0),
+		};
+		order
+			.iter()
+			.position(|t| *t == device_type)
+			.map(|rank| rank as u8)
+	}
+}
+
+/// Returned when no adapter satisfies an `AdapterSelection`.
+#[derive(Debug)]
+pub struct AdapterSelectionError {
+	pub selection: AdapterSelection,
+	pub backends: wgpu::Backends,
+	/// Every adapter of the instance, in enumeration order.
+	pub available: Vec<wgpu::AdapterInfo>,
+}
+impl fmt::Display for AdapterSelectionError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"no adapter matches {:?} on backends {:?}",
+			self.selection, self.backends
+		)?;
+		if self.available.is_empty() {
+			return write!(f, ", no adapters are available");
+		}
+		write!(f, ", available adapters:")?;
+		for (index, info) in self.available.iter().enumerate() {
+			write!(
+				f,
+				"\n  [{}] {} ({:?}, {:?})",
+				index, info.name, info.device_type, info.backend
+			)?;
+		}
+		Ok(())
+	}
+}
+impl std::error::Error for AdapterSelectionError {}
+
+/// Picks an adapter of `instance` according to `selection`. When a surface is
+/// given, adapters that can not present to it are skipped.
+pub fn select_adapter(
+	instance: &wgpu::Instance,
+	backends: wgpu::Backends,
+	selection: &AdapterSelection,
+	surface: Option<&wgpu::Surface>,
+) -> Result<wgpu::Adapter, AdapterSelectionError> {
+	let mut adapters: Vec<wgpu::Adapter> = instance.enumerate_adapters(backends).collect();
+	let available: Vec<wgpu::AdapterInfo> =
+		adapters.iter().map(|adapter| adapter.get_info()).collect();
+
+	for (index, info) in available.iter().enumerate() {
+		log::debug!("adapter [{}] {:?}", index, info);
+	}
+
+	let selected = select_adapter_index(selection, &available, |index| match surface {
+		Some(surface) => adapters[index].is_surface_supported(surface),
+		None => true,
+	});
+
+	match selected {
+		Some(index) => Ok(adapters.swap_remove(index)),
+		None => Err(AdapterSelectionError {
+			selection: selection.clone(),
+			backends,
+			available,
+		}),
+	}
+}
+
+/// The position in `available` of the adapter `selection` picks, skipping the
+/// ones `supported` rejects.
+fn select_adapter_index(
+	selection: &AdapterSelection,
+	available: &[wgpu::AdapterInfo],
+	supported: impl Fn(usize) -> bool,
+) -> Option<usize> {
+	match selection {
+		AdapterSelection::Index(index) => {
+			Some(*index).filter(|index| *index < available.len() && supported(*index))
+		}
+		AdapterSelection::NameContains(name) => {
+			let name = name.to_lowercase();
+			available.iter().enumerate().position(|(index, info)| {
+				info.name.to_lowercase().contains(&name) && supported(index)
+			})
+		}
+		_ => available
+			.iter()
+			.enumerate()
+			.filter(|(index, _)| supported(*index))
+			.filter_map(|(index, info)| {
+				let rank = selection.device_type_rank(info.device_type)?;
+				Some((rank, index))
+			})
+			// min_by_key keeps the first of equally ranked adapters
+			.min_by_key(|(rank, _)| *rank)
+			.map(|(_, index)| index),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn info(name: &str, device_type: DeviceType) -> wgpu::AdapterInfo {
+		wgpu::AdapterInfo {
+			name: name.to_string(),
+			vendor: 0,
+			device: 0,
+			device_type,
+			backend: wgpu::Backend::Vulkan,
+		}
+	}
+
+	fn adapters() -> Vec<wgpu::AdapterInfo> {
+		vec![
+			info("llvmpipe (LLVM 15.0.6, 256 bits)", DeviceType::Cpu),
+			info("Intel(R) UHD Graphics 620", DeviceType::IntegratedGpu),
+			info("NVIDIA GeForce GTX 1060", DeviceType::DiscreteGpu),
+			info("AMD Radeon RX 580", DeviceType::DiscreteGpu),
+		]
+	}
+
+	fn select(selection: AdapterSelection) -> Option<usize> {
+		select_adapter_index(&selection, &adapters(), |_| true)
+	}
+
+	#[test]
+	fn power_preference_maps_to_device_type_order() {
+		assert_eq!(
+			AdapterSelection::from_power_preference(wgpu::PowerPreference::LowPower),
+			AdapterSelection::PreferIntegrated
+		);
+		assert_eq!(
+			AdapterSelection::from_power_preference(wgpu::PowerPreference::HighPerformance),
+			AdapterSelection::PreferDiscrete
+		);
+	}
+
+	#[test]
+	fn device_type_ranks() {
+		let discrete = AdapterSelection::PreferDiscrete;
+		assert_eq!(discrete.device_type_rank(DeviceType::DiscreteGpu), Some(0));
+		assert_eq!(
+			discrete.device_type_rank(DeviceType::IntegratedGpu),
+			Some(1)
+		);
+		assert_eq!(discrete.device_type_rank(DeviceType::Cpu), Some(4));
+
+		let integrated = AdapterSelection::PreferIntegrated;
+		assert_eq!(
+			integrated.device_type_rank(DeviceType::IntegratedGpu),
+			Some(0)
+		);
+		assert_eq!(
+			integrated.device_type_rank(DeviceType::DiscreteGpu),
+			Some(1)
+		);
+
+		let software = AdapterSelection::SoftwareFallback;
+		assert_eq!(software.device_type_rank(DeviceType::Cpu), Some(0));
+		assert_eq!(software.device_type_rank(DeviceType::DiscreteGpu), None);
+
+		let by_name = AdapterSelection::NameContains("nvidia".to_string());
+		assert_eq!(by_name.device_type_rank(DeviceType::Cpu), Some(0));
+	}
+
+	#[test]
+	fn device_type_preferences_pick_the_first_best_ranked() {
+		assert_eq!(select(AdapterSelection::PreferDiscrete), Some(2));
+		assert_eq!(select(AdapterSelection::PreferIntegrated), Some(1));
+		assert_eq!(select(AdapterSelection::SoftwareFallback), Some(0));
+	}
+
+	#[test]
+	fn names_match_substrings_ignoring_case() {
+		assert_eq!(
+			select(AdapterSelection::NameContains("radeon".to_string())),
+			Some(3)
+		);
+		assert_eq!(
+			select(AdapterSelection::NameContains("GEFORCE".to_string())),
+			Some(2)
+		);
+		assert_eq!(
+			select(AdapterSelection::NameContains("Apple".to_string())),
+			None
+		);
+	}
+
+	#[test]
+	fn indices_must_be_in_range() {
+		assert_eq!(select(AdapterSelection::Index(1)), Some(1));
+		assert_eq!(select(AdapterSelection::Index(4)), None);
+	}
+
+	#[test]
+	fn unsupported_adapters_are_skipped() {
+		let available = adapters();
+		let no_nvidia = |index: usize| index != 2;
+		assert_eq!(
+			select_adapter_index(&AdapterSelection::PreferDiscrete, &available, no_nvidia),
+			Some(3)
+		);
+		assert_eq!(
+			select_adapter_index(&AdapterSelection::Index(2), &available, no_nvidia),
+			None
+		);
+		assert_eq!(
+			select_adapter_index(
+				&AdapterSelection::NameContains("nvidia".to_string()),
+				&available,
+				no_nvidia
+			),
+			None
+		);
+	}
+
+	#[test]
+	fn error_lists_the_available_adapters() {
+		let error = AdapterSelectionError {
+			selection: AdapterSelection::NameContains("Apple".to_string()),
+			backends: wgpu::Backends::VULKAN,
+			available: adapters(),
+		};
+		let message = error.to_string();
+		assert!(message.starts_with("no adapter matches NameContains(\"Apple\")"));
+		assert!(message.contains("\n  [0] llvmpipe (LLVM 15.0.6, 256 bits) (Cpu, Vulkan)"));
+		assert!(message.contains("\n  [3] AMD Radeon RX 580 (DiscreteGpu, Vulkan)"));
+
+		let error = AdapterSelectionError {
+			available: vec![],
+			..error
+		};
+		assert!(error.to_string().ends_with(", no adapters are available"));
+	}
+
+	// the only test that sets these variables, so it can not race another
+	#[test]
+	fn environment_overrides_the_settings() {
+		let previous: Vec<_> = ["WGPU_ADAPTER_NAME", "WGPU_BACKEND"]
+			.iter()
+			.map(|key| (*key, std::env::var_os(key)))
+			.collect();
+		let settings = crate::settings::RendererSettings {
+			adapter: Some(AdapterSelection::Index(1)),
+			..Default::default()
+		};
+
+		std::env::remove_var("WGPU_ADAPTER_NAME");
+		std::env::remove_var("WGPU_BACKEND");
+		assert_eq!(AdapterSelection::from_env(), None);
+		assert_eq!(settings.effective_adapter(), AdapterSelection::Index(1));
+		assert_eq!(settings.effective_backends(), wgpu::Backends::PRIMARY);
+
+		std::env::set_var("WGPU_ADAPTER_NAME", "");
+		assert_eq!(AdapterSelection::from_env(), None);
+
+		std::env::set_var("WGPU_ADAPTER_NAME", "radeon");
+		std::env::set_var("WGPU_BACKEND", "Vulkan,GL");
+		assert_eq!(
+			AdapterSelection::from_env(),
+			Some(AdapterSelection::NameContains("radeon".to_string()))
+		);
+		assert_eq!(
+			settings.effective_adapter(),
+			AdapterSelection::NameContains("radeon".to_string())
+		);
+		assert_eq!(
+			settings.effective_backends(),
+			wgpu::Backends::VULKAN | wgpu::Backends::GL
+		);
+
+		for (key, value) in previous {
+			match value {
+				Some(value) => std::env::set_var(key, value),
+				None => std::env::remove_var(key),
+			}
+		}
+	}
+}
@@ -8,6 +8,11 @@ use cgmath::SquareMatrix;
 pub struct CameraUniform {
 	view_projection_matrix: [[f32; 4]; 4],
 }
+impl Default for CameraUniform {
+	fn default() -> Self {
+		Self::new()
+	}
+}
 impl CameraUniform {
 	pub fn new() -> Self {
 		Self {
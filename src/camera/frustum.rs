@@ -0,0 +1,132 @@
+// File: camera/frustum.rs
+
+use crate::mesh::{Aabb, BoundingSphere};
+
+use bevy::math::{Mat4, Vec3, Vec4};
+
+/// The volume a camera sees, as six planes with normals pointing inwards.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Frustum {
+	/// `xyz` is the unit normal and `w` the distance, so a point `p` is inside
+	/// a plane when `dot(xyz, p) + w >= 0`.
+	pub planes: [Vec4; 6],
+}
+impl Frustum {
+	/// Extracts the planes of a view projection matrix with wgpu's 0 to 1 depth
+	/// range, in the order left, right, bottom, top, near, far.
+	pub fn from_view_projection(view_projection: &Mat4) -> Self {
+		let [row0, row1, row2, row3] = [0, 1, 2, 3].map(|i| view_projection.row(i));
+		let planes = [
+			row3 + row0,
+			row3 - row0,
+			row3 + row1,
+			row3 - row1,
+			row2,
+			row3 - row2,
+		]
+		.map(|plane| plane / plane.truncate().length());
+		Self { planes }
+	}
+	pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+		self.planes
+			.iter()
+			.all(|plane| distance(plane, sphere.center) >= -sphere.radius)
+	}
+	pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+		let center = aabb.center();
+		let half_extents = aabb.half_extents();
+		self.planes.iter().all(|plane| {
+			let reach = half_extents.dot(plane.truncate().abs());
+			distance(plane, center) >= -reach
+		})
+	}
+}
+
+fn distance(plane: &Vec4, point: Vec3) -> f32 {
+	plane.truncate().dot(point) + plane.w
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::camera::{Camera, Projection};
+
+	/// Looking down -z from z = 5, seeing from 0.1 to 100.
+	fn frustum() -> Frustum {
+		let projection = Mat4::perspective_rh(45f32.to_radians(), 1.5, 0.1, 100.0);
+		let view = Mat4::look_at_rh(Vec3::new(0.0, 0.0, 5.0), Vec3::ZERO, Vec3::Y);
+		Frustum::from_view_projection(&(projection * view))
+	}
+	fn point(x: f32, y: f32, z: f32) -> BoundingSphere {
+		BoundingSphere {
+			center: Vec3::new(x, y, z),
+			radius: 0.0,
+		}
+	}
+
+	#[test]
+	fn planes_are_normalized() {
+		for plane in frustum().planes {
+			assert!((plane.truncate().length() - 1.0).abs() < 1e-5);
+		}
+	}
+
+	#[test]
+	fn points() {
+		let frustum = frustum();
+		assert!(frustum.intersects_sphere(&point(0.0, 0.0, 0.0)));
+		assert!(frustum.intersects_sphere(&point(0.0, 0.0, -94.0)));
+		// behind the camera and before the near plane
+		assert!(!frustum.intersects_sphere(&point(0.0, 0.0, 6.0)));
+		assert!(!frustum.intersects_sphere(&point(0.0, 0.0, 4.95)));
+		// past zfar
+		assert!(!frustum.intersects_sphere(&point(0.0, 0.0, -96.0)));
+		// beside and above the view
+		assert!(!frustum.intersects_sphere(&point(10.0, 0.0, 0.0)));
+		assert!(!frustum.intersects_sphere(&point(0.0, 10.0, 0.0)));
+		// wider than high
+		assert!(frustum.intersects_sphere(&point(2.5, 0.0, 0.0)));
+		assert!(!frustum.intersects_sphere(&point(0.0, 2.5, 0.0)));
+	}
+
+	#[test]
+	fn spheres_reaching_in_intersect() {
+		let frustum = frustum();
+		let sphere = |x: f32, radius: f32| BoundingSphere {
+			center: Vec3::new(x, 0.0, 0.0),
+			radius,
+		};
+		assert!(!frustum.intersects_sphere(&sphere(10.0, 1.0)));
+		assert!(frustum.intersects_sphere(&sphere(10.0, 8.5)));
+	}
+
+	#[test]
+	fn boxes() {
+		let frustum = frustum();
+		let cube = |center: Vec3| Aabb::new(center - Vec3::ONE, center + Vec3::ONE);
+		assert!(frustum.intersects_aabb(&cube(Vec3::ZERO)));
+		assert!(frustum.intersects_aabb(&cube(Vec3::new(0.0, 0.0, 5.5))));
+		assert!(!frustum.intersects_aabb(&cube(Vec3::new(0.0, 0.0, 7.0))));
+		assert!(!frustum.intersects_aabb(&cube(Vec3::new(0.0, 0.0, -97.0))));
+		assert!(!frustum.intersects_aabb(&cube(Vec3::new(10.0, 0.0, 0.0))));
+	}
+
+	#[test]
+	fn camera_frustum_uses_wgpu_depth() {
+		let projection = Projection::with_aspect(1.5, 45.0, 0.1, 100.0);
+		let camera = Camera::new(
+			cgmath::Point3::new(0.0, 0.0, 5.0),
+			cgmath::Point3::new(0.0, 0.0, 0.0),
+			&projection,
+		);
+		let expected = frustum();
+		for (plane, expected) in camera.frustum().planes.iter().zip(expected.planes) {
+			assert!(
+				plane.abs_diff_eq(expected, 1e-4),
+				"{} != {}",
+				plane,
+				expected
+			);
+		}
+	}
+}
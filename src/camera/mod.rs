@@ -3,11 +3,13 @@
 mod camera_bind_group;
 mod camera_controller;
 mod camera_uniform;
+mod frustum;
 mod projection;
 
 pub use camera_bind_group::CameraBindGroup;
 pub use camera_controller::CameraController;
 pub use camera_uniform::CameraUniform;
+pub use frustum::Frustum;
 pub use projection::Projection;
 
 use cgmath::SquareMatrix;
@@ -60,6 +62,22 @@ impl Camera {
 		self.view_matrix = cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up);
 		self.update_view_projection_matrix();
 	}
+	/// What the camera sees, for culling.
+	pub fn frustum(&self) -> Frustum {
+		let view_projection: [[f32; 4]; 4] = self.view_projection_matrix.into();
+		Frustum::from_view_projection(&bevy::math::Mat4::from_cols_array_2d(&view_projection))
+	}
+	/// Height of a world space `sphere` on screen relative to the screen height,
+	/// for picking levels of detail. Infinite when the camera is inside it.
+	pub fn screen_size(&self, sphere: &crate::mesh::BoundingSphere) -> f32 {
+		let eye = bevy::math::Vec3::new(self.eye.x, self.eye.y, self.eye.z);
+		let distance = sphere.center.distance(eye);
+		if distance <= sphere.radius {
+			return f32::INFINITY;
+		}
+		let half_height = (self.projection.fovy.to_radians() * 0.5).tan();
+		sphere.radius / (distance * half_height)
+	}
 	fn update_view_projection_matrix(&mut self) {
 		self.view_projection_matrix =
 			OPENGL_TO_WGPU_MATRIX * self.projection.matrix * self.view_matrix;
@@ -83,3 +101,59 @@ impl Camera {
 		self.update_projection_matrix();
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::mesh::BoundingSphere;
+
+	use bevy::math::Vec3;
+
+	#[test]
+	fn screen_size_at_a_known_distance() {
+		let projection = Projection::new(800, 600, 45.0, 0.1, 100.0);
+		let camera = Camera::new(
+			cgmath::Point3::new(0.0, 0.0, 10.0),
+			cgmath::Point3::new(0.0, 0.0, 0.0),
+			&projection,
+		);
+		let half_height = 10.0 * (22.5_f32).to_radians().tan();
+
+		let sphere = BoundingSphere {
+			center: Vec3::ZERO,
+			radius: 1.0,
+		};
+		let size = camera.screen_size(&sphere);
+		assert!((size - 1.0 / half_height).abs() < 1e-6);
+
+		// a sphere filling the visible height covers the whole screen
+		let filling = BoundingSphere {
+			center: Vec3::ZERO,
+			radius: half_height,
+		};
+		assert!((camera.screen_size(&filling) - 1.0).abs() < 1e-6);
+
+		// twice as far is half as large, whatever the direction
+		let farther = BoundingSphere {
+			center: Vec3::new(0.0, 12.0, -6.0),
+			radius: 1.0,
+		};
+		assert!((camera.screen_size(&farther) - size * 0.5).abs() < 1e-5);
+	}
+
+	#[test]
+	fn screen_size_inside_is_infinite() {
+		let projection = Projection::new(800, 600, 45.0, 0.1, 100.0);
+		let camera = Camera::new(
+			cgmath::Point3::new(0.0, 0.0, 0.5),
+			cgmath::Point3::new(0.0, 0.0, 0.0),
+			&projection,
+		);
+		let sphere = BoundingSphere {
+			center: Vec3::ZERO,
+			radius: 1.0,
+		};
+		assert_eq!(camera.screen_size(&sphere), f32::INFINITY);
+	}
+}
@@ -1,6 +1,8 @@
 // File: camera/projection.rs
 
-#[derive(Copy, Clone, Debug)]
+use bevy::prelude::Component;
+
+#[derive(Copy, Clone, Debug, Component)]
 pub struct Projection {
 	pub aspect: f32,
 	pub fovy: f32,
@@ -10,7 +12,9 @@ pub struct Projection {
 }
 impl Projection {
 	pub fn new(width: u32, height: u32, fovy: f32, znear: f32, zfar: f32) -> Self {
-		let aspect = Self::aspect(width, height);
+		Self::with_aspect(Self::aspect(width, height), fovy, znear, zfar)
+	}
+	pub fn with_aspect(aspect: f32, fovy: f32, znear: f32, zfar: f32) -> Self {
 		Projection {
 			aspect,
 			fovy,
@@ -0,0 +1,24 @@
+// File: culling.rs
+
+use bevy::ecs::component::Component;
+
+/// Whether a `ShouldDraw` entity is inside the primary camera's frustum. Added
+/// and updated by the renderer plugin, entities without it are drawn.
+#[derive(Component, Copy, Clone, Debug)]
+pub struct FrustumVisibility {
+	pub visible: bool,
+}
+impl Default for FrustumVisibility {
+	fn default() -> Self {
+		Self { visible: true }
+	}
+}
+
+/// Meshes skipped and drawn in the last frame, for diagnostics.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CullingStats {
+	/// Entities outside the frustum.
+	pub culled: usize,
+	/// Draws sent to the renderer.
+	pub drawn: usize,
+}
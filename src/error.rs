@@ -0,0 +1,80 @@
+// File: error.rs
+
+use crate::adapter::AdapterSelectionError;
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum RendererError {
+	/// Surfaces and render targets need a width and height of at least one pixel.
+	ZeroSized {
+		width: u32,
+		height: u32,
+	},
+	/// wgpu only supports 1 or 4 samples per pixel.
+	UnsupportedSampleCount {
+		samples: u32,
+	},
+	Adapter(AdapterSelectionError),
+	RequestDevice(wgpu::RequestDeviceError),
+	/// The selected adapter can not present to the window surface.
+	UnsupportedSurface {
+		adapter: String,
+	},
+	Texture(anyhow::Error),
+	Surface(wgpu::SurfaceError),
+	Readback(anyhow::Error),
+}
+impl fmt::Display for RendererError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			RendererError::ZeroSized { width, height } => {
+				write!(f, "can not render to a {}x{} target", width, height)
+			}
+			RendererError::UnsupportedSampleCount { samples } => {
+				write!(f, "{} msaa samples are not supported, use 1 or 4", samples)
+			}
+			RendererError::Adapter(err) => write!(f, "{}", err),
+			RendererError::RequestDevice(err) => write!(f, "{}", err),
+			RendererError::UnsupportedSurface { adapter } => {
+				write!(f, "adapter {} does not support the window surface", adapter)
+			}
+			RendererError::Texture(err) => write!(f, "failed to create texture: {}", err),
+			RendererError::Surface(err) => write!(f, "failed to acquire frame: {}", err),
+			RendererError::Readback(err) => write!(f, "failed to read back frame: {}", err),
+		}
+	}
+}
+impl std::error::Error for RendererError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			RendererError::Adapter(err) => Some(err),
+			RendererError::RequestDevice(err) => Some(err),
+			RendererError::Surface(err) => Some(err),
+			RendererError::Texture(err) | RendererError::Readback(err) => Some(err.as_ref()),
+			RendererError::ZeroSized { .. }
+			| RendererError::UnsupportedSampleCount { .. }
+			| RendererError::UnsupportedSurface { .. } => None,
+		}
+	}
+}
+impl From<AdapterSelectionError> for RendererError {
+	fn from(err: AdapterSelectionError) -> Self {
+		RendererError::Adapter(err)
+	}
+}
+impl From<wgpu::RequestDeviceError> for RendererError {
+	fn from(err: wgpu::RequestDeviceError) -> Self {
+		RendererError::RequestDevice(err)
+	}
+}
+impl From<wgpu::SurfaceError> for RendererError {
+	fn from(err: wgpu::SurfaceError) -> Self {
+		RendererError::Surface(err)
+	}
+}
+
+/// Sent by the `render` system when the surface ran out of memory. The frame is
+/// dropped; apps can react by freeing resources or exiting.
+#[derive(Clone, Debug)]
+pub struct SurfaceOutOfMemory;
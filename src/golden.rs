@@ -0,0 +1,335 @@
+// File: golden.rs
+
+//! Renders small scenes offscreen and compares them against reference PNGs in
+//! `tests/golden`. Set `GOLDEN_BLESS=1` to write the references from the
+//! current output, a missing reference fails the comparison otherwise.
+//!
+//! The rendering tests need an adapter and are ignored by default, run them
+//! with `cargo test -- --ignored`. The references were rendered by llvmpipe
+//! through the GL backend (`WGPU_BACKEND=gl`), edges may differ slightly on
+//! other adapters.
+
+use crate::camera::{Camera, CameraBindGroup, Projection};
+use crate::material::Material;
+use crate::mesh::MeshGenerator;
+use crate::render_state::{MeshDraw, RenderState};
+use crate::settings::RendererSettings;
+
+use anyhow::*;
+
+use bevy::transform::components::{GlobalTransform, Transform};
+
+use std::path::{Path, PathBuf};
+
+pub struct GoldenScene {
+	pub width: u32,
+	pub height: u32,
+	pub eye: cgmath::Point3<f32>,
+	pub target: cgmath::Point3<f32>,
+	pub fovy: f32,
+	pub meshes: Vec<(MeshGenerator, Transform)>,
+	/// Base color texture of the renderer's default material.
+	pub texture: Option<image::DynamicImage>,
+	pub settings: RendererSettings,
+}
+impl Default for GoldenScene {
+	fn default() -> Self {
+		Self {
+			width: 128,
+			height: 128,
+			eye: cgmath::Point3::new(0.0, 0.0, 2.0),
+			target: cgmath::Point3::new(0.0, 0.0, 0.0),
+			fovy: 45.0,
+			meshes: vec![],
+			texture: None,
+			settings: RendererSettings::default(),
+		}
+	}
+}
+impl GoldenScene {
+	pub fn render(&mut self) -> Result<image::RgbaImage> {
+		let mut renderer = pollster::block_on(RenderState::new_offscreen(
+			self.width,
+			self.height,
+			wgpu::TextureFormat::Rgba8UnormSrgb,
+			&self.settings,
+		))?;
+
+		if let Some(img) = &self.texture {
+			renderer.set_default_material(&Material {
+				base_color_texture: Some(img.clone()),
+				..Default::default()
+			})?;
+		}
+
+		let camera = {
+			let projection = Projection::new(self.width, self.height, self.fovy, 0.1, 1000.0);
+			Camera::new(self.eye, self.target, &projection)
+		};
+		let camera_bind_group =
+			CameraBindGroup::new(&renderer.device, Some("golden_camera"), &camera);
+
+		let meshes: Vec<_> = self
+			.meshes
+			.iter_mut()
+			.map(|(generator, transform)| {
+				(
+					generator.build(&renderer.device, Some("golden_mesh")),
+					GlobalTransform::from(*transform),
+				)
+			})
+			.collect();
+
+		renderer.render(
+			&camera_bind_group,
+			meshes.iter().map(|(mesh, transform)| MeshDraw {
+				mesh,
+				transform,
+				instances: None,
+				material: None,
+			}),
+		)?;
+		Ok(renderer.capture_frame()?)
+	}
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Tolerance {
+	/// Largest difference of any channel for a pixel to still match.
+	pub per_channel: u8,
+	/// How many pixels may exceed `per_channel` before the comparison fails.
+	pub max_mismatched_pixels: usize,
+}
+impl Default for Tolerance {
+	fn default() -> Self {
+		Self {
+			per_channel: 2,
+			max_mismatched_pixels: 0,
+		}
+	}
+}
+
+pub struct ImageDiff {
+	pub mismatched_pixels: usize,
+	pub max_channel_difference: u8,
+	/// Mismatched pixels in red, everything else as a faded copy of the reference.
+	pub diff_image: image::RgbaImage,
+}
+impl ImageDiff {
+	pub fn passes(&self, tolerance: Tolerance) -> bool {
+		self.mismatched_pixels <= tolerance.max_mismatched_pixels
+	}
+}
+
+pub fn compare_images(
+	actual: &image::RgbaImage,
+	expected: &image::RgbaImage,
+	tolerance: Tolerance,
+) -> Result<ImageDiff> {
+	if actual.dimensions() != expected.dimensions() {
+		bail!(
+			"image is {:?} but the reference is {:?}",
+			actual.dimensions(),
+			expected.dimensions()
+		);
+	}
+
+	let (width, height) = expected.dimensions();
+	let mut diff_image = image::RgbaImage::new(width, height);
+	let mut mismatched_pixels = 0;
+	let mut max_channel_difference = 0;
+
+	for ((a, e), d) in actual
+		.pixels()
+		.zip(expected.pixels())
+		.zip(diff_image.pixels_mut())
+	{
+		let difference =
+			a.0.iter()
+				.zip(e.0.iter())
+				.map(|(a, e)| (*a as i16 - *e as i16).unsigned_abs() as u8)
+				.max()
+				.unwrap_or(0);
+		max_channel_difference = max_channel_difference.max(difference);
+
+		*d = if difference > tolerance.per_channel {
+			mismatched_pixels += 1;
+			image::Rgba([255, 0, 0, 255])
+		} else {
+			let luma = (e.0[0] as u16 + e.0[1] as u16 + e.0[2] as u16) / 3;
+			let faded = (luma / 4) as u8;
+			image::Rgba([faded, faded, faded, 255])
+		};
+	}
+
+	Ok(ImageDiff {
+		mismatched_pixels,
+		max_channel_difference,
+		diff_image,
+	})
+}
+
+pub fn reference_path(name: &str) -> PathBuf {
+	Path::new(env!("CARGO_MANIFEST_DIR"))
+		.join("tests")
+		.join("golden")
+		.join(format!("{}.png", name))
+}
+pub fn output_dir() -> PathBuf {
+	Path::new(env!("CARGO_MANIFEST_DIR"))
+		.join("target")
+		.join("golden")
+}
+
+/// Compares `actual` against the stored reference named `name`. On failure the
+/// rendered image and a diff image are written to `target/golden`.
+pub fn check_golden(name: &str, actual: &image::RgbaImage, tolerance: Tolerance) -> Result<()> {
+	let reference = reference_path(name);
+	let bless = std::env::var("GOLDEN_BLESS").ok().as_deref() == Some("1");
+
+	if bless {
+		std::fs::create_dir_all(reference.parent().unwrap())?;
+		actual.save(&reference)?;
+		log::warn!("wrote golden reference {}", reference.display());
+		return Ok(());
+	}
+	if !reference.exists() {
+		let out = output_dir();
+		std::fs::create_dir_all(&out)?;
+		let actual_path = out.join(format!("{}.actual.png", name));
+		actual.save(&actual_path)?;
+		bail!(
+			"{} has no reference at {}, check {} and rerun with GOLDEN_BLESS=1 to accept it",
+			name,
+			reference.display(),
+			actual_path.display()
+		);
+	}
+
+	let expected = image::open(&reference)
+		.with_context(|| format!("failed to load {}", reference.display()))?
+		.to_rgba8();
+	let diff = compare_images(actual, &expected, tolerance)?;
+	if diff.passes(tolerance) {
+		return Ok(());
+	}
+
+	let out = output_dir();
+	std::fs::create_dir_all(&out)?;
+	let actual_path = out.join(format!("{}.actual.png", name));
+	let diff_path = out.join(format!("{}.diff.png", name));
+	actual.save(&actual_path)?;
+	diff.diff_image.save(&diff_path)?;
+
+	bail!(
+		"{} differs from its reference: {} pixels mismatched (max channel difference {}), see {} and {}",
+		name,
+		diff.mismatched_pixels,
+		diff.max_channel_difference,
+		actual_path.display(),
+		diff_path.display()
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn solid(width: u32, height: u32, color: [u8; 4]) -> image::RgbaImage {
+		image::RgbaImage::from_pixel(width, height, image::Rgba(color))
+	}
+
+	#[test]
+	fn identical_images_match() {
+		let image = solid(4, 4, [10, 20, 30, 255]);
+		let diff = compare_images(&image, &image, Tolerance::default()).unwrap();
+		assert_eq!(diff.mismatched_pixels, 0);
+		assert_eq!(diff.max_channel_difference, 0);
+	}
+
+	#[test]
+	fn differences_within_tolerance_match() {
+		let actual = solid(4, 4, [12, 20, 30, 255]);
+		let expected = solid(4, 4, [10, 20, 30, 255]);
+		let diff = compare_images(&actual, &expected, Tolerance::default()).unwrap();
+		assert!(diff.passes(Tolerance::default()));
+		assert_eq!(diff.max_channel_difference, 2);
+	}
+
+	#[test]
+	fn mismatched_pixels_are_counted_and_marked() {
+		let mut actual = solid(4, 4, [10, 20, 30, 255]);
+		actual.put_pixel(1, 2, image::Rgba([200, 20, 30, 255]));
+		let expected = solid(4, 4, [10, 20, 30, 255]);
+
+		let diff = compare_images(&actual, &expected, Tolerance::default()).unwrap();
+		assert_eq!(diff.mismatched_pixels, 1);
+		assert!(!diff.passes(Tolerance::default()));
+		assert_eq!(diff.diff_image.get_pixel(1, 2).0, [255, 0, 0, 255]);
+		assert!(diff.passes(Tolerance {
+			per_channel: 2,
+			max_mismatched_pixels: 1,
+		}));
+	}
+
+	#[test]
+	fn size_mismatch_is_an_error() {
+		let actual = solid(4, 4, [0, 0, 0, 255]);
+		let expected = solid(4, 5, [0, 0, 0, 255]);
+		assert!(compare_images(&actual, &expected, Tolerance::default()).is_err());
+	}
+
+	#[test]
+	#[ignore = "needs an adapter"]
+	fn uv_sphere_golden() {
+		let mut generator = MeshGenerator::default();
+		generator.uv_sphere(0.5, 32, 32);
+		let mut scene = GoldenScene {
+			meshes: vec![(generator, Transform::default())],
+			..Default::default()
+		};
+		let image = scene.render().unwrap();
+		check_golden("uv_sphere", &image, Tolerance::default()).unwrap();
+	}
+
+	#[test]
+	#[ignore = "needs an adapter"]
+	fn uv_sphere_checkerboard_golden() {
+		let checkerboard = image::RgbaImage::from_fn(8, 8, |x, y| {
+			if (x + y) % 2 == 0 {
+				image::Rgba([255, 255, 255, 255])
+			} else {
+				image::Rgba([0, 0, 0, 255])
+			}
+		});
+		let mut generator = MeshGenerator::default();
+		generator.uv_sphere(0.5, 32, 32);
+		let mut scene = GoldenScene {
+			eye: cgmath::Point3::new(1.5, 1.0, 1.5),
+			meshes: vec![(generator, Transform::default())],
+			texture: Some(image::DynamicImage::ImageRgba8(checkerboard)),
+			..Default::default()
+		};
+		let image = scene.render().unwrap();
+		check_golden("uv_sphere_checkerboard", &image, Tolerance::default()).unwrap();
+	}
+
+	#[test]
+	#[ignore = "needs an adapter"]
+	fn transformed_spheres_golden() {
+		let mut generator = MeshGenerator::default();
+		generator.uv_sphere(0.25, 32, 32);
+		let mut scene = GoldenScene {
+			meshes: vec![
+				(generator.clone(), Transform::from_xyz(-0.4, 0.0, 0.0)),
+				(
+					generator,
+					Transform::from_xyz(0.4, 0.0, 0.0).with_scale(bevy::math::Vec3::splat(0.5)),
+				),
+			],
+			..Default::default()
+		};
+		let image = scene.render().unwrap();
+		check_golden("transformed_spheres", &image, Tolerance::default()).unwrap();
+	}
+}
@@ -0,0 +1,101 @@
+// File: instance.rs
+
+use bevy::ecs::component::Component;
+use bevy::math::Mat3;
+use bevy::transform::components::{GlobalTransform, Transform};
+
+/// One copy of an instanced mesh, relative to the entity's `GlobalTransform`.
+#[derive(Copy, Clone, Debug)]
+pub struct Instance {
+	pub transform: Transform,
+	/// Multiplied with the sampled texture color.
+	pub color: [f32; 4],
+}
+impl Default for Instance {
+	fn default() -> Self {
+		Self {
+			transform: Transform::default(),
+			color: [1.0, 1.0, 1.0, 1.0],
+		}
+	}
+}
+impl Instance {
+	pub fn to_raw(&self) -> InstanceRaw {
+		let model_matrix = GlobalTransform::from(self.transform).compute_matrix();
+		let normal_matrix = Mat3::from_mat4(model_matrix).inverse().transpose();
+		InstanceRaw {
+			model_matrix: model_matrix.to_cols_array_2d(),
+			normal_matrix: normal_matrix.to_cols_array_2d(),
+			color: self.color,
+		}
+	}
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct InstanceRaw {
+	model_matrix: [[f32; 4]; 4],
+	normal_matrix: [[f32; 3]; 3],
+	color: [f32; 4],
+}
+impl InstanceRaw {
+	// Locations below 8 are reserved for per vertex attributes.
+	pub const ATTRIBS: [wgpu::VertexAttribute; 8] = wgpu::vertex_attr_array![
+		8 => Float32x4, 9 => Float32x4, 10 => Float32x4, 11 => Float32x4,
+		12 => Float32x3, 13 => Float32x3, 14 => Float32x3,
+		15 => Float32x4
+	];
+
+	pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+		use std::mem;
+
+		wgpu::VertexBufferLayout {
+			array_stride: mem::size_of::<Self>() as wgpu::BufferAddress,
+			step_mode: wgpu::VertexStepMode::Instance,
+			attributes: &Self::ATTRIBS,
+		}
+	}
+}
+
+/// Draws the entity's `Mesh` once per instance with a single draw call.
+#[derive(Component, Default)]
+pub struct InstancedMesh {
+	pub instances: Vec<Instance>,
+}
+
+/// Gpu copy of an `InstancedMesh`, kept up to date by the renderer plugin.
+#[derive(Component)]
+pub struct InstanceBuffer {
+	pub buffer: wgpu::Buffer,
+	pub capacity: usize,
+	pub count: u32,
+}
+impl InstanceBuffer {
+	pub fn new(device: &wgpu::Device, capacity: usize) -> Self {
+		let capacity = capacity.max(1);
+		let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("instance buffer"),
+			size: (std::mem::size_of::<InstanceRaw>() * capacity) as wgpu::BufferAddress,
+			usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+		Self {
+			buffer,
+			capacity,
+			count: 0,
+		}
+	}
+	/// Uploads the instances, reallocating when there are more than fit.
+	pub fn write(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, instances: &[Instance]) {
+		self.count = 0;
+		if instances.is_empty() {
+			return;
+		}
+		if instances.len() > self.capacity {
+			*self = Self::new(device, instances.len().next_power_of_two());
+		}
+		let raw: Vec<InstanceRaw> = instances.iter().map(Instance::to_raw).collect();
+		queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&raw));
+		self.count = raw.len() as u32;
+	}
+}
@@ -0,0 +1,25 @@
+// File: lib.rs
+
+pub mod adapter;
+pub mod camera;
+pub mod culling;
+pub mod error;
+#[cfg(any(test, feature = "golden"))]
+pub mod golden;
+pub mod instance;
+pub mod loaders;
+pub mod material;
+pub mod mesh;
+pub mod model;
+pub mod plugin;
+pub mod render_state;
+pub mod screenshot;
+pub mod settings;
+pub mod texture;
+pub mod vertex;
+pub mod vertex_layout;
+
+pub use error::RendererError;
+pub use plugin::WgpuRendererPlugin;
+pub use render_state::RenderState;
+pub use settings::RendererSettings;
@@ -0,0 +1,718 @@
+// File: loaders/gltf.rs
+
+//! glTF 2.0 loading. `.gltf` files with embedded, base64 data uri or external
+//! buffers and binary `.glb` files are supported.
+
+use crate::camera::{Camera, Projection};
+use crate::material::Material;
+use crate::mesh::{MeshData, MeshGenerator, ShouldDraw};
+use crate::texture::SamplerSettings;
+use crate::vertex::Vertex;
+
+use bevy::asset::{Assets, Handle};
+use bevy::ecs::entity::Entity;
+use bevy::ecs::system::Commands;
+use bevy::math::{Mat4, Vec3};
+use bevy::prelude::BuildChildren;
+use bevy::transform::components::{GlobalTransform, Transform};
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum GltfError {
+	/// Reading or decoding the document, its buffers or its images failed.
+	Import { path: PathBuf, source: gltf::Error },
+	/// A triangle primitive without a POSITION attribute.
+	MissingPositions { path: PathBuf, mesh: String },
+	/// A vertex attribute with a different number of elements than POSITION.
+	AttributeCount {
+		path: PathBuf,
+		mesh: String,
+		attribute: &'static str,
+		expected: usize,
+		found: usize,
+	},
+}
+impl fmt::Display for GltfError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			GltfError::Import { path, source } => {
+				write!(f, "failed to load {}: {}", path.display(), source)
+			}
+			GltfError::MissingPositions { path, mesh } => write!(
+				f,
+				"{}: mesh {} has a primitive without positions",
+				path.display(),
+				mesh
+			),
+			GltfError::AttributeCount {
+				path,
+				mesh,
+				attribute,
+				expected,
+				found,
+			} => write!(
+				f,
+				"{}: mesh {} has {} {} values for {} positions",
+				path.display(),
+				mesh,
+				found,
+				attribute,
+				expected
+			),
+		}
+	}
+}
+impl std::error::Error for GltfError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			GltfError::Import { source, .. } => Some(source),
+			GltfError::MissingPositions { .. } | GltfError::AttributeCount { .. } => None,
+		}
+	}
+}
+
+pub struct GltfPrimitive {
+	/// Flat normals and tangents are computed when the primitive has none.
+	pub mesh: MeshGenerator,
+	/// Index into `GltfModel::materials`.
+	pub material: Option<usize>,
+}
+
+pub struct GltfModel {
+	pub document: gltf::Document,
+	/// The triangle primitives of each glTF mesh, by mesh index.
+	pub meshes: Vec<Vec<GltfPrimitive>>,
+	/// By glTF material index.
+	pub materials: Vec<Material>,
+}
+
+/// Entities created by `GltfModel::spawn`.
+pub struct GltfSpawned {
+	/// Parent of the scene's root nodes.
+	pub root: Entity,
+	pub cameras: Vec<Entity>,
+}
+
+pub fn load_gltf(path: impl AsRef<Path>) -> Result<GltfModel, GltfError> {
+	let path = path.as_ref();
+	let (document, buffers, images) = gltf::import(path).map_err(|source| GltfError::Import {
+		path: path.to_path_buf(),
+		source,
+	})?;
+
+	let images: Vec<image::DynamicImage> = images.into_iter().map(convert_image).collect();
+	let materials = document
+		.materials()
+		.map(|material| convert_material(&material, &images))
+		.collect();
+
+	let mut meshes = Vec::with_capacity(document.meshes().len());
+	for mesh in document.meshes() {
+		let name = mesh
+			.name()
+			.map(String::from)
+			.unwrap_or_else(|| mesh.index().to_string());
+		let mut primitives = vec![];
+		for primitive in mesh.primitives() {
+			if primitive.mode() != gltf::mesh::Mode::Triangles {
+				log::warn!(
+					"{}: skipping {:?} primitive of mesh {}, only triangles are supported",
+					path.display(),
+					primitive.mode(),
+					name
+				);
+				continue;
+			}
+			let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+			let positions: Vec<[f32; 3]> = reader
+				.read_positions()
+				.ok_or_else(|| GltfError::MissingPositions {
+					path: path.to_path_buf(),
+					mesh: name.clone(),
+				})?
+				.collect();
+			let normals: Option<Vec<[f32; 3]>> = reader.read_normals().map(Iterator::collect);
+			let tangents: Option<Vec<[f32; 4]>> = reader.read_tangents().map(Iterator::collect);
+			let uvs: Vec<[f32; 2]> = match reader.read_tex_coords(0) {
+				Some(uvs) => uvs.into_f32().collect(),
+				None => vec![[0.0; 2]; positions.len()],
+			};
+			let colors: Vec<[f32; 3]> = match reader.read_colors(0) {
+				Some(colors) => colors.into_rgb_f32().collect(),
+				None => vec![[1.0; 3]; positions.len()],
+			};
+			let counts = [
+				("NORMAL", normals.as_ref().map(Vec::len)),
+				("TANGENT", tangents.as_ref().map(Vec::len)),
+				("TEXCOORD_0", Some(uvs.len())),
+				("COLOR_0", Some(colors.len())),
+			];
+			for (attribute, found) in counts {
+				match found {
+					Some(found) if found != positions.len() => {
+						return Err(GltfError::AttributeCount {
+							path: path.to_path_buf(),
+							mesh: name,
+							attribute,
+							expected: positions.len(),
+							found,
+						});
+					}
+					_ => {}
+				}
+			}
+			let indices: Vec<u32> = match reader.read_indices() {
+				Some(indices) => indices.into_u32().collect(),
+				None => (0..positions.len() as u32).collect(),
+			};
+
+			let vertices = positions
+				.iter()
+				.zip(colors.iter())
+				.zip(uvs.iter())
+				.enumerate()
+				.map(|(i, ((position, color), uv))| Vertex {
+					position: *position,
+					color: *color,
+					uv: *uv,
+					normal: normals.as_ref().map_or([0.0; 3], |normals| normals[i]),
+					tangent: tangents.as_ref().map_or([0.0; 4], |tangents| tangents[i]),
+				})
+				.collect();
+			let mut mesh = MeshGenerator { vertices, indices };
+			// The spec asks for flat normals when they are missing, and tangents
+			// computed with MikkTSpace, which these only approximate.
+			if normals.is_none() {
+				mesh.compute_flat_normals();
+			}
+			if normals.is_none() || tangents.is_none() {
+				mesh.compute_angle_weighted_tangents();
+			}
+			primitives.push(GltfPrimitive {
+				mesh,
+				material: primitive.material().index(),
+			});
+		}
+		meshes.push(primitives);
+	}
+
+	Ok(GltfModel {
+		document,
+		meshes,
+		materials,
+	})
+}
+
+impl GltfModel {
+	/// Spawns the nodes of the default scene, or the first scene when there is
+	/// none. Every node becomes an entity with a `Transform` below its parent.
+	/// Primitives become `Handle<MeshData>` entities, cameras get a `Camera` and
+	/// a `Projection` placed by the node's world transform.
+	pub fn spawn(
+		self,
+		commands: &mut Commands,
+		mesh_assets: &mut Assets<MeshData>,
+		material_assets: &mut Assets<Material>,
+	) -> GltfSpawned {
+		let GltfModel {
+			document,
+			meshes,
+			materials,
+		} = self;
+		let materials: Vec<Handle<Material>> = materials
+			.into_iter()
+			.map(|material| material_assets.add(material))
+			.collect();
+		let meshes: Vec<Vec<PrimitiveHandles>> = meshes
+			.into_iter()
+			.map(|primitives| {
+				primitives
+					.into_iter()
+					.map(|primitive| {
+						(
+							mesh_assets.add(MeshData::from(primitive.mesh)),
+							primitive.material.map(|index| materials[index].clone()),
+						)
+					})
+					.collect()
+			})
+			.collect();
+
+		let root = commands
+			.spawn()
+			.insert(Transform::default())
+			.insert(GlobalTransform::default())
+			.id();
+		let mut spawner = NodeSpawner {
+			commands,
+			meshes: &meshes,
+			cameras: vec![],
+		};
+
+		let scene = document
+			.default_scene()
+			.or_else(|| document.scenes().next());
+		if let Some(scene) = scene {
+			for node in scene.nodes() {
+				spawner.spawn_node(&node, root, Mat4::IDENTITY);
+			}
+		}
+
+		GltfSpawned {
+			root,
+			cameras: spawner.cameras,
+		}
+	}
+}
+
+/// The assets of one primitive.
+type PrimitiveHandles = (Handle<MeshData>, Option<Handle<Material>>);
+
+struct NodeSpawner<'w, 's, 'a> {
+	commands: &'a mut Commands<'w, 's>,
+	meshes: &'a [Vec<PrimitiveHandles>],
+	cameras: Vec<Entity>,
+}
+impl<'w, 's, 'a> NodeSpawner<'w, 's, 'a> {
+	fn spawn_node(&mut self, node: &gltf::Node, parent: Entity, parent_matrix: Mat4) {
+		let matrix = Mat4::from_cols_array_2d(&node.transform().matrix());
+		let world_matrix = parent_matrix * matrix;
+
+		let entity = self
+			.commands
+			.spawn()
+			.insert(Transform::from_matrix(matrix))
+			.insert(GlobalTransform::from_matrix(world_matrix))
+			.id();
+		self.commands.entity(parent).push_children(&[entity]);
+
+		if let Some(mesh) = node.mesh() {
+			for (mesh_data, material) in &self.meshes[mesh.index()] {
+				let mut primitive = self.commands.spawn();
+				primitive
+					.insert(mesh_data.clone())
+					.insert(Transform::default())
+					.insert(GlobalTransform::from_matrix(world_matrix))
+					.insert(ShouldDraw);
+				if let Some(material) = material {
+					primitive.insert(material.clone());
+				}
+				let primitive = primitive.id();
+				self.commands.entity(entity).push_children(&[primitive]);
+			}
+		}
+
+		if let Some(camera) = node.camera() {
+			match camera.projection() {
+				gltf::camera::Projection::Perspective(perspective) => {
+					let projection = Projection::with_aspect(
+						perspective.aspect_ratio().unwrap_or(1.0),
+						perspective.yfov().to_degrees(),
+						perspective.znear(),
+						perspective.zfar().unwrap_or(1000.0),
+					);
+					// glTF cameras look down their local -Z axis
+					let eye = world_matrix.transform_point3(Vec3::ZERO);
+					let target = eye + world_matrix.transform_vector3(-Vec3::Z);
+					let up = world_matrix.transform_vector3(Vec3::Y);
+					let mut camera = Camera::new(
+						cgmath::Point3::new(eye.x, eye.y, eye.z),
+						cgmath::Point3::new(target.x, target.y, target.z),
+						&projection,
+					);
+					camera.set_up(cgmath::Vector3::new(up.x, up.y, up.z));
+					self.commands
+						.entity(entity)
+						.insert(camera)
+						.insert(projection);
+					self.cameras.push(entity);
+				}
+				gltf::camera::Projection::Orthographic(_) => {
+					log::warn!(
+						"skipping orthographic camera {}, only perspective cameras are supported",
+						camera.name().unwrap_or("")
+					);
+				}
+			}
+		}
+
+		for child in node.children() {
+			self.spawn_node(&child, entity, world_matrix);
+		}
+	}
+}
+
+fn convert_image(data: gltf::image::Data) -> image::DynamicImage {
+	use gltf::image::Format;
+
+	let (width, height) = (data.width, data.height);
+	let pixels = data.pixels;
+	let wide = || -> Vec<u16> {
+		pixels
+			.chunks_exact(2)
+			.map(|bytes| u16::from_ne_bytes([bytes[0], bytes[1]]))
+			.collect()
+	};
+	let swizzled = |channels: usize| -> Vec<u8> {
+		let mut swizzled = pixels.clone();
+		for pixel in swizzled.chunks_exact_mut(channels) {
+			pixel.swap(0, 2);
+		}
+		swizzled
+	};
+
+	// The buffers have the size the format implies, so from_raw always succeeds.
+	match data.format {
+		Format::R8 => image::GrayImage::from_raw(width, height, pixels.clone())
+			.map(image::DynamicImage::ImageLuma8),
+		Format::R8G8 => image::GrayAlphaImage::from_raw(width, height, pixels.clone())
+			.map(image::DynamicImage::ImageLumaA8),
+		Format::R8G8B8 => image::RgbImage::from_raw(width, height, pixels.clone())
+			.map(image::DynamicImage::ImageRgb8),
+		Format::R8G8B8A8 => image::RgbaImage::from_raw(width, height, pixels.clone())
+			.map(image::DynamicImage::ImageRgba8),
+		Format::B8G8R8 => image::RgbImage::from_raw(width, height, swizzled(3))
+			.map(image::DynamicImage::ImageRgb8),
+		Format::B8G8R8A8 => image::RgbaImage::from_raw(width, height, swizzled(4))
+			.map(image::DynamicImage::ImageRgba8),
+		Format::R16 => image::ImageBuffer::from_raw(width, height, wide())
+			.map(image::DynamicImage::ImageLuma16),
+		Format::R16G16 => image::ImageBuffer::from_raw(width, height, wide())
+			.map(image::DynamicImage::ImageLumaA16),
+		Format::R16G16B16 => {
+			image::ImageBuffer::from_raw(width, height, wide()).map(image::DynamicImage::ImageRgb16)
+		}
+		Format::R16G16B16A16 => image::ImageBuffer::from_raw(width, height, wide())
+			.map(image::DynamicImage::ImageRgba16),
+	}
+	.expect("glTF image data matches its format")
+}
+
+fn convert_material(material: &gltf::Material, images: &[image::DynamicImage]) -> Material {
+	let pbr = material.pbr_metallic_roughness();
+	let image = |texture: gltf::Texture| images[texture.source().index()].clone();
+
+	let base_color_texture = pbr.base_color_texture().map(|info| info.texture());
+	// A material has one sampler, the base color texture's is the most visible.
+	let sampler = base_color_texture
+		.as_ref()
+		.map(|texture| convert_sampler(&texture.sampler()))
+		.unwrap_or_else(|| SamplerSettings {
+			address_mode: wgpu::AddressMode::Repeat,
+			..Default::default()
+		});
+
+	Material {
+		base_color: pbr.base_color_factor(),
+		base_color_texture: base_color_texture.map(image),
+		normal_texture: material
+			.normal_texture()
+			.map(|normal| image(normal.texture())),
+		roughness_texture: pbr
+			.metallic_roughness_texture()
+			.map(|info| image(info.texture())),
+		roughness: pbr.roughness_factor(),
+		sampler,
+	}
+}
+
+fn convert_sampler(sampler: &gltf::texture::Sampler) -> SamplerSettings {
+	use gltf::texture::{MagFilter, MinFilter, WrappingMode};
+
+	let address_mode = match sampler.wrap_s() {
+		WrappingMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+		WrappingMode::MirroredRepeat => wgpu::AddressMode::MirrorRepeat,
+		WrappingMode::Repeat => wgpu::AddressMode::Repeat,
+	};
+	let mag_filter = match sampler.mag_filter() {
+		Some(MagFilter::Nearest) => wgpu::FilterMode::Nearest,
+		Some(MagFilter::Linear) | None => wgpu::FilterMode::Linear,
+	};
+	let (min_filter, mipmap_filter) = match sampler.min_filter() {
+		Some(MinFilter::Nearest) => (wgpu::FilterMode::Nearest, wgpu::FilterMode::Nearest),
+		Some(MinFilter::NearestMipmapNearest) => {
+			(wgpu::FilterMode::Nearest, wgpu::FilterMode::Nearest)
+		}
+		Some(MinFilter::NearestMipmapLinear) => {
+			(wgpu::FilterMode::Nearest, wgpu::FilterMode::Linear)
+		}
+		Some(MinFilter::LinearMipmapNearest) => {
+			(wgpu::FilterMode::Linear, wgpu::FilterMode::Nearest)
+		}
+		Some(MinFilter::LinearMipmapLinear) => (wgpu::FilterMode::Linear, wgpu::FilterMode::Linear),
+		Some(MinFilter::Linear) | None => (wgpu::FilterMode::Linear, wgpu::FilterMode::Nearest),
+	};
+
+	SamplerSettings {
+		address_mode,
+		mag_filter,
+		min_filter,
+		mipmap_filter,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use bevy::app::App;
+	use bevy::asset::AddAsset;
+	use bevy::ecs::system::{IntoSystem, ResMut};
+	use bevy::prelude::{Children, Parent};
+
+	/// A triangle's positions followed by its u16 indices, padded to 4 bytes.
+	fn triangle_buffer() -> Vec<u8> {
+		let mut bytes = vec![];
+		for float in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+			bytes.extend_from_slice(&float.to_le_bytes());
+		}
+		for index in [0u16, 1, 2, 0] {
+			bytes.extend_from_slice(&index.to_le_bytes());
+		}
+		bytes
+	}
+
+	/// A parent node with the triangle and a camera as children. `buffer` is the
+	/// json of the only buffer.
+	fn document(buffer: &str) -> String {
+		format!(
+			r#"{{
+				"asset": {{ "version": "2.0" }},
+				"scene": 0,
+				"scenes": [{{ "nodes": [0] }}],
+				"nodes": [
+					{{ "name": "parent", "translation": [1, 0, 0], "children": [1, 2] }},
+					{{ "name": "triangle", "mesh": 0, "scale": [2, 2, 2] }},
+					{{ "name": "camera", "camera": 0, "translation": [0, 0, 5] }}
+				],
+				"cameras": [{{
+					"type": "perspective",
+					"perspective": {{ "yfov": 0.5, "znear": 0.1, "zfar": 50, "aspectRatio": 1.5 }}
+				}}],
+				"meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0 }}, "indices": 1 }}] }}],
+				"buffers": [{}],
+				"bufferViews": [
+					{{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+					{{ "buffer": 0, "byteOffset": 36, "byteLength": 6 }}
+				],
+				"accessors": [
+					{{
+						"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+						"min": [0, 0, 0], "max": [1, 1, 0]
+					}},
+					{{ "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }}
+				]
+			}}"#,
+			buffer
+		)
+	}
+
+	fn base64(bytes: &[u8]) -> String {
+		const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+		let mut encoded = String::new();
+		for chunk in bytes.chunks(3) {
+			let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, byte)| {
+				bits | (*byte as u32) << (16 - 8 * i)
+			});
+			for i in 0..4 {
+				if i <= chunk.len() {
+					encoded.push(ALPHABET[(bits >> (18 - 6 * i) & 63) as usize] as char);
+				} else {
+					encoded.push('=');
+				}
+			}
+		}
+		encoded
+	}
+
+	fn glb(json: &str, binary: &[u8]) -> Vec<u8> {
+		let mut json = json.as_bytes().to_vec();
+		let padded = json.len() + (4 - json.len() % 4) % 4;
+		json.resize(padded, b' ');
+		let length = 12 + 8 + json.len() + 8 + binary.len();
+
+		let mut bytes = vec![];
+		bytes.extend_from_slice(b"glTF");
+		bytes.extend_from_slice(&2u32.to_le_bytes());
+		bytes.extend_from_slice(&(length as u32).to_le_bytes());
+		bytes.extend_from_slice(&(json.len() as u32).to_le_bytes());
+		bytes.extend_from_slice(b"JSON");
+		bytes.extend_from_slice(&json);
+		bytes.extend_from_slice(&(binary.len() as u32).to_le_bytes());
+		bytes.extend_from_slice(b"BIN\0");
+		bytes.extend_from_slice(binary);
+		bytes
+	}
+
+	/// Writes `files` to a directory of their own and loads the first one.
+	fn load(test: &str, files: &[(&str, Vec<u8>)]) -> GltfModel {
+		let directory = std::env::temp_dir().join(format!("wgpu_dev_gltf_{}", test));
+		std::fs::create_dir_all(&directory).unwrap();
+		for (name, contents) in files {
+			std::fs::write(directory.join(name), contents).unwrap();
+		}
+		load_gltf(directory.join(files[0].0)).unwrap_or_else(|err| panic!("{}", err))
+	}
+
+	fn assert_triangle(model: &GltfModel) {
+		assert_eq!(model.meshes.len(), 1);
+		assert_eq!(model.meshes[0].len(), 1);
+		let mesh = &model.meshes[0][0].mesh;
+		let positions: Vec<_> = mesh.vertices.iter().map(|vertex| vertex.position).collect();
+		assert_eq!(
+			positions,
+			[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]
+		);
+		assert_eq!(mesh.indices, [0, 1, 2]);
+		// missing normals are computed flat
+		assert!(mesh
+			.vertices
+			.iter()
+			.all(|vertex| vertex.normal == [0.0, 0.0, 1.0]));
+	}
+
+	#[test]
+	fn glb_with_embedded_buffer() {
+		let binary = triangle_buffer();
+		let json = document(&format!(r#"{{ "byteLength": {} }}"#, binary.len()));
+		let model = load("glb", &[("model.glb", glb(&json, &binary))]);
+		assert_triangle(&model);
+	}
+
+	#[test]
+	fn gltf_with_data_uri_buffer() {
+		let binary = triangle_buffer();
+		let json = document(&format!(
+			r#"{{ "byteLength": {}, "uri": "data:application/octet-stream;base64,{}" }}"#,
+			binary.len(),
+			base64(&binary)
+		));
+		let model = load("data_uri", &[("model.gltf", json.into_bytes())]);
+		assert_triangle(&model);
+	}
+
+	#[test]
+	fn gltf_with_external_buffer() {
+		let binary = triangle_buffer();
+		let json = document(&format!(
+			r#"{{ "byteLength": {}, "uri": "triangle.bin" }}"#,
+			binary.len()
+		));
+		let model = load(
+			"external",
+			&[("model.gltf", json.into_bytes()), ("triangle.bin", binary)],
+		);
+		assert_triangle(&model);
+	}
+
+	#[test]
+	fn missing_external_buffer_is_an_error() {
+		let json = document(r#"{ "byteLength": 44, "uri": "missing.bin" }"#);
+		let directory = std::env::temp_dir().join("wgpu_dev_gltf_missing_buffer");
+		std::fs::create_dir_all(&directory).unwrap();
+		let path = directory.join("model.gltf");
+		std::fs::write(&path, json).unwrap();
+		assert!(matches!(load_gltf(&path), Err(GltfError::Import { .. })));
+	}
+
+	#[test]
+	fn short_normal_accessor_is_an_error() {
+		let binary = triangle_buffer();
+		let json = document(&format!(r#"{{ "byteLength": {} }}"#, binary.len()))
+			.replace(r#"{ "POSITION": 0 }"#, r#"{ "POSITION": 0, "NORMAL": 2 }"#)
+			.replace(
+				r#"{ "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }"#,
+				r#"{ "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" },
+				{ "bufferView": 0, "componentType": 5126, "count": 2, "type": "VEC3" }"#,
+			);
+		let directory = std::env::temp_dir().join("wgpu_dev_gltf_short_normals");
+		std::fs::create_dir_all(&directory).unwrap();
+		let path = directory.join("model.glb");
+		std::fs::write(&path, glb(&json, &binary)).unwrap();
+		assert!(matches!(
+			load_gltf(&path),
+			Err(GltfError::AttributeCount {
+				attribute: "NORMAL",
+				expected: 3,
+				found: 2,
+				..
+			})
+		));
+	}
+
+	#[test]
+	fn spawn_builds_the_node_hierarchy() {
+		let binary = triangle_buffer();
+		let json = document(&format!(r#"{{ "byteLength": {} }}"#, binary.len()));
+		let mut model = Some(load("spawn", &[("model.glb", glb(&json, &binary))]));
+
+		let mut app = App::new();
+		app.add_plugin(bevy::core::CorePlugin)
+			.add_plugin(bevy::asset::AssetPlugin)
+			.add_asset::<MeshData>()
+			.add_asset::<Material>()
+			.add_startup_system(
+				(move |mut commands: Commands,
+				       mut meshes: ResMut<Assets<MeshData>>,
+				       mut materials: ResMut<Assets<Material>>| {
+					let model = model.take().unwrap();
+					let spawned = model.spawn(&mut commands, &mut meshes, &mut materials);
+					commands.insert_resource(spawned);
+				})
+				.system(),
+			);
+		app.update();
+
+		let world = &mut app.world;
+		let spawned = world.remove_resource::<GltfSpawned>().unwrap();
+		let children = |entity: Entity| -> Vec<Entity> {
+			world
+				.get::<Children>(entity)
+				.unwrap()
+				.iter()
+				.copied()
+				.collect()
+		};
+		let parent = |entity: Entity| world.get::<Parent>(entity).unwrap().0;
+		let global = |entity: Entity| *world.get::<GlobalTransform>(entity).unwrap();
+
+		let root_children = children(spawned.root);
+		assert_eq!(root_children.len(), 1);
+		let node = root_children[0];
+		assert_eq!(parent(node), spawned.root);
+		assert_eq!(
+			world.get::<Transform>(node).unwrap().translation,
+			Vec3::new(1.0, 0.0, 0.0)
+		);
+
+		let node_children = children(node);
+		assert_eq!(node_children.len(), 2);
+		let (triangle, camera) = (node_children[0], node_children[1]);
+		assert_eq!(parent(triangle), node);
+		assert_eq!(parent(camera), node);
+		assert_eq!(spawned.cameras, [camera]);
+
+		// the primitive is its own entity below the mesh node
+		let primitives = children(triangle);
+		assert_eq!(primitives.len(), 1);
+		assert!(world.get::<Handle<MeshData>>(primitives[0]).is_some());
+		assert_eq!(parent(primitives[0]), triangle);
+		for entity in [triangle, primitives[0]] {
+			assert_eq!(global(entity).translation, Vec3::new(1.0, 0.0, 0.0));
+			assert_eq!(global(entity).scale, Vec3::splat(2.0));
+		}
+
+		assert_eq!(global(camera).translation, Vec3::new(1.0, 0.0, 5.0));
+		let view = world.get::<Camera>(camera).unwrap();
+		assert_eq!(view.eye, cgmath::Point3::new(1.0, 0.0, 5.0));
+		assert_eq!(view.target, cgmath::Point3::new(1.0, 0.0, 4.0));
+		assert_eq!(view.up, cgmath::Vector3::new(0.0, 1.0, 0.0));
+		let projection = world.get::<Projection>(camera).unwrap();
+		assert_eq!(projection.aspect, 1.5);
+		assert!((projection.fovy - 0.5f32.to_degrees()).abs() < 1e-4);
+		assert_eq!((projection.znear, projection.zfar), (0.1, 50.0));
+	}
+}
@@ -0,0 +1,6 @@
+// File: loaders/mod.rs
+
+pub mod gltf;
+pub mod obj;
+pub mod ply;
+pub mod stl;
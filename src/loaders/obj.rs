@@ -0,0 +1,536 @@
+// File: loaders/obj.rs
+
+//! Wavefront OBJ and MTL loading. Faces with more than three vertices are
+//! triangulated as fans, which is exact for the convex polygons exporters write.
+//! Vertices that share position, uv and normal indices are merged.
+
+use crate::material::Material;
+use crate::mesh::MeshGenerator;
+use crate::vertex::Vertex;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum ObjError {
+	Io {
+		path: PathBuf,
+		source: std::io::Error,
+	},
+	/// A statement that could not be understood, `line` starts at 1.
+	Parse {
+		path: PathBuf,
+		line: usize,
+		message: String,
+	},
+	Image {
+		path: PathBuf,
+		source: image::ImageError,
+	},
+}
+impl fmt::Display for ObjError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ObjError::Io { path, source } => {
+				write!(f, "failed to read {}: {}", path.display(), source)
+			}
+			ObjError::Parse {
+				path,
+				line,
+				message,
+			} => write!(f, "{}:{}: {}", path.display(), line, message),
+			ObjError::Image { path, source } => {
+				write!(f, "failed to load texture {}: {}", path.display(), source)
+			}
+		}
+	}
+}
+impl std::error::Error for ObjError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			ObjError::Io { source, .. } => Some(source),
+			ObjError::Image { source, .. } => Some(source),
+			ObjError::Parse { .. } => None,
+		}
+	}
+}
+
+/// The faces of one object, group or material change.
+pub struct ObjMesh {
+	pub name: String,
+	/// Smooth normals are computed when the file has none, tangents always.
+	pub mesh: MeshGenerator,
+	/// Key into `ObjModel::materials`.
+	pub material: Option<String>,
+}
+
+pub struct ObjModel {
+	pub meshes: Vec<ObjMesh>,
+	pub materials: HashMap<String, Material>,
+}
+
+pub fn load_obj(path: impl AsRef<Path>) -> Result<ObjModel, ObjError> {
+	let path = path.as_ref();
+	read_obj(&read(path)?, path)
+}
+
+/// Parses OBJ source that was read from `path`. The path names the source in
+/// errors and material libraries are loaded relative to it.
+pub fn read_obj(source: &str, path: impl AsRef<Path>) -> Result<ObjModel, ObjError> {
+	let path = path.as_ref();
+	let mut parser = ObjParser::new(path);
+
+	for (index, line) in source.lines().enumerate() {
+		parser.parse_line(line).map_err(|message| ObjError::Parse {
+			path: path.to_path_buf(),
+			line: index + 1,
+			message,
+		})?;
+	}
+	parser.start_mesh();
+
+	let mut materials = HashMap::new();
+	for library in &parser.material_libraries {
+		materials.extend(load_mtl(library)?);
+	}
+
+	Ok(ObjModel {
+		meshes: parser.meshes,
+		materials,
+	})
+}
+
+/// Loads every material of an MTL file. Texture paths are relative to the file.
+pub fn load_mtl(path: impl AsRef<Path>) -> Result<HashMap<String, Material>, ObjError> {
+	let path = path.as_ref();
+	let source = read(path)?;
+	let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+	let mut materials = HashMap::new();
+	let mut current: Option<(String, Material)> = None;
+	let mut has_roughness = false;
+
+	for (index, line) in source.lines().enumerate() {
+		let parse_error = |message: String| ObjError::Parse {
+			path: path.to_path_buf(),
+			line: index + 1,
+			message,
+		};
+		let mut words = statement(line);
+		let keyword = match words.next() {
+			Some(keyword) => keyword,
+			None => continue,
+		};
+		let args: Vec<&str> = words.collect();
+
+		if keyword == "newmtl" {
+			if let Some((name, material)) = current.take() {
+				materials.insert(name, material);
+			}
+			let name = args.join(" ");
+			if name.is_empty() {
+				return Err(parse_error("newmtl needs a name".into()));
+			}
+			current = Some((name, Material::default()));
+			has_roughness = false;
+			continue;
+		}
+
+		let material = match &mut current {
+			Some((_, material)) => material,
+			None => return Err(parse_error(format!("{} before newmtl", keyword))),
+		};
+		match keyword {
+			"Kd" => {
+				let [r, g, b] = floats::<3>(keyword, &args).map_err(parse_error)?;
+				material.base_color = [r, g, b, material.base_color[3]];
+			}
+			"d" => material.base_color[3] = floats::<1>(keyword, &args).map_err(parse_error)?[0],
+			"Tr" => {
+				material.base_color[3] = 1.0 - floats::<1>(keyword, &args).map_err(parse_error)?[0]
+			}
+			"Pr" => {
+				material.roughness = floats::<1>(keyword, &args).map_err(parse_error)?[0];
+				has_roughness = true;
+			}
+			// Approximates roughness from the Blinn-Phong exponent unless Pr is given.
+			"Ns" => {
+				let exponent = floats::<1>(keyword, &args).map_err(parse_error)?[0];
+				if !has_roughness {
+					material.roughness = (2.0 / (exponent.max(0.0) + 2.0)).sqrt();
+				}
+			}
+			"map_Kd" | "map_Bump" | "map_bump" | "bump" | "norm" | "map_Pr" => {
+				// Options such as -bm come before the file name.
+				let file = args
+					.last()
+					.ok_or_else(|| parse_error(format!("{} needs a file name", keyword)))?;
+				let texture_path = directory.join(file);
+				let img = image::open(&texture_path).map_err(|source| ObjError::Image {
+					path: texture_path,
+					source,
+				})?;
+				match keyword {
+					"map_Kd" => material.base_color_texture = Some(img),
+					"map_Pr" => material.roughness_texture = Some(img),
+					_ => material.normal_texture = Some(img),
+				}
+			}
+			_ => {}
+		}
+	}
+	if let Some((name, material)) = current {
+		materials.insert(name, material);
+	}
+
+	Ok(materials)
+}
+
+fn read(path: &Path) -> Result<String, ObjError> {
+	std::fs::read_to_string(path).map_err(|source| ObjError::Io {
+		path: path.to_path_buf(),
+		source,
+	})
+}
+
+/// The words of a line without its comment.
+fn statement(line: &str) -> std::str::SplitWhitespace<'_> {
+	line.split('#').next().unwrap_or("").split_whitespace()
+}
+
+fn floats<const N: usize>(keyword: &str, args: &[&str]) -> Result<[f32; N], String> {
+	if args.len() < N {
+		return Err(format!(
+			"{} needs {} numbers but has {}",
+			keyword,
+			N,
+			args.len()
+		));
+	}
+	let mut values = [0.0; N];
+	for (value, arg) in values.iter_mut().zip(args) {
+		*value = arg
+			.parse()
+			.map_err(|_| format!("{} has an invalid number {:?}", keyword, arg))?;
+	}
+	Ok(values)
+}
+
+/// Position, uv and normal index of a face vertex, all starting at 0.
+type FaceVertex = (usize, Option<usize>, Option<usize>);
+
+struct ObjParser<'a> {
+	directory: &'a Path,
+
+	positions: Vec<[f32; 3]>,
+	colors: Vec<[f32; 3]>,
+	uvs: Vec<[f32; 2]>,
+	normals: Vec<[f32; 3]>,
+
+	name: String,
+	material: Option<String>,
+	current: ObjMesh,
+	indices: HashMap<FaceVertex, usize>,
+	missing_normals: bool,
+
+	meshes: Vec<ObjMesh>,
+	material_libraries: Vec<PathBuf>,
+}
+impl<'a> ObjParser<'a> {
+	fn new(path: &'a Path) -> Self {
+		Self {
+			directory: path.parent().unwrap_or_else(|| Path::new("")),
+
+			positions: vec![],
+			colors: vec![],
+			uvs: vec![],
+			normals: vec![],
+
+			name: String::from("default"),
+			material: None,
+			current: Self::empty_mesh("default", None),
+			indices: HashMap::new(),
+			missing_normals: false,
+
+			meshes: vec![],
+			material_libraries: vec![],
+		}
+	}
+	fn empty_mesh(name: &str, material: Option<String>) -> ObjMesh {
+		ObjMesh {
+			name: name.to_string(),
+			mesh: MeshGenerator::default(),
+			material,
+		}
+	}
+	fn parse_line(&mut self, line: &str) -> Result<(), String> {
+		let mut words = statement(line);
+		let keyword = match words.next() {
+			Some(keyword) => keyword,
+			None => return Ok(()),
+		};
+		let args: Vec<&str> = words.collect();
+
+		match keyword {
+			"v" => {
+				self.positions.push(floats::<3>(keyword, &args)?);
+				// Some exporters append a vertex color.
+				let color = if args.len() >= 6 {
+					floats::<3>(keyword, &args[3..])?
+				} else {
+					[1.0, 1.0, 1.0]
+				};
+				self.colors.push(color);
+			}
+			"vt" => {
+				let u = floats::<1>(keyword, &args)?[0];
+				let v = if args.len() > 1 {
+					floats::<1>(keyword, &args[1..])?[0]
+				} else {
+					0.0
+				};
+				// obj puts v = 0 at the bottom of the image, wgpu at the top
+				self.uvs.push([u, 1.0 - v]);
+			}
+			"vn" => self.normals.push(floats::<3>(keyword, &args)?),
+			"f" => self.parse_face(&args)?,
+			"o" | "g" => {
+				self.name = if args.is_empty() {
+					String::from("default")
+				} else {
+					args.join(" ")
+				};
+				self.start_mesh();
+			}
+			"usemtl" => {
+				if args.is_empty() {
+					return Err("usemtl needs a material name".into());
+				}
+				self.material = Some(args.join(" "));
+				self.start_mesh();
+			}
+			"mtllib" => {
+				if args.is_empty() {
+					return Err("mtllib needs a file name".into());
+				}
+				let directory = self.directory;
+				self.material_libraries
+					.extend(args.iter().map(|file| directory.join(file)));
+			}
+			// Smoothing groups, lines, points and free-form geometry are not supported.
+			_ => {}
+		}
+		Ok(())
+	}
+	fn parse_face(&mut self, args: &[&str]) -> Result<(), String> {
+		if args.len() < 3 {
+			return Err(format!("face needs 3 vertices but has {}", args.len()));
+		}
+		let face = args
+			.iter()
+			.map(|arg| self.parse_face_vertex(arg))
+			.collect::<Result<Vec<_>, _>>()?;
+		let face: Vec<usize> = face
+			.into_iter()
+			.map(|vertex| self.vertex_index(vertex))
+			.collect();
+
+		for i in 1..face.len() - 1 {
+			for index in [face[0], face[i], face[i + 1]] {
+				self.current.mesh.indices.push(index as u32);
+			}
+		}
+		Ok(())
+	}
+	fn parse_face_vertex(&self, arg: &str) -> Result<FaceVertex, String> {
+		let mut parts = arg.split('/');
+		let position = Self::resolve(parts.next(), self.positions.len(), "position", arg)?
+			.ok_or_else(|| format!("face vertex {:?} has no position", arg))?;
+		let uv = Self::resolve(parts.next(), self.uvs.len(), "uv", arg)?;
+		let normal = Self::resolve(parts.next(), self.normals.len(), "normal", arg)?;
+		if parts.next().is_some() {
+			return Err(format!("face vertex {:?} has too many parts", arg));
+		}
+		Ok((position, uv, normal))
+	}
+	/// Turns a 1 based or negative (relative) index into a 0 based one.
+	fn resolve(
+		part: Option<&str>,
+		count: usize,
+		kind: &str,
+		arg: &str,
+	) -> Result<Option<usize>, String> {
+		let part = match part {
+			Some(part) if !part.is_empty() => part,
+			_ => return Ok(None),
+		};
+		let index: isize = part
+			.parse()
+			.map_err(|_| format!("face vertex {:?} has an invalid {} index", arg, kind))?;
+		let resolved = match index {
+			i if i > 0 => i - 1,
+			i if i < 0 => count as isize + i,
+			_ => -1,
+		};
+		if resolved < 0 || resolved as usize >= count {
+			return Err(format!(
+				"face vertex {:?} refers to {} {} but there are {}",
+				arg, kind, index, count
+			));
+		}
+		Ok(Some(resolved as usize))
+	}
+	fn vertex_index(&mut self, vertex: FaceVertex) -> usize {
+		if let Some(index) = self.indices.get(&vertex) {
+			return *index;
+		}
+		let (position, uv, normal) = vertex;
+		let index = self.current.mesh.vertices.len();
+		self.current.mesh.vertices.push(Vertex {
+			position: self.positions[position],
+			color: self.colors[position],
+			uv: uv.map(|uv| self.uvs[uv]).unwrap_or([0.0, 0.0]),
+			normal: match normal {
+				Some(normal) => self.normals[normal],
+				None => {
+					self.missing_normals = true;
+					[0.0; 3]
+				}
+			},
+			..Default::default()
+		});
+		self.indices.insert(vertex, index);
+		index
+	}
+	fn start_mesh(&mut self) {
+		let next = Self::empty_mesh(&self.name, self.material.clone());
+		let mut finished = std::mem::replace(&mut self.current, next);
+		self.indices.clear();
+		if !finished.mesh.indices.is_empty() {
+			if self.missing_normals {
+				finished.mesh.compute_smooth_normals();
+			}
+			finished.mesh.compute_angle_weighted_tangents();
+			self.meshes.push(finished);
+		}
+		self.missing_normals = false;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const QUAD: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n";
+
+	fn parse(source: &str) -> Result<ObjModel, ObjError> {
+		read_obj(source, "/nonexistent/model.obj")
+	}
+	fn parse_error_line(source: &str) -> usize {
+		match parse(source) {
+			Err(ObjError::Parse { line, .. }) => line,
+			Err(err) => panic!("expected a parse error, got {}", err),
+			Ok(_) => panic!("expected a parse error"),
+		}
+	}
+
+	#[test]
+	fn malformed_lines_are_errors() {
+		assert_eq!(parse_error_line("v 0 0\n"), 1);
+		assert_eq!(parse_error_line("v 0 0 0\nv 0 x 0\n"), 2);
+		assert_eq!(parse_error_line(&format!("{}f 1 2\n", QUAD)), 5);
+		assert_eq!(parse_error_line(&format!("{}f 1 2 5\n", QUAD)), 5);
+		assert_eq!(parse_error_line(&format!("{}f 1/1 2 3\n", QUAD)), 5);
+		assert_eq!(parse_error_line(&format!("{}f 1//1/1 2 3\n", QUAD)), 5);
+		assert_eq!(parse_error_line("usemtl\n"), 1);
+	}
+
+	#[test]
+	fn comments_and_unknown_statements_are_ignored() {
+		let source = format!("# a quad\n{}s 1\nl 1 2\nf 1 2 3 # first\n", QUAD);
+		let model = parse(&source).unwrap();
+		assert_eq!(model.meshes.len(), 1);
+		assert_eq!(model.meshes[0].mesh.indices, [0, 1, 2]);
+	}
+
+	#[test]
+	fn material_libraries_must_exist() {
+		assert_eq!(parse_error_line("mtllib\n"), 1);
+		match parse("mtllib missing.mtl\n") {
+			Err(ObjError::Io { path, .. }) => {
+				assert_eq!(path, Path::new("/nonexistent/missing.mtl"))
+			}
+			Err(err) => panic!("expected an io error, got {}", err),
+			Ok(_) => panic!("expected an io error"),
+		}
+	}
+
+	#[test]
+	fn polygons_are_fan_triangulated() {
+		let source = format!("{}v 0.5 1.5 0\nf 1 2 3 5 4\n", QUAD);
+		let model = parse(&source).unwrap();
+		let mesh = &model.meshes[0].mesh;
+		assert_eq!(mesh.indices, [0, 1, 2, 0, 2, 3, 0, 3, 4]);
+		assert_eq!(mesh.vertices[3].position, [0.5, 1.5, 0.0]);
+	}
+
+	#[test]
+	fn negative_indices_are_relative() {
+		let absolute = parse(&format!("{}vn 0 0 1\nf 2//1 3//1 4//1\n", QUAD)).unwrap();
+		let relative = parse(&format!("{}vn 0 0 1\nf -3//-1 -2//-1 -1//-1\n", QUAD)).unwrap();
+		let positions = |model: &ObjModel| -> Vec<[f32; 3]> {
+			let mesh = &model.meshes[0].mesh;
+			mesh.indices
+				.iter()
+				.map(|index| mesh.vertices[*index as usize].position)
+				.collect()
+		};
+		assert_eq!(positions(&relative), positions(&absolute));
+		assert_eq!(relative.meshes[0].mesh.vertices[0].normal, [0.0, 0.0, 1.0]);
+		assert_eq!(parse_error_line(&format!("{}f -5 -2 -1\n", QUAD)), 5);
+	}
+
+	#[test]
+	fn shared_face_vertices_are_merged() {
+		let source = format!(
+			"{}vt 0 0\nvt 1 1\nf 1/1 2/1 3/1\nf 1/1 3/1 4/1\nf 1/2 3/2 4/2\n",
+			QUAD
+		);
+		let model = parse(&source).unwrap();
+		let mesh = &model.meshes[0].mesh;
+		// the third face uses other uvs, so it gets its own vertices
+		assert_eq!(mesh.vertices.len(), 7);
+		assert_eq!(mesh.indices, [0, 1, 2, 0, 2, 3, 4, 5, 6]);
+		// obj puts v = 0 at the bottom
+		assert_eq!(mesh.vertices[0].uv, [0.0, 1.0]);
+		assert_eq!(mesh.vertices[4].uv, [1.0, 0.0]);
+	}
+
+	#[test]
+	fn groups_and_materials_start_meshes() {
+		let source = format!(
+			"{}o first\nf 1 2 3\nusemtl red\nf 1 3 4\ng\nf 1 2 4\n",
+			QUAD
+		);
+		let model = parse(&source).unwrap();
+		let meshes: Vec<_> = model
+			.meshes
+			.iter()
+			.map(|mesh| (mesh.name.as_str(), mesh.material.as_deref()))
+			.collect();
+		assert_eq!(
+			meshes,
+			[
+				("first", None),
+				("first", Some("red")),
+				("default", Some("red"))
+			]
+		);
+		// vertices are merged per mesh only
+		assert!(model
+			.meshes
+			.iter()
+			.all(|mesh| mesh.mesh.vertices.len() == 3));
+	}
+}
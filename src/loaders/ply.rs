@@ -0,0 +1,590 @@
+// File: loaders/ply.rs
+
+//! PLY loading and saving, ASCII and binary little endian. Vertices read their
+//! position, normal, uv (`s t`, `u v` or `texture_u texture_v`) and color
+//! (`red green blue`) properties, other properties and elements are skipped.
+//! Faces with more than three vertices are triangulated as fans.
+
+use crate::mesh::MeshGenerator;
+use crate::vertex::Vertex;
+
+use std::fmt;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum PlyError {
+	Io {
+		path: PathBuf,
+		source: std::io::Error,
+	},
+	/// A malformed header or body, or an unsupported format.
+	Parse { message: String },
+}
+impl fmt::Display for PlyError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			PlyError::Io { path, source } => {
+				write!(f, "failed to access {}: {}", path.display(), source)
+			}
+			PlyError::Parse { message } => write!(f, "invalid ply: {}", message),
+		}
+	}
+}
+impl std::error::Error for PlyError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			PlyError::Io { source, .. } => Some(source),
+			PlyError::Parse { .. } => None,
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PlyFormat {
+	Ascii,
+	BinaryLittleEndian,
+}
+
+pub fn load_ply(path: impl AsRef<Path>) -> Result<MeshGenerator, PlyError> {
+	let path = path.as_ref();
+	let bytes = std::fs::read(path).map_err(|source| PlyError::Io {
+		path: path.to_path_buf(),
+		source,
+	})?;
+	read_ply(&bytes)
+}
+
+/// Smooth normals are computed when the file has none, tangents always.
+/// Vertices without a color are white.
+pub fn read_ply(bytes: &[u8]) -> Result<MeshGenerator, PlyError> {
+	read(bytes).map_err(|message| PlyError::Parse { message })
+}
+
+pub fn save_ply(
+	generator: &MeshGenerator,
+	format: PlyFormat,
+	path: impl AsRef<Path>,
+) -> Result<(), PlyError> {
+	let path = path.as_ref();
+	std::fs::write(path, write_ply(generator, format)).map_err(|source| PlyError::Io {
+		path: path.to_path_buf(),
+		source,
+	})
+}
+
+/// Writes positions, normals and uvs, and 8 bit colors when a vertex is not
+/// white.
+pub fn write_ply(generator: &MeshGenerator, format: PlyFormat) -> Vec<u8> {
+	let has_colors = generator
+		.vertices
+		.iter()
+		.any(|vertex| vertex.color != [1.0, 1.0, 1.0]);
+	let triangle_count = generator.indices.len() / 3;
+
+	let mut header = String::from("ply\n");
+	header.push_str(match format {
+		PlyFormat::Ascii => "format ascii 1.0\n",
+		PlyFormat::BinaryLittleEndian => "format binary_little_endian 1.0\n",
+	});
+	header.push_str("comment wgpu_dev\n");
+	let _ = writeln!(header, "element vertex {}", generator.vertices.len());
+	for name in ["x", "y", "z", "nx", "ny", "nz", "s", "t"] {
+		let _ = writeln!(header, "property float {}", name);
+	}
+	if has_colors {
+		header.push_str("property uchar red\nproperty uchar green\nproperty uchar blue\n");
+	}
+	let _ = writeln!(header, "element face {}", triangle_count);
+	header.push_str("property list uchar uint vertex_indices\nend_header\n");
+
+	// ply puts t = 0 at the bottom of the image like obj
+	let floats = |vertex: &Vertex| {
+		let [x, y, z] = vertex.position;
+		let [nx, ny, nz] = vertex.normal;
+		let [s, t] = vertex.uv;
+		[x, y, z, nx, ny, nz, s, 1.0 - t]
+	};
+	let color = |vertex: &Vertex| {
+		vertex
+			.color
+			.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+	};
+	let triangles = generator.indices.chunks_exact(3);
+
+	let mut bytes = header.into_bytes();
+	match format {
+		PlyFormat::Ascii => {
+			let mut body = String::new();
+			for vertex in &generator.vertices {
+				let values: Vec<String> = floats(vertex).iter().map(f32::to_string).collect();
+				body.push_str(&values.join(" "));
+				if has_colors {
+					let [r, g, b] = color(vertex);
+					let _ = write!(body, " {} {} {}", r, g, b);
+				}
+				body.push('\n');
+			}
+			for triangle in triangles {
+				let _ = writeln!(body, "3 {} {} {}", triangle[0], triangle[1], triangle[2]);
+			}
+			bytes.extend_from_slice(body.as_bytes());
+		}
+		PlyFormat::BinaryLittleEndian => {
+			for vertex in &generator.vertices {
+				for float in floats(vertex) {
+					bytes.extend_from_slice(&float.to_le_bytes());
+				}
+				if has_colors {
+					bytes.extend_from_slice(&color(vertex));
+				}
+			}
+			for triangle in triangles {
+				bytes.push(3);
+				for index in triangle {
+					bytes.extend_from_slice(&index.to_le_bytes());
+				}
+			}
+		}
+	}
+	bytes
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Scalar {
+	I8,
+	U8,
+	I16,
+	U16,
+	I32,
+	U32,
+	F32,
+	F64,
+}
+impl Scalar {
+	fn parse(name: &str) -> Result<Self, String> {
+		Ok(match name {
+			"char" | "int8" => Scalar::I8,
+			"uchar" | "uint8" => Scalar::U8,
+			"short" | "int16" => Scalar::I16,
+			"ushort" | "uint16" => Scalar::U16,
+			"int" | "int32" => Scalar::I32,
+			"uint" | "uint32" => Scalar::U32,
+			"float" | "float32" => Scalar::F32,
+			"double" | "float64" => Scalar::F64,
+			_ => return Err(format!("unknown property type `{}`", name)),
+		})
+	}
+	/// What a color of this type is divided by, integers are scaled so that
+	/// their largest value is 1.
+	fn color_scale(self) -> f64 {
+		match self {
+			Scalar::I8 => i8::MAX as f64,
+			Scalar::U8 => u8::MAX as f64,
+			Scalar::I16 => i16::MAX as f64,
+			Scalar::U16 => u16::MAX as f64,
+			Scalar::I32 => i32::MAX as f64,
+			Scalar::U32 => u32::MAX as f64,
+			Scalar::F32 | Scalar::F64 => 1.0,
+		}
+	}
+}
+
+enum Property {
+	Scalar {
+		name: String,
+		scalar: Scalar,
+	},
+	List {
+		name: String,
+		count: Scalar,
+		item: Scalar,
+	},
+}
+
+struct Element {
+	name: String,
+	count: usize,
+	properties: Vec<Property>,
+}
+
+/// The body's values, read as `f64` which holds every PLY type exactly.
+enum Values<'a> {
+	Ascii(std::str::SplitWhitespace<'a>),
+	Binary { bytes: &'a [u8], offset: usize },
+}
+impl Values<'_> {
+	fn read(&mut self, scalar: Scalar) -> Result<f64, String> {
+		match self {
+			Values::Ascii(words) => {
+				let word = words.next().ok_or("the body ends early")?;
+				word.parse()
+					.map_err(|_| format!("expected a number, found `{}`", word))
+			}
+			Values::Binary { bytes, offset } => {
+				let size = match scalar {
+					Scalar::I8 | Scalar::U8 => 1,
+					Scalar::I16 | Scalar::U16 => 2,
+					Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+					Scalar::F64 => 8,
+				};
+				let value = bytes
+					.get(*offset..*offset + size)
+					.ok_or("the body ends early")?;
+				*offset += size;
+				let mut buffer = [0u8; 8];
+				buffer[..size].copy_from_slice(value);
+				let [b0, b1, b2, b3, ..] = buffer;
+				Ok(match scalar {
+					Scalar::I8 => b0 as i8 as f64,
+					Scalar::U8 => b0 as f64,
+					Scalar::I16 => i16::from_le_bytes([b0, b1]) as f64,
+					Scalar::U16 => u16::from_le_bytes([b0, b1]) as f64,
+					Scalar::I32 => i32::from_le_bytes([b0, b1, b2, b3]) as f64,
+					Scalar::U32 => u32::from_le_bytes([b0, b1, b2, b3]) as f64,
+					Scalar::F32 => f32::from_le_bytes([b0, b1, b2, b3]) as f64,
+					Scalar::F64 => f64::from_le_bytes(buffer),
+				})
+			}
+		}
+	}
+}
+
+fn read(bytes: &[u8]) -> Result<MeshGenerator, String> {
+	let (format, elements, body) = read_header(bytes)?;
+	let mut values = match format {
+		PlyFormat::Ascii => Values::Ascii(
+			std::str::from_utf8(body)
+				.map_err(|err| err.to_string())?
+				.split_whitespace(),
+		),
+		PlyFormat::BinaryLittleEndian => Values::Binary {
+			bytes: body,
+			offset: 0,
+		},
+	};
+
+	let mut generator = MeshGenerator::default();
+	let mut has_normals = false;
+	let mut faces: Vec<Vec<u32>> = vec![];
+	for element in &elements {
+		for _ in 0..element.count {
+			let mut vertex = Vertex {
+				color: [1.0, 1.0, 1.0],
+				..Vertex::default()
+			};
+			for property in &element.properties {
+				match property {
+					Property::Scalar { name, scalar } => {
+						let value = values.read(*scalar)?;
+						if element.name != "vertex" {
+							continue;
+						}
+						let value32 = value as f32;
+						match name.as_str() {
+							"x" => vertex.position[0] = value32,
+							"y" => vertex.position[1] = value32,
+							"z" => vertex.position[2] = value32,
+							"nx" => vertex.normal[0] = value32,
+							"ny" => vertex.normal[1] = value32,
+							"nz" => vertex.normal[2] = value32,
+							"s" | "u" | "texture_u" | "texture_s" => vertex.uv[0] = value32,
+							// ply puts t = 0 at the bottom of the image, wgpu at the top
+							"t" | "v" | "texture_v" | "texture_t" => vertex.uv[1] = 1.0 - value32,
+							"red" => vertex.color[0] = (value / scalar.color_scale()) as f32,
+							"green" => vertex.color[1] = (value / scalar.color_scale()) as f32,
+							"blue" => vertex.color[2] = (value / scalar.color_scale()) as f32,
+							_ => {}
+						}
+					}
+					Property::List { name, count, item } => {
+						let count = values.read(*count)?;
+						if count < 0.0 {
+							return Err(format!("negative list length {}", count));
+						}
+						// the count comes from the file, so it is not trusted for an allocation
+						let mut list = vec![];
+						for _ in 0..count as usize {
+							list.push(values.read(*item)?);
+						}
+						if element.name == "face"
+							&& (name == "vertex_indices" || name == "vertex_index")
+						{
+							let indices = list
+								.into_iter()
+								.map(|index| {
+									if index >= 0.0 {
+										Ok(index as u32)
+									} else {
+										Err(format!("negative vertex index {}", index))
+									}
+								})
+								.collect::<Result<_, String>>()?;
+							faces.push(indices);
+						}
+					}
+				}
+			}
+			if element.name == "vertex" {
+				generator.vertices.push(vertex);
+			}
+		}
+		if element.name == "vertex" {
+			has_normals = element
+				.properties
+				.iter()
+				.any(|property| matches!(property, Property::Scalar { name, .. } if name == "nx"));
+		}
+	}
+
+	for face in faces {
+		if let Some(index) = face
+			.iter()
+			.find(|index| **index as usize >= generator.vertices.len())
+		{
+			return Err(format!("vertex index {} is out of range", index));
+		}
+		for k in 1..face.len().saturating_sub(1) {
+			generator
+				.indices
+				.extend_from_slice(&[face[0], face[k], face[k + 1]]);
+		}
+	}
+	if !has_normals {
+		generator.compute_smooth_normals();
+	}
+	generator.compute_angle_weighted_tangents();
+	Ok(generator)
+}
+
+/// The format, the elements and the bytes after the header.
+fn read_header(bytes: &[u8]) -> Result<(PlyFormat, Vec<Element>, &[u8]), String> {
+	const END: &[u8] = b"end_header";
+	let end = bytes
+		.windows(END.len())
+		.position(|window| window == END)
+		.ok_or("missing end_header")?;
+	let body_start = bytes[end..]
+		.iter()
+		.position(|byte| *byte == b'\n')
+		.map(|newline| end + newline + 1)
+		.ok_or("missing a line break after end_header")?;
+	let header = std::str::from_utf8(&bytes[..end]).map_err(|err| err.to_string())?;
+
+	let mut lines = header.lines();
+	if lines.next().map(str::trim) != Some("ply") {
+		return Err("missing the ply magic".into());
+	}
+	let mut format = None;
+	let mut elements: Vec<Element> = vec![];
+	for line in lines {
+		let words: Vec<&str> = line.split_whitespace().collect();
+		match words.as_slice() {
+			["format", "ascii", _] => format = Some(PlyFormat::Ascii),
+			["format", "binary_little_endian", _] => format = Some(PlyFormat::BinaryLittleEndian),
+			["format", other, ..] => return Err(format!("unsupported format `{}`", other)),
+			["element", name, count] => elements.push(Element {
+				name: name.to_string(),
+				count: count
+					.parse()
+					.map_err(|_| format!("invalid element count `{}`", count))?,
+				properties: vec![],
+			}),
+			["property", "list", count, item, name] => elements
+				.last_mut()
+				.ok_or("property before any element")?
+				.properties
+				.push(Property::List {
+					name: name.to_string(),
+					count: Scalar::parse(count)?,
+					item: Scalar::parse(item)?,
+				}),
+			["property", scalar, name] => elements
+				.last_mut()
+				.ok_or("property before any element")?
+				.properties
+				.push(Property::Scalar {
+					name: name.to_string(),
+					scalar: Scalar::parse(scalar)?,
+				}),
+			["comment", ..] | ["obj_info", ..] | [] => {}
+			_ => return Err(format!("unknown header line `{}`", line)),
+		}
+	}
+	let format = format.ok_or("missing the format line")?;
+	// items without properties take no bytes, so their count can not be checked
+	// against the body
+	if let Some(element) = elements
+		.iter()
+		.find(|element| element.count > 0 && element.properties.is_empty())
+	{
+		return Err(format!(
+			"element `{}` has {} items but no properties",
+			element.name, element.count
+		));
+	}
+	Ok((format, elements, &bytes[body_start..]))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn colored_torus() -> MeshGenerator {
+		let mut generator = MeshGenerator::default();
+		generator.torus(1.0, 0.25, 12, 6);
+		for (i, vertex) in generator.vertices.iter_mut().enumerate() {
+			vertex.color = [(i % 256) as f32 / 255.0, 0.5, 1.0];
+		}
+		generator
+	}
+
+	fn assert_round_trip(generator: &MeshGenerator, format: PlyFormat) {
+		let loaded = read_ply(&write_ply(generator, format)).unwrap();
+		assert_eq!(loaded.indices, generator.indices);
+		assert_eq!(loaded.vertices.len(), generator.vertices.len());
+		for (loaded, original) in loaded.vertices.iter().zip(&generator.vertices) {
+			assert_eq!(loaded.position, original.position);
+			assert_eq!(loaded.normal, original.normal);
+			for (loaded, original) in loaded.uv.iter().zip(original.uv) {
+				assert!((loaded - original).abs() < 1e-6);
+			}
+			for (loaded, original) in loaded.color.iter().zip(original.color) {
+				assert!((loaded - original).abs() <= 1.0 / 255.0);
+			}
+		}
+	}
+
+	#[test]
+	fn ascii_round_trip() {
+		assert_round_trip(&colored_torus(), PlyFormat::Ascii);
+	}
+
+	#[test]
+	fn binary_round_trip() {
+		assert_round_trip(&colored_torus(), PlyFormat::BinaryLittleEndian);
+	}
+
+	#[test]
+	fn white_meshes_are_written_without_colors() {
+		let mut generator = MeshGenerator::default();
+		generator.cuboid(1.0, 1.0, 1.0);
+		let bytes = write_ply(&generator, PlyFormat::Ascii);
+		let source = String::from_utf8(bytes).unwrap();
+		assert!(!source.contains("red"));
+		assert_round_trip(&generator, PlyFormat::Ascii);
+	}
+
+	#[test]
+	fn polygons_and_extra_properties() {
+		let source = "ply
+format ascii 1.0
+comment a quad with alpha and an unused element
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+property uchar alpha
+element face 1
+property list uchar int vertex_indices
+element edge 1
+property int vertex1
+property int vertex2
+end_header
+0 0 0 255 0 0 255
+1 0 0 0 255 0 255
+1 1 0 0 0 255 255
+0 1 0 255 255 255 255
+4 0 1 2 3
+0 1
+";
+		let loaded = read_ply(source.as_bytes()).unwrap();
+		assert_eq!(loaded.indices, [0, 1, 2, 0, 2, 3]);
+		assert_eq!(loaded.vertices[1].color, [0.0, 1.0, 0.0]);
+		assert_eq!(loaded.vertices[0].normal, [0.0, 0.0, 1.0]);
+	}
+
+	#[test]
+	fn out_of_range_indices_are_an_error() {
+		let source = "ply
+format ascii 1.0
+element vertex 1
+property float x
+property float y
+property float z
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0
+3 0 1 2
+";
+		assert!(read_ply(source.as_bytes()).is_err());
+	}
+
+	#[test]
+	fn huge_list_lengths_are_an_error() {
+		let mut bytes = b"ply
+format binary_little_endian 1.0
+element vertex 1
+property float x
+property float y
+property float z
+element face 1
+property list uint int vertex_indices
+end_header
+"
+		.to_vec();
+		for float in [0.0f32; 3] {
+			bytes.extend_from_slice(&float.to_le_bytes());
+		}
+		bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+		bytes.extend_from_slice(&0i32.to_le_bytes());
+		assert!(read_ply(&bytes).is_err());
+	}
+
+	#[test]
+	fn elements_without_properties_are_an_error() {
+		let source = "ply
+format ascii 1.0
+element vertex 4000000000
+element face 0
+property list uchar int vertex_indices
+end_header
+";
+		assert!(read_ply(source.as_bytes()).is_err());
+
+		let source = "ply
+format ascii 1.0
+element vertex 0
+property float x
+element padding 18446744073709551615
+end_header
+";
+		assert!(read_ply(source.as_bytes()).is_err());
+	}
+
+	#[test]
+	fn integer_colors_are_scaled_by_their_type() {
+		let source = "ply
+format ascii 1.0
+element vertex 3
+property float x
+property float y
+property float z
+property int red
+property short green
+property float blue
+end_header
+0 0 0 2147483647 32767 1.0
+1 0 0 0 0 0.5
+0 1 0 2147483647 0 0.25
+";
+		let loaded = read_ply(source.as_bytes()).unwrap();
+		assert_eq!(loaded.vertices[0].color, [1.0, 1.0, 1.0]);
+		assert_eq!(loaded.vertices[1].color, [0.0, 0.0, 0.5]);
+		assert_eq!(loaded.vertices[2].color, [1.0, 0.0, 0.25]);
+	}
+}
@@ -0,0 +1,353 @@
+// File: loaders/stl.rs
+
+//! STL loading and saving, ASCII and binary. STL stores separate corners for
+//! every triangle, so the loaded mesh is flat shaded with the facet normals.
+//! Use `MeshGenerator::weld_vertices` to share corners.
+
+use crate::mesh::MeshGenerator;
+use crate::vertex::Vertex;
+
+use bevy::math::Vec3;
+
+use std::fmt;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+const HEADER_SIZE: usize = 80;
+const TRIANGLE_SIZE: usize = 50;
+
+#[derive(Debug)]
+pub enum StlError {
+	Io {
+		path: PathBuf,
+		source: std::io::Error,
+	},
+	/// Data that is neither valid ASCII nor binary STL.
+	Parse { message: String },
+}
+impl fmt::Display for StlError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			StlError::Io { path, source } => {
+				write!(f, "failed to access {}: {}", path.display(), source)
+			}
+			StlError::Parse { message } => write!(f, "invalid stl: {}", message),
+		}
+	}
+}
+impl std::error::Error for StlError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			StlError::Io { source, .. } => Some(source),
+			StlError::Parse { .. } => None,
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StlFormat {
+	Ascii,
+	Binary,
+}
+
+pub fn load_stl(path: impl AsRef<Path>) -> Result<MeshGenerator, StlError> {
+	let path = path.as_ref();
+	let bytes = std::fs::read(path).map_err(|source| StlError::Io {
+		path: path.to_path_buf(),
+		source,
+	})?;
+	read_stl(&bytes)
+}
+
+/// Reads binary STL when the data is long enough for its triangle count and
+/// does not read as ASCII, ASCII otherwise.
+/// Facet normals that are missing or zero are computed from the winding.
+pub fn read_stl(bytes: &[u8]) -> Result<MeshGenerator, StlError> {
+	let parse_error = |message: String| StlError::Parse { message };
+	let triangles = if is_binary(bytes) {
+		read_binary(bytes)
+	} else {
+		let source = std::str::from_utf8(bytes).map_err(|err| parse_error(err.to_string()))?;
+		read_ascii(source).map_err(parse_error)?
+	};
+
+	let mut generator = MeshGenerator::default();
+	for (normal, corners) in triangles {
+		let normal = match Vec3::from(normal).try_normalize() {
+			Some(normal) => normal,
+			None => {
+				let [a, b, c] = corners.map(Vec3::from);
+				(b - a).cross(c - a).normalize_or_zero()
+			}
+		};
+		let first = generator.vertices.len() as u32;
+		for position in corners {
+			generator.vertices.push(Vertex {
+				position,
+				color: [1.0, 1.0, 1.0],
+				normal: normal.into(),
+				..Vertex::default()
+			});
+		}
+		generator
+			.indices
+			.extend_from_slice(&[first, first + 1, first + 2]);
+	}
+	generator.compute_angle_weighted_tangents();
+	Ok(generator)
+}
+
+pub fn save_stl(
+	generator: &MeshGenerator,
+	format: StlFormat,
+	path: impl AsRef<Path>,
+) -> Result<(), StlError> {
+	let path = path.as_ref();
+	std::fs::write(path, write_stl(generator, format)).map_err(|source| StlError::Io {
+		path: path.to_path_buf(),
+		source,
+	})
+}
+
+/// Writes the triangles with normals computed from their winding.
+pub fn write_stl(generator: &MeshGenerator, format: StlFormat) -> Vec<u8> {
+	let triangles = generator.indices.chunks_exact(3).map(|triangle| {
+		let corners = [0, 1, 2].map(|k| generator.vertices[triangle[k] as usize].position);
+		let [a, b, c] = corners.map(Vec3::from);
+		let normal = (b - a).cross(c - a).normalize_or_zero();
+		(normal.to_array(), corners)
+	});
+
+	match format {
+		StlFormat::Binary => {
+			let mut header = [0u8; HEADER_SIZE];
+			let name = b"wgpu_dev";
+			header[..name.len()].copy_from_slice(name);
+
+			let count = generator.indices.len() / 3;
+			let mut bytes = Vec::with_capacity(HEADER_SIZE + 4 + count * TRIANGLE_SIZE);
+			bytes.extend_from_slice(&header);
+			bytes.extend_from_slice(&(count as u32).to_le_bytes());
+			for (normal, corners) in triangles {
+				for float in std::iter::once(normal).chain(corners).flatten() {
+					bytes.extend_from_slice(&float.to_le_bytes());
+				}
+				// attribute byte count, unused
+				bytes.extend_from_slice(&[0, 0]);
+			}
+			bytes
+		}
+		StlFormat::Ascii => {
+			// `{:e}` prints the shortest form that reads back to the same float
+			let mut source = String::from("solid wgpu_dev\n");
+			for (normal, corners) in triangles {
+				let _ = writeln!(
+					source,
+					"facet normal {:e} {:e} {:e}\n  outer loop",
+					normal[0], normal[1], normal[2]
+				);
+				for [x, y, z] in corners {
+					let _ = writeln!(source, "    vertex {:e} {:e} {:e}", x, y, z);
+				}
+				source.push_str("  endloop\nendfacet\n");
+			}
+			source.push_str("endsolid wgpu_dev\n");
+			source.into_bytes()
+		}
+	}
+}
+
+/// Binary files may start with `solid` too, so the size decides. Some
+/// exporters write bytes after the triangles, so a longer file is binary as
+/// well unless it reads as ASCII.
+fn is_binary(bytes: &[u8]) -> bool {
+	let size = match binary_size(bytes) {
+		Some(size) => size,
+		None => return false,
+	};
+	if bytes.len() == size {
+		return true;
+	}
+	bytes.len() > size
+		&& match std::str::from_utf8(bytes) {
+			Ok(source) => read_ascii(source).is_err(),
+			Err(_) => true,
+		}
+}
+
+/// The size of a binary file with the triangle count in the header.
+fn binary_size(bytes: &[u8]) -> Option<usize> {
+	if bytes.len() < HEADER_SIZE + 4 {
+		return None;
+	}
+	let count = u32::from_le_bytes([
+		bytes[HEADER_SIZE],
+		bytes[HEADER_SIZE + 1],
+		bytes[HEADER_SIZE + 2],
+		bytes[HEADER_SIZE + 3],
+	]) as usize;
+	count
+		.checked_mul(TRIANGLE_SIZE)
+		.and_then(|size| size.checked_add(HEADER_SIZE + 4))
+}
+
+type Facet = ([f32; 3], [[f32; 3]; 3]);
+
+/// Reads the header's number of triangles, `bytes` has to be long enough.
+fn read_binary(bytes: &[u8]) -> Vec<Facet> {
+	let end = binary_size(bytes).unwrap_or(bytes.len());
+	bytes[HEADER_SIZE + 4..end]
+		.chunks_exact(TRIANGLE_SIZE)
+		.map(|triangle| {
+			let float = |i: usize| {
+				let at = i * 4;
+				f32::from_le_bytes([
+					triangle[at],
+					triangle[at + 1],
+					triangle[at + 2],
+					triangle[at + 3],
+				])
+			};
+			let vector = |i: usize| [float(i * 3), float(i * 3 + 1), float(i * 3 + 2)];
+			(vector(0), [vector(1), vector(2), vector(3)])
+		})
+		.collect()
+}
+
+fn read_ascii(source: &str) -> Result<Vec<Facet>, String> {
+	let mut words = source.split_whitespace();
+	if words.next() != Some("solid") {
+		return Err("expected `solid` or a binary header".into());
+	}
+	let expect = |expected: &str, words: &mut std::str::SplitWhitespace<'_>| match words.next() {
+		Some(word) if word == expected => Ok(()),
+		Some(word) => Err(format!("expected `{}`, found `{}`", expected, word)),
+		None => Err(format!("expected `{}`, found the end", expected)),
+	};
+	let vector = |words: &mut std::str::SplitWhitespace<'_>| -> Result<[f32; 3], String> {
+		let mut vector = [0.0; 3];
+		for value in &mut vector {
+			let word = words.next().ok_or("expected a number, found the end")?;
+			*value = word
+				.parse()
+				.map_err(|_| format!("expected a number, found `{}`", word))?;
+		}
+		Ok(vector)
+	};
+
+	let mut facets = vec![];
+	// the solid's name may have several words, skip to the first facet
+	let mut word = words.find(|word| *word == "facet" || *word == "endsolid");
+	while word == Some("facet") {
+		expect("normal", &mut words)?;
+		let normal = vector(&mut words)?;
+		expect("outer", &mut words)?;
+		expect("loop", &mut words)?;
+		let mut corners = [[0.0; 3]; 3];
+		for corner in &mut corners {
+			expect("vertex", &mut words)?;
+			*corner = vector(&mut words)?;
+		}
+		expect("endloop", &mut words)?;
+		expect("endfacet", &mut words)?;
+		facets.push((normal, corners));
+		word = words.next();
+	}
+	match word {
+		Some("endsolid") => Ok(facets),
+		Some(word) => Err(format!("expected `facet` or `endsolid`, found `{}`", word)),
+		None => Err("expected `endsolid`, found the end".into()),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn positions(generator: &MeshGenerator) -> Vec<[f32; 3]> {
+		generator
+			.indices
+			.iter()
+			.map(|index| generator.vertices[*index as usize].position)
+			.collect()
+	}
+
+	#[test]
+	fn binary_round_trip() {
+		let mut generator = MeshGenerator::default();
+		generator.icosphere(1.0, 2, crate::mesh::SphereUvs::Equirectangular);
+		let bytes = write_stl(&generator, StlFormat::Binary);
+		assert_eq!(bytes.len(), 84 + generator.indices.len() / 3 * 50);
+
+		let loaded = read_stl(&bytes).unwrap();
+		assert_eq!(positions(&loaded), positions(&generator));
+	}
+
+	#[test]
+	fn ascii_round_trip() {
+		let mut generator = MeshGenerator::default();
+		generator.torus(1.0, 0.25, 12, 6);
+		let bytes = write_stl(&generator, StlFormat::Ascii);
+		assert!(bytes.starts_with(b"solid"));
+
+		let loaded = read_stl(&bytes).unwrap();
+		assert_eq!(positions(&loaded), positions(&generator));
+		for vertex in &loaded.vertices {
+			let length = Vec3::from(vertex.normal).length();
+			assert!((length - 1.0).abs() < 1e-5);
+		}
+	}
+
+	#[test]
+	fn binary_header_starting_with_solid() {
+		let mut generator = MeshGenerator::default();
+		generator.cuboid(1.0, 1.0, 1.0);
+		let mut bytes = write_stl(&generator, StlFormat::Binary);
+		bytes[..6].copy_from_slice(b"solid ");
+
+		let loaded = read_stl(&bytes).unwrap();
+		assert_eq!(positions(&loaded), positions(&generator));
+	}
+
+	#[test]
+	fn ascii_with_zero_normals() {
+		let source = "solid triangle
+			facet normal 0 0 0
+				outer loop
+					vertex 0 0 0
+					vertex 1 0 0
+					vertex 0 1 0
+				endloop
+			endfacet
+			endsolid triangle";
+		let loaded = read_stl(source.as_bytes()).unwrap();
+		assert_eq!(loaded.indices, [0, 1, 2]);
+		assert_eq!(loaded.vertices[0].normal, [0.0, 0.0, 1.0]);
+	}
+
+	#[test]
+	fn truncated_ascii_is_an_error() {
+		let source = "solid broken\nfacet normal 0 0 1\nouter loop\nvertex 0 0";
+		assert!(read_stl(source.as_bytes()).is_err());
+	}
+
+	#[test]
+	fn binary_with_trailing_bytes() {
+		let mut generator = MeshGenerator::default();
+		generator.cuboid(1.0, 1.0, 1.0);
+		let mut bytes = write_stl(&generator, StlFormat::Binary);
+		bytes[..6].copy_from_slice(b"solid ");
+		bytes.extend_from_slice(&[0; 60]);
+
+		let loaded = read_stl(&bytes).unwrap();
+		assert_eq!(positions(&loaded), positions(&generator));
+	}
+
+	#[test]
+	fn short_binary_is_not_binary() {
+		let mut generator = MeshGenerator::default();
+		generator.cuboid(1.0, 1.0, 1.0);
+		let bytes = write_stl(&generator, StlFormat::Binary);
+		assert!(read_stl(&bytes[..bytes.len() - 1]).is_err());
+	}
+}
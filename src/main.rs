@@ -1,24 +1,23 @@
 // File: main.rs
 
-mod camera;
-mod mesh;
-mod render_state;
-mod texture;
-mod vertex;
+use wgpu_dev::camera::{Camera, CameraController, PrimaryCamera, Projection};
+use wgpu_dev::loaders::gltf::load_gltf;
+use wgpu_dev::loaders::obj::load_obj;
+use wgpu_dev::loaders::ply::load_ply;
+use wgpu_dev::loaders::stl::load_stl;
+use wgpu_dev::material::Material;
+use wgpu_dev::mesh::{Lod, MeshData, MeshGenerator, OptimizeSettings, ShouldDraw, SphereUvs};
+use wgpu_dev::screenshot::ScreenshotRequest;
+use wgpu_dev::{RenderState, WgpuRendererPlugin};
 
-use camera::{Camera, CameraBindGroup, CameraController, PrimaryCamera, Projection};
-use mesh::{Mesh, MeshGenerator, ShouldDraw};
-use render_state::*;
-
-use bevy::{
-	app::App, ecs::system::Commands, input::keyboard::KeyCode, prelude::*, window::WindowResized,
-	winit::WinitWindows,
-};
-
-fn init_renderer(windows: Res<WinitWindows>, mut commands: Commands) {
-	let (_id, window) = windows.as_ref().windows.iter().next().unwrap();
-	let renderer = pollster::block_on(RenderState::new(&window));
+use bevy::{app::App, ecs::system::Commands, input::keyboard::KeyCode, prelude::*};
 
+fn setup_scene(
+	renderer: Res<RenderState>,
+	mut meshes: ResMut<Assets<MeshData>>,
+	mut materials: ResMut<Assets<Material>>,
+	mut commands: Commands,
+) {
 	{
 		let camera = {
 			let projection = {
@@ -31,79 +30,122 @@ fn init_renderer(windows: Res<WinitWindows>, mut commands: Commands) {
 			let target = cgmath::Point3::new(0.0, 0.0, 0.0);
 			Camera::new(eye, target, &projection)
 		};
-		let camera_bind_group =
-			CameraBindGroup::new(&renderer.device, Some("camera_bind_group"), &camera);
 		let camera_controller = CameraController::default();
 
 		commands
 			.spawn()
 			.insert(PrimaryCamera {})
 			.insert(camera)
-			.insert(camera_bind_group)
 			.insert(camera_controller);
 	}
 
-	{
+	// `cargo run -- model.obj` (or .gltf, .glb, .stl, .ply) shows the model instead of the sphere
+	if let Some(path) = std::env::args().nth(1) {
+		if path.ends_with(".gltf") || path.ends_with(".glb") {
+			let model = load_gltf(&path).unwrap_or_else(|err| panic!("{}", err));
+			model.spawn(&mut commands, &mut meshes, &mut materials);
+		} else if path.ends_with(".stl") || path.ends_with(".ply") {
+			let mut mesh = if path.ends_with(".stl") {
+				load_stl(&path).unwrap_or_else(|err| panic!("{}", err))
+			} else {
+				load_ply(&path).unwrap_or_else(|err| panic!("{}", err))
+			};
+			mesh.optimize(OptimizeSettings::default());
+			commands
+				.spawn()
+				.insert(meshes.add(MeshData::from(mesh)))
+				.insert(Transform::default())
+				.insert(GlobalTransform::default())
+				.insert(ShouldDraw);
+		} else {
+			let model = load_obj(&path).unwrap_or_else(|err| panic!("{}", err));
+			let material_handles: std::collections::HashMap<_, _> = model
+				.materials
+				.into_iter()
+				.map(|(name, material)| (name, materials.add(material)))
+				.collect();
+			for mut obj_mesh in model.meshes {
+				obj_mesh.mesh.optimize(OptimizeSettings::default());
+				let mut entity = commands.spawn();
+				entity
+					.insert(meshes.add(MeshData::from(obj_mesh.mesh)))
+					.insert(Transform::default())
+					.insert(GlobalTransform::default())
+					.insert(ShouldDraw);
+				if let Some(handle) = obj_mesh
+					.material
+					.and_then(|name| material_handles.get(&name))
+				{
+					entity.insert(handle.clone());
+				}
+			}
+		}
+	} else {
 		let radius = 0.25;
-		let divs = 64;
-		let sphere = MeshGenerator::default()
-			.uv_sphere(radius, divs, divs)
-			.build(&renderer.device, Some("sphere_mesh"));
-		commands.spawn().insert(sphere).insert(ShouldDraw {});
-	}
-
-	commands.insert_resource(renderer);
-}
-fn render(
-	renderer: Res<RenderState>,
-	camera_query: Query<&CameraBindGroup, With<PrimaryCamera>>,
-	mesh_query: Query<&Mesh, With<ShouldDraw>>,
-) {
-	let renderer = renderer.as_ref();
-	let camera_bind_group = camera_query.iter().next().unwrap();
-	let _result = renderer.render(camera_bind_group, mesh_query.iter());
-}
-fn window_resize(
-	mut reader: EventReader<WindowResized>,
-	mut renderer: ResMut<RenderState>,
-	mut camera_query: Query<(&mut Camera, &mut CameraBindGroup), With<PrimaryCamera>>,
-) {
-	if let Some(event) = reader.iter().next() {
-		let (width, height) = (event.width as u32, event.height as u32);
-		renderer.as_mut().resize(width, height);
-
-		let (mut camera, mut camera_bind_group) = camera_query.iter_mut().next().unwrap();
-		camera.set_aspect(width, height);
-		camera_bind_group.uniform.set_view_proj(&camera);
-		renderer.queue.write_buffer(
-			&camera_bind_group.buffer,
-			0,
-			bytemuck::cast_slice(&[camera_bind_group.uniform]),
-		);
+		let subdivisions = 5;
+		// the sphere and its simplified levels are only generated on the first run
+		let cache_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+			.join("target")
+			.join("mesh_cache");
+		if let Err(err) = std::fs::create_dir_all(&cache_dir) {
+			log::warn!("failed to create {}: {}", cache_dir.display(), err);
+		}
+		// (ratio, screen size) of each simplified level, the full sphere is drawn
+		// down to a fifth of the screen height
+		let simplified = [(0.25, 0.1), (0.05, 0.0)];
+		let ratios: Vec<f32> = simplified.iter().map(|(ratio, _)| *ratio).collect();
+		let screen_sizes: Vec<f32> = std::iter::once(0.2)
+			.chain(simplified.iter().map(|(_, screen_size)| *screen_size))
+			.collect();
+		let key: Vec<String> = ratios.iter().map(f32::to_string).collect();
+		let cache = cache_dir.join(format!(
+			"icosphere_{}_{}_{}.wmsh",
+			radius,
+			subdivisions,
+			key.join("_")
+		));
+		let generate = || {
+			let mut sphere = MeshGenerator::default();
+			sphere.icosphere(radius, subdivisions, SphereUvs::Equirectangular);
+			sphere.optimize(OptimizeSettings::default());
+			let mut levels = sphere.lod_chain(&ratios);
+			levels.insert(0, sphere);
+			levels
+		};
+		let mut levels = MeshGenerator::read_cache_or_else(&cache, generate);
+		if levels.len() != screen_sizes.len() {
+			log::warn!(
+				"regenerating {}: it has {} levels instead of {}",
+				cache.display(),
+				levels.len(),
+				screen_sizes.len()
+			);
+			levels = generate();
+			if let Err(err) = MeshGenerator::write_cache(&levels, &cache) {
+				log::warn!("{}", err);
+			}
+		}
+		let levels = levels
+			.into_iter()
+			.map(|level| meshes.add(MeshData::from(level)))
+			.collect();
+		commands
+			.spawn()
+			.insert(Lod::new(levels, screen_sizes))
+			.insert(Transform::default())
+			.insert(GlobalTransform::default())
+			.insert(ShouldDraw);
 	}
 }
-pub fn camera_controls(
-	keys: Res<Input<KeyCode>>,
-	renderer: Res<RenderState>,
-	mut camera_query: Query<
-		(&mut Camera, &mut CameraController, &mut CameraBindGroup),
-		With<PrimaryCamera>,
-	>,
-) {
-	let (mut camera, mut camera_controller, mut camera_bind_group) =
-		camera_query.iter_mut().next().unwrap();
-
-	let changed = camera_controller.process_events(keys);
-	if changed {
-		camera_controller.update_camera(&mut camera);
-		camera.update_view_matrix();
-
-		camera_bind_group.uniform.set_view_proj(&camera);
-		renderer.queue.write_buffer(
-			&camera_bind_group.buffer,
-			0,
-			bytemuck::cast_slice(&[camera_bind_group.uniform]),
-		);
+fn screenshot_key(keys: Res<Input<KeyCode>>, mut writer: EventWriter<ScreenshotRequest>) {
+	if keys.just_pressed(KeyCode::F12) {
+		let timestamp = std::time::SystemTime::now()
+			.duration_since(std::time::UNIX_EPOCH)
+			.map(|duration| duration.as_secs())
+			.unwrap_or_default();
+		writer.send(ScreenshotRequest {
+			path: format!("screenshot_{}.png", timestamp).into(),
+		});
 	}
 }
 
@@ -111,12 +153,14 @@ fn main() {
 	env_logger::init();
 
 	App::new()
+		.add_plugin(bevy::core::CorePlugin)
+		.add_plugin(bevy::asset::AssetPlugin)
 		.add_plugin(bevy::window::WindowPlugin::default())
-		.add_plugin(bevy::input::InputPlugin::default())
-		.add_plugin(bevy::winit::WinitPlugin::default())
-		.add_startup_system(init_renderer.system())
-		.add_system(render.system())
-		.add_system(window_resize.system())
-		.add_system(camera_controls.system())
+		.add_plugin(bevy::input::InputPlugin)
+		.add_plugin(bevy::winit::WinitPlugin)
+		.add_plugin(bevy::transform::TransformPlugin)
+		.add_plugin(WgpuRendererPlugin)
+		.add_startup_system(setup_scene.system())
+		.add_system(screenshot_key.system())
 		.run();
 }
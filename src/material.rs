@@ -0,0 +1,163 @@
+// File: material.rs
+
+use crate::texture::{SamplerSettings, Texture, TextureBindGroup};
+
+use anyhow::*;
+
+use bevy::asset::HandleId;
+use bevy::reflect::TypeUuid;
+
+use std::collections::HashMap;
+
+/// Surface description of a mesh. Entities select one with a `Handle<Material>`,
+/// entities without one are drawn with the renderer's default material.
+#[derive(Clone, Debug, TypeUuid)]
+#[uuid = "5f0f6a3e-2c1b-4d8e-9a57-3b1f4e6c8d21"]
+pub struct Material {
+	/// Multiplied with the base color texture.
+	pub base_color: [f32; 4],
+	pub base_color_texture: Option<image::DynamicImage>,
+	/// Tangent space normals, stored linearly.
+	pub normal_texture: Option<image::DynamicImage>,
+	/// Roughness in the green channel, like glTF's metallic roughness textures.
+	pub roughness_texture: Option<image::DynamicImage>,
+	pub roughness: f32,
+	/// Used for every texture of the material.
+	pub sampler: SamplerSettings,
+}
+impl Default for Material {
+	fn default() -> Self {
+		Self {
+			base_color: [1.0, 1.0, 1.0, 1.0],
+			base_color_texture: None,
+			normal_texture: None,
+			roughness_texture: None,
+			roughness: 1.0,
+			sampler: SamplerSettings::default(),
+		}
+	}
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MaterialUniform {
+	base_color: [f32; 4],
+	roughness: f32,
+	_padding: [f32; 3],
+}
+impl MaterialUniform {
+	pub fn new(material: &Material) -> Self {
+		Self {
+			base_color: material.base_color,
+			roughness: material.roughness,
+			_padding: [0.0; 3],
+		}
+	}
+}
+
+/// Gpu copy of a `Material`. Missing maps are replaced by 1x1 textures that
+/// leave the result unchanged.
+pub struct MaterialBindGroup {
+	pub buffer: wgpu::Buffer,
+	pub textures: [Texture; 3],
+	pub bind_group: TextureBindGroup,
+}
+impl MaterialBindGroup {
+	pub fn new(
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		material: &Material,
+		label: Option<&str>,
+	) -> Result<Self> {
+		let base_color_texture = Self::texture_or_solid(
+			device,
+			queue,
+			material.base_color_texture.as_ref(),
+			[255, 255, 255, 255],
+			wgpu::TextureFormat::Rgba8UnormSrgb,
+			&material.sampler,
+		)?;
+		Self::with_base_color_texture(device, queue, material, base_color_texture, label)
+	}
+	/// Uses `base_color_texture` instead of `material.base_color_texture`.
+	pub fn with_base_color_texture(
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		material: &Material,
+		base_color_texture: Texture,
+		label: Option<&str>,
+	) -> Result<Self> {
+		let normal_texture = Self::texture_or_solid(
+			device,
+			queue,
+			material.normal_texture.as_ref(),
+			[128, 128, 255, 255],
+			wgpu::TextureFormat::Rgba8Unorm,
+			&material.sampler,
+		)?;
+		let roughness_texture = Self::texture_or_solid(
+			device,
+			queue,
+			material.roughness_texture.as_ref(),
+			[255, 255, 255, 255],
+			wgpu::TextureFormat::Rgba8Unorm,
+			&material.sampler,
+		)?;
+
+		let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("material buffer"),
+			size: std::mem::size_of::<MaterialUniform>() as wgpu::BufferAddress,
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+		queue.write_buffer(
+			&buffer,
+			0,
+			bytemuck::cast_slice(&[MaterialUniform::new(material)]),
+		);
+
+		let bind_group = TextureBindGroup::with_uniform(
+			device,
+			label,
+			&[&base_color_texture, &normal_texture, &roughness_texture],
+			&buffer,
+		);
+
+		Ok(Self {
+			buffer,
+			textures: [base_color_texture, normal_texture, roughness_texture],
+			bind_group,
+		})
+	}
+	pub fn create_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+		TextureBindGroup::create_layout_with_uniform(device, 3)
+	}
+	/// Uploads `img`, or a 1x1 texture of `solid` when there is none.
+	fn texture_or_solid(
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		img: Option<&image::DynamicImage>,
+		solid: [u8; 4],
+		format: wgpu::TextureFormat,
+		sampler: &SamplerSettings,
+	) -> Result<Texture> {
+		match img {
+			Some(img) => Texture::from_image_with(device, queue, img, None, format, sampler),
+			None => {
+				let img = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+					1,
+					1,
+					image::Rgba(solid),
+				));
+				Texture::from_image_with(device, queue, &img, None, format, sampler)
+			}
+		}
+	}
+}
+
+/// `MaterialBindGroup`s of every loaded `Material`, kept up to date by the
+/// renderer plugin.
+#[derive(Default)]
+pub struct GpuMaterials {
+	pub bind_groups: HashMap<HandleId, MaterialBindGroup>,
+}
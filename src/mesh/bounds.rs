@@ -0,0 +1,171 @@
+// File: mesh/bounds.rs
+
+use bevy::math::{Mat4, Vec3};
+
+/// Axis aligned bounding box.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+	pub min: Vec3,
+	pub max: Vec3,
+}
+impl Aabb {
+	/// `None` without points.
+	pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Option<Self> {
+		let mut points = points.into_iter();
+		let first = points.next()?;
+		Some(points.fold(Self::new(first, first), |aabb, point| {
+			Self::new(aabb.min.min(point), aabb.max.max(point))
+		}))
+	}
+	pub fn new(min: Vec3, max: Vec3) -> Self {
+		Self { min, max }
+	}
+	pub fn center(&self) -> Vec3 {
+		(self.min + self.max) * 0.5
+	}
+	pub fn half_extents(&self) -> Vec3 {
+		(self.max - self.min) * 0.5
+	}
+	/// The box around this one after `matrix` is applied.
+	pub fn transformed(&self, matrix: &Mat4) -> Self {
+		let center = matrix.transform_point3(self.center());
+		let half_extents = self.half_extents();
+		let half_extents = matrix.x_axis.truncate().abs() * half_extents.x
+			+ matrix.y_axis.truncate().abs() * half_extents.y
+			+ matrix.z_axis.truncate().abs() * half_extents.z;
+		Self::new(center - half_extents, center + half_extents)
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BoundingSphere {
+	pub center: Vec3,
+	pub radius: f32,
+}
+impl BoundingSphere {
+	/// The sphere around `aabb`'s center reaching the farthest point.
+	pub fn from_points(aabb: &Aabb, points: impl IntoIterator<Item = Vec3>) -> Self {
+		let center = aabb.center();
+		let radius_squared = points
+			.into_iter()
+			.map(|point| point.distance_squared(center))
+			.fold(0.0, f32::max);
+		Self {
+			center,
+			radius: radius_squared.sqrt(),
+		}
+	}
+	/// The sphere around this one after `matrix` is applied, scaled by the
+	/// matrix's largest axis scale.
+	pub fn transformed(&self, matrix: &Mat4) -> Self {
+		let scale = matrix
+			.x_axis
+			.truncate()
+			.length()
+			.max(matrix.y_axis.truncate().length())
+			.max(matrix.z_axis.truncate().length());
+		Self {
+			center: matrix.transform_point3(self.center),
+			radius: self.radius * scale,
+		}
+	}
+}
+
+/// Local bounds of a mesh, used for frustum culling.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MeshBounds {
+	pub aabb: Aabb,
+	pub sphere: BoundingSphere,
+}
+impl MeshBounds {
+	/// `None` without points.
+	pub fn from_points(points: &[Vec3]) -> Option<Self> {
+		let aabb = Aabb::from_points(points.iter().copied())?;
+		let sphere = BoundingSphere::from_points(&aabb, points.iter().copied());
+		Some(Self { aabb, sphere })
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use bevy::math::Quat;
+
+	fn corners(aabb: &Aabb) -> Vec<Vec3> {
+		(0..8)
+			.map(|i| {
+				Vec3::select(
+					bevy::math::BVec3::new(i & 1 != 0, i & 2 != 0, i & 4 != 0),
+					aabb.max,
+					aabb.min,
+				)
+			})
+			.collect()
+	}
+	fn matrices() -> Vec<Mat4> {
+		vec![
+			Mat4::IDENTITY,
+			Mat4::from_translation(Vec3::new(1.0, -2.0, 3.0)),
+			Mat4::from_rotation_y(0.7),
+			Mat4::from_scale_rotation_translation(
+				Vec3::new(2.0, 0.5, 3.0),
+				Quat::from_euler(bevy::math::EulerRot::XYZ, 0.3, -1.1, 2.0),
+				Vec3::new(-4.0, 0.0, 1.0),
+			),
+			Mat4::from_scale(Vec3::new(-1.0, 1.0, 1.0)),
+		]
+	}
+
+	#[test]
+	fn empty_points_have_no_bounds() {
+		assert_eq!(Aabb::from_points(vec![]), None);
+		assert_eq!(MeshBounds::from_points(&[]), None);
+	}
+
+	#[test]
+	fn bounds_enclose_their_points() {
+		let points = [
+			Vec3::new(1.0, 2.0, 3.0),
+			Vec3::new(-1.0, 0.5, 2.0),
+			Vec3::new(0.0, -3.0, 4.0),
+		];
+		let bounds = MeshBounds::from_points(&points).unwrap();
+		assert_eq!(bounds.aabb.min, Vec3::new(-1.0, -3.0, 2.0));
+		assert_eq!(bounds.aabb.max, Vec3::new(1.0, 2.0, 4.0));
+		assert_eq!(bounds.sphere.center, Vec3::new(0.0, -0.5, 3.0));
+		for point in points {
+			assert!(point.distance(bounds.sphere.center) <= bounds.sphere.radius + 1e-5);
+		}
+	}
+
+	#[test]
+	fn rotated_box_is_enclosed() {
+		let aabb = Aabb::new(Vec3::splat(-1.0), Vec3::splat(1.0));
+		let rotated = aabb.transformed(&Mat4::from_rotation_y(std::f32::consts::FRAC_PI_4));
+		let diagonal = 2f32.sqrt();
+		assert!(rotated
+			.max
+			.abs_diff_eq(Vec3::new(diagonal, 1.0, diagonal), 1e-5));
+		assert!(rotated.min.abs_diff_eq(-rotated.max, 1e-5));
+	}
+
+	#[test]
+	fn transformed_bounds_stay_enclosing() {
+		let aabb = Aabb::new(Vec3::new(-1.0, 0.0, 2.0), Vec3::new(0.5, 3.0, 2.5));
+		let points = corners(&aabb);
+		let sphere = BoundingSphere::from_points(&aabb, points.iter().copied());
+		for matrix in matrices() {
+			let transformed_aabb = aabb.transformed(&matrix);
+			let transformed_sphere = sphere.transformed(&matrix);
+			for point in &points {
+				let point = matrix.transform_point3(*point);
+				let epsilon = Vec3::splat(1e-4);
+				assert!(point.cmpge(transformed_aabb.min - epsilon).all());
+				assert!(point.cmple(transformed_aabb.max + epsilon).all());
+				let distance = point.distance(transformed_sphere.center);
+				assert!(distance <= transformed_sphere.radius + 1e-4);
+			}
+		}
+	}
+}
@@ -0,0 +1,550 @@
+// File: mesh/cache.rs
+
+//! Binary mesh cache, so big generated or imported meshes load without redoing
+//! the work. All numbers are little endian and every section starts 4 byte
+//! aligned:
+//!
+//! - magic `WMSH`, format version, length in bytes of everything after the
+//!   checksum, FNV-1a checksum of those bytes
+//! - the `Vertex` layout: attribute count, then semantic, format and offset of
+//!   each attribute, then the stride
+//! - index size in bytes, 2 or 4
+//! - whether there are bounds, then the box's min and max and the sphere's
+//!   center and radius of the first level
+//! - level count, then the vertex and index count of each level
+//! - the vertices and indices of each level, indices padded to 4 bytes
+
+use super::{Aabb, BoundingSphere, Mesh, MeshBounds, MeshGenerator};
+use crate::vertex::Vertex;
+
+use bevy::math::Vec3;
+
+use std::borrow::Cow;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Bump when the format changes, older caches are regenerated.
+pub const MESH_CACHE_VERSION: u32 = 2;
+const MAGIC: [u8; 4] = *b"WMSH";
+
+#[derive(Debug)]
+pub enum MeshCacheError {
+	Io {
+		path: PathBuf,
+		source: std::io::Error,
+	},
+	/// The data does not start with the cache's magic bytes.
+	NotACache,
+	Version {
+		found: u32,
+	},
+	Checksum {
+		expected: u32,
+		found: u32,
+	},
+	/// Written for a different `Vertex` layout.
+	Layout,
+	/// The data ends before the header says it does.
+	Truncated,
+}
+impl fmt::Display for MeshCacheError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			MeshCacheError::Io { path, source } => {
+				write!(f, "failed to access {}: {}", path.display(), source)
+			}
+			MeshCacheError::NotACache => write!(f, "not a mesh cache"),
+			MeshCacheError::Version { found } => write!(
+				f,
+				"mesh cache version {} is not the supported version {}",
+				found, MESH_CACHE_VERSION
+			),
+			MeshCacheError::Checksum { expected, found } => write!(
+				f,
+				"mesh cache checksum {:08x} does not match its contents {:08x}",
+				expected, found
+			),
+			MeshCacheError::Layout => write!(f, "mesh cache has a different vertex layout"),
+			MeshCacheError::Truncated => write!(f, "mesh cache is truncated"),
+		}
+	}
+}
+impl std::error::Error for MeshCacheError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			MeshCacheError::Io { source, .. } => Some(source),
+			_ => None,
+		}
+	}
+}
+
+/// One level of detail read from a cache. The slices borrow the cache's bytes
+/// when they are aligned and the host is little endian, 16 bit indices are
+/// always widened into a copy.
+#[derive(Clone, Debug)]
+pub struct CachedLevel<'a> {
+	pub vertices: Cow<'a, [Vertex]>,
+	pub indices: Cow<'a, [u32]>,
+}
+impl CachedLevel<'_> {
+	pub fn to_generator(&self) -> MeshGenerator {
+		MeshGenerator {
+			vertices: self.vertices.to_vec(),
+			indices: self.indices.to_vec(),
+		}
+	}
+	/// Uploads the level straight from the cached data.
+	pub fn build(&self, device: &wgpu::Device, label: Option<&str>) -> Mesh {
+		Mesh::new(
+			device,
+			label,
+			&self.vertices,
+			&self.indices,
+			self.indices.len() as u32,
+		)
+	}
+}
+
+/// A parsed cache, from the most to the least detailed level.
+#[derive(Clone, Debug)]
+pub struct MeshCache<'a> {
+	/// Bounds of the first level.
+	pub bounds: Option<MeshBounds>,
+	pub levels: Vec<CachedLevel<'a>>,
+}
+impl<'a> MeshCache<'a> {
+	/// Checks the header and checksum and casts the levels out of `bytes`.
+	pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, MeshCacheError> {
+		let mut reader = Reader { bytes, offset: 0 };
+		if reader.take(4)? != MAGIC {
+			return Err(MeshCacheError::NotACache);
+		}
+		let version = reader.u32()?;
+		if version != MESH_CACHE_VERSION {
+			return Err(MeshCacheError::Version { found: version });
+		}
+		let body_length = reader.u32()? as usize;
+		let expected = reader.u32()?;
+		let end = reader
+			.offset
+			.checked_add(body_length)
+			.filter(|end| *end <= bytes.len())
+			.ok_or(MeshCacheError::Truncated)?;
+		reader.bytes = &bytes[..end];
+		let found = checksum(&bytes[reader.offset..end]);
+		if expected != found {
+			return Err(MeshCacheError::Checksum { expected, found });
+		}
+
+		if reader.words(layout_words().len())? != layout_words() {
+			return Err(MeshCacheError::Layout);
+		}
+		let index_size = reader.u32()? as usize;
+		if index_size != 2 && index_size != 4 {
+			return Err(MeshCacheError::Layout);
+		}
+		let has_bounds = reader.u32()? != 0;
+		let mut floats = [0.0; 10];
+		for float in &mut floats {
+			*float = f32::from_bits(reader.u32()?);
+		}
+		let bounds = if has_bounds {
+			Some(MeshBounds {
+				aabb: Aabb::new(
+					Vec3::new(floats[0], floats[1], floats[2]),
+					Vec3::new(floats[3], floats[4], floats[5]),
+				),
+				sphere: BoundingSphere {
+					center: Vec3::new(floats[6], floats[7], floats[8]),
+					radius: floats[9],
+				},
+			})
+		} else {
+			None
+		};
+
+		let level_count = reader.u32()? as usize;
+		let counts = reader.words(level_count * 2)?;
+		let mut levels = Vec::with_capacity(level_count);
+		for count in counts.chunks_exact(2) {
+			let (vertex_count, index_count) = (count[0] as usize, count[1] as usize);
+			let vertex_bytes = vertex_count
+				.checked_mul(std::mem::size_of::<Vertex>())
+				.ok_or(MeshCacheError::Truncated)?;
+			let vertices = read_words(reader.take(vertex_bytes)?);
+			let index_bytes = index_count
+				.checked_mul(index_size)
+				.ok_or(MeshCacheError::Truncated)?;
+			let index_data = reader.take(index_bytes)?;
+			reader.take((4 - index_bytes % 4) % 4)?;
+			let indices = if index_size == 2 {
+				Cow::Owned(
+					index_data
+						.chunks_exact(2)
+						.map(|index| u16::from_le_bytes([index[0], index[1]]) as u32)
+						.collect(),
+				)
+			} else {
+				read_words(index_data)
+			};
+			levels.push(CachedLevel { vertices, indices });
+		}
+		Ok(Self { bounds, levels })
+	}
+}
+
+impl MeshGenerator {
+	/// Serializes `levels`, from the most to the least detailed, into the cache
+	/// format. Indices are stored as 16 bit when every level allows it.
+	pub fn to_cache_bytes(levels: &[MeshGenerator]) -> Vec<u8> {
+		let vertex_count = levels
+			.iter()
+			.map(|level| level.vertices.len())
+			.max()
+			.unwrap_or(0);
+		let index_size = match Mesh::index_format(vertex_count) {
+			wgpu::IndexFormat::Uint16 => 2,
+			wgpu::IndexFormat::Uint32 => 4,
+		};
+		let bounds = levels.first().and_then(MeshGenerator::bounds);
+
+		let mut body = vec![];
+		push_words(&mut body, &layout_words());
+		push_words(&mut body, &[index_size, bounds.is_some() as u32]);
+		let (aabb, sphere) = match bounds {
+			Some(bounds) => (bounds.aabb, bounds.sphere),
+			None => (
+				Aabb::new(Vec3::ZERO, Vec3::ZERO),
+				BoundingSphere {
+					center: Vec3::ZERO,
+					radius: 0.0,
+				},
+			),
+		};
+		let floats = [
+			aabb.min.to_array(),
+			aabb.max.to_array(),
+			sphere.center.to_array(),
+		]
+		.concat();
+		push_words(&mut body, bytemuck::cast_slice(&floats));
+		push_words(&mut body, &[sphere.radius.to_bits(), levels.len() as u32]);
+		for level in levels {
+			push_words(
+				&mut body,
+				&[level.vertices.len() as u32, level.indices.len() as u32],
+			);
+		}
+		for level in levels {
+			push_words(&mut body, bytemuck::cast_slice(&level.vertices));
+			if index_size == 2 {
+				for index in &level.indices {
+					body.extend_from_slice(&(*index as u16).to_le_bytes());
+				}
+				body.resize(body.len() + (level.indices.len() % 2) * 2, 0);
+			} else {
+				push_words(&mut body, &level.indices);
+			}
+		}
+
+		let mut bytes = Vec::with_capacity(body.len() + 16);
+		bytes.extend_from_slice(&MAGIC);
+		bytes.extend_from_slice(&MESH_CACHE_VERSION.to_le_bytes());
+		bytes.extend_from_slice(&(body.len() as u32).to_le_bytes());
+		bytes.extend_from_slice(&checksum(&body).to_le_bytes());
+		bytes.extend_from_slice(&body);
+		bytes
+	}
+	pub fn write_cache(
+		levels: &[MeshGenerator],
+		path: impl AsRef<Path>,
+	) -> Result<(), MeshCacheError> {
+		let path = path.as_ref();
+		std::fs::write(path, Self::to_cache_bytes(levels)).map_err(|source| MeshCacheError::Io {
+			path: path.to_path_buf(),
+			source,
+		})
+	}
+	pub fn read_cache(path: impl AsRef<Path>) -> Result<Vec<MeshGenerator>, MeshCacheError> {
+		let path = path.as_ref();
+		let bytes = std::fs::read(path).map_err(|source| MeshCacheError::Io {
+			path: path.to_path_buf(),
+			source,
+		})?;
+		let cache = MeshCache::from_bytes(&bytes)?;
+		Ok(cache.levels.iter().map(CachedLevel::to_generator).collect())
+	}
+	/// Reads the levels cached at `path`. When the file is missing, from another
+	/// version or layout, or corrupt, the levels are generated again and the
+	/// cache is rewritten.
+	pub fn read_cache_or_else(
+		path: impl AsRef<Path>,
+		generate: impl FnOnce() -> Vec<MeshGenerator>,
+	) -> Vec<MeshGenerator> {
+		let path = path.as_ref();
+		match Self::read_cache(path) {
+			Ok(levels) => return levels,
+			Err(MeshCacheError::Io { source, .. })
+				if source.kind() == std::io::ErrorKind::NotFound => {}
+			Err(err) => log::warn!("regenerating {}: {}", path.display(), err),
+		}
+		let levels = generate();
+		if let Err(err) = Self::write_cache(&levels, path) {
+			log::warn!("{}", err);
+		}
+		levels
+	}
+}
+
+/// The attributes and stride of `Vertex::layout()`, as stored in the header.
+fn layout_words() -> Vec<u32> {
+	let layout = Vertex::layout();
+	let stream = &layout.streams[0];
+	let mut words = vec![stream.attributes.len() as u32];
+	for attribute in &stream.attributes {
+		words.extend([
+			attribute.semantic as u32,
+			attribute.format as u32,
+			attribute.offset as u32,
+		]);
+	}
+	words.push(stream.stride as u32);
+	words
+}
+
+fn push_words(bytes: &mut Vec<u8>, words: &[u32]) {
+	for word in words {
+		bytes.extend_from_slice(&word.to_le_bytes());
+	}
+}
+
+/// Casts little endian 4 byte words into `T`s without copying when possible.
+fn read_words<T: bytemuck::Pod>(bytes: &[u8]) -> Cow<'_, [T]> {
+	if cfg!(target_endian = "little") {
+		if let Ok(values) = bytemuck::try_cast_slice(bytes) {
+			return Cow::Borrowed(values);
+		}
+	}
+	// unaligned, or a big endian host
+	let words: Vec<u32> = bytes
+		.chunks_exact(4)
+		.map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+		.collect();
+	Cow::Owned(
+		bytemuck::cast_slice::<u32, u8>(&words)
+			.chunks_exact(std::mem::size_of::<T>())
+			.map(bytemuck::pod_read_unaligned)
+			.collect(),
+	)
+}
+
+/// 32 bit FNV-1a.
+fn checksum(bytes: &[u8]) -> u32 {
+	bytes.iter().fold(0x811c_9dc5, |hash, byte| {
+		(hash ^ *byte as u32).wrapping_mul(0x0100_0193)
+	})
+}
+
+struct Reader<'a> {
+	bytes: &'a [u8],
+	offset: usize,
+}
+impl<'a> Reader<'a> {
+	fn take(&mut self, len: usize) -> Result<&'a [u8], MeshCacheError> {
+		let end = self
+			.offset
+			.checked_add(len)
+			.filter(|end| *end <= self.bytes.len())
+			.ok_or(MeshCacheError::Truncated)?;
+		let bytes = &self.bytes[self.offset..end];
+		self.offset = end;
+		Ok(bytes)
+	}
+	fn u32(&mut self) -> Result<u32, MeshCacheError> {
+		let bytes = self.take(4)?;
+		Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+	}
+	fn words(&mut self, count: usize) -> Result<Vec<u32>, MeshCacheError> {
+		(0..count).map(|_| self.u32()).collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::mesh::SphereUvs;
+
+	/// Bytes before the body: magic, version, body length and checksum.
+	const HEADER_SIZE: usize = 16;
+
+	fn sphere() -> MeshGenerator {
+		let mut generator = MeshGenerator::default();
+		generator.icosphere(1.0, 2, SphereUvs::Equirectangular);
+		generator
+	}
+	fn assert_same(read: &[MeshGenerator], written: &[MeshGenerator]) {
+		assert_eq!(read.len(), written.len());
+		for (read, written) in read.iter().zip(written) {
+			assert_eq!(
+				bytemuck::cast_slice::<Vertex, u8>(&read.vertices),
+				bytemuck::cast_slice::<Vertex, u8>(&written.vertices)
+			);
+			assert_eq!(read.indices, written.indices);
+		}
+	}
+	fn round_trip(levels: &[MeshGenerator]) -> Vec<MeshGenerator> {
+		let bytes = MeshGenerator::to_cache_bytes(levels);
+		let cache = MeshCache::from_bytes(&bytes).unwrap();
+		assert_eq!(cache.bounds, levels.first().and_then(MeshGenerator::bounds));
+		cache.levels.iter().map(CachedLevel::to_generator).collect()
+	}
+	fn index_size(bytes: &[u8]) -> u32 {
+		let at = HEADER_SIZE + layout_words().len() * 4;
+		u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+	}
+	/// Changes a byte of the body and fixes the checksum up.
+	fn rewrite_body(bytes: &mut [u8], at: usize, value: u8) {
+		bytes[HEADER_SIZE + at] = value;
+		let checksum = checksum(&bytes[HEADER_SIZE..]);
+		bytes[12..16].copy_from_slice(&checksum.to_le_bytes());
+	}
+
+	#[test]
+	fn one_level_round_trip() {
+		let levels = vec![sphere()];
+		assert_same(&round_trip(&levels), &levels);
+	}
+
+	#[test]
+	fn two_level_round_trip() {
+		let levels = vec![sphere(), sphere().simplify(0.25).clone()];
+		assert_same(&round_trip(&levels), &levels);
+	}
+
+	#[test]
+	fn empty_round_trip() {
+		let bytes = MeshGenerator::to_cache_bytes(&[]);
+		let cache = MeshCache::from_bytes(&bytes).unwrap();
+		assert_eq!(cache.bounds, None);
+		assert!(cache.levels.is_empty());
+	}
+
+	#[test]
+	fn index_size_follows_vertex_count() {
+		let small = vec![sphere()];
+		assert_eq!(index_size(&MeshGenerator::to_cache_bytes(&small)), 2);
+
+		// too many vertices for 16 bit indices, in one of the levels
+		let mut big = MeshGenerator::default();
+		big.plane(1.0, 1.0, 256, 256);
+		let levels = vec![big, sphere()];
+		let bytes = MeshGenerator::to_cache_bytes(&levels);
+		assert_eq!(index_size(&bytes), 4);
+		assert_same(&round_trip(&levels), &levels);
+	}
+
+	#[test]
+	fn odd_index_counts_are_padded() {
+		let mut triangle = sphere();
+		triangle.indices.truncate(3);
+		let levels = vec![triangle, sphere()];
+		let bytes = MeshGenerator::to_cache_bytes(&levels);
+		assert_eq!(index_size(&bytes), 2);
+		assert_eq!(bytes.len() % 4, 0);
+		assert_same(&round_trip(&levels), &levels);
+	}
+
+	#[test]
+	fn flipped_body_byte_fails_the_checksum() {
+		let mut bytes = MeshGenerator::to_cache_bytes(&[sphere()]);
+		let at = bytes.len() - 100;
+		bytes[at] ^= 1;
+		assert!(matches!(
+			MeshCache::from_bytes(&bytes),
+			Err(MeshCacheError::Checksum { .. })
+		));
+	}
+
+	#[test]
+	fn other_version_is_rejected() {
+		let mut bytes = MeshGenerator::to_cache_bytes(&[sphere()]);
+		bytes[4..8].copy_from_slice(&(MESH_CACHE_VERSION + 1).to_le_bytes());
+		assert!(matches!(
+			MeshCache::from_bytes(&bytes),
+			Err(MeshCacheError::Version { found }) if found == MESH_CACHE_VERSION + 1
+		));
+	}
+
+	#[test]
+	fn other_data_is_rejected() {
+		let mut bytes = MeshGenerator::to_cache_bytes(&[sphere()]);
+		bytes[0] = b'X';
+		assert!(matches!(
+			MeshCache::from_bytes(&bytes),
+			Err(MeshCacheError::NotACache)
+		));
+
+		// the stride is the last layout word
+		let mut bytes = MeshGenerator::to_cache_bytes(&[sphere()]);
+		rewrite_body(&mut bytes, layout_words().len() * 4 - 4, 0);
+		assert!(matches!(
+			MeshCache::from_bytes(&bytes),
+			Err(MeshCacheError::Layout)
+		));
+	}
+
+	#[test]
+	fn truncated_data_is_rejected() {
+		let bytes = MeshGenerator::to_cache_bytes(&[sphere()]);
+		for len in [2, 10, HEADER_SIZE + 8, bytes.len() - 4] {
+			assert!(
+				matches!(
+					MeshCache::from_bytes(&bytes[..len]),
+					Err(MeshCacheError::Truncated)
+				),
+				"{} bytes",
+				len
+			);
+		}
+
+		// a level count larger than the data, with a matching checksum
+		let mut bytes = MeshGenerator::to_cache_bytes(&[sphere()]);
+		let level_count = layout_words().len() * 4 + 12 * 4;
+		rewrite_body(&mut bytes, level_count, 9);
+		assert!(matches!(
+			MeshCache::from_bytes(&bytes),
+			Err(MeshCacheError::Truncated)
+		));
+	}
+
+	#[test]
+	fn read_cache_or_else_regenerates() {
+		let directory = std::env::temp_dir().join("wgpu_dev_mesh_cache");
+		std::fs::create_dir_all(&directory).unwrap();
+		let path = directory.join("sphere.wmsh");
+		let _ = std::fs::remove_file(&path);
+
+		// missing, generated and written
+		let levels = MeshGenerator::read_cache_or_else(&path, || vec![sphere()]);
+		assert_same(&levels, &[sphere()]);
+		assert!(path.exists());
+
+		// read back without generating
+		let levels = MeshGenerator::read_cache_or_else(&path, || panic!("cache not used"));
+		assert_same(&levels, &[sphere()]);
+
+		// corrupt, generated again and rewritten
+		let mut bytes = std::fs::read(&path).unwrap();
+		let at = bytes.len() - 1;
+		bytes[at] ^= 1;
+		std::fs::write(&path, bytes).unwrap();
+		let mut generated = false;
+		let levels = MeshGenerator::read_cache_or_else(&path, || {
+			generated = true;
+			vec![sphere()]
+		});
+		assert!(generated);
+		assert_same(&levels, &[sphere()]);
+		assert_same(&MeshGenerator::read_cache(&path).unwrap(), &[sphere()]);
+	}
+}
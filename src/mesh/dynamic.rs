@@ -0,0 +1,338 @@
+// File: mesh/dynamic.rs
+
+use super::{Mesh, MeshBounds};
+use crate::vertex::Vertex;
+use crate::vertex_layout::VertexLayout;
+
+use bevy::math::Vec3;
+
+/// Dynamic meshes keep spare room in their buffers and update them with
+/// `queue.write_buffer`, for geometry that changes every frame like water or
+/// deformations. Buffers that run out of room grow to twice their size, keeping
+/// their contents. Indices are always 32 bit so a mesh can grow past 65536
+/// vertices.
+impl Mesh {
+	/// An empty dynamic mesh of `Vertex`s with room for `vertex_capacity`
+	/// vertices and `index_capacity` indices.
+	pub fn new_dynamic(
+		device: &wgpu::Device,
+		label: Option<&str>,
+		vertex_capacity: usize,
+		index_capacity: usize,
+	) -> Self {
+		Self::with_layout_dynamic(
+			device,
+			label,
+			Vertex::layout(),
+			vertex_capacity,
+			index_capacity,
+		)
+	}
+	/// An empty dynamic mesh with any vertex layout, filled with
+	/// `write_vertex_stream` and `write_indices`.
+	pub fn with_layout_dynamic(
+		device: &wgpu::Device,
+		label: Option<&str>,
+		layout: VertexLayout,
+		vertex_capacity: usize,
+		index_capacity: usize,
+	) -> Self {
+		let vertex_capacity = vertex_capacity.max(1);
+		let index_capacity = index_capacity.max(1);
+		let (vertex_buffer_label, index_buffer_label) = match label {
+			Some(l) => (
+				Some(format!("{} vertex buffer label", l)),
+				Some(format!("{} index buffer label", l)),
+			),
+			None => (None, None),
+		};
+
+		let vertex_buffers = layout
+			.streams
+			.iter()
+			.map(|stream| {
+				create_dynamic_buffer(
+					device,
+					vertex_buffer_label.as_deref(),
+					stream.stride * vertex_capacity as wgpu::BufferAddress,
+					wgpu::BufferUsages::VERTEX,
+				)
+			})
+			.collect();
+		let index_buffer = create_dynamic_buffer(
+			device,
+			index_buffer_label.as_deref(),
+			INDEX_SIZE * index_capacity as wgpu::BufferAddress,
+			wgpu::BufferUsages::INDEX,
+		);
+
+		Self {
+			layout,
+			vertex_buffer_label,
+			vertex_buffers,
+			index_buffer_label,
+			index_buffer,
+			index_format: wgpu::IndexFormat::Uint32,
+			num_indices: 0,
+			bounds: None,
+			vertex_capacity,
+			index_capacity,
+			dynamic: true,
+		}
+	}
+
+	/// Overwrites the vertices from `first_vertex` on, growing the buffer when
+	/// they do not fit. The mesh's layout has to be `Vertex::layout()`.
+	pub fn write_vertices(
+		&mut self,
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		first_vertex: usize,
+		vertices: &[Vertex],
+	) {
+		debug_assert_eq!(
+			self.layout,
+			Vertex::layout(),
+			"the mesh does not store `Vertex`s"
+		);
+		self.write_vertex_stream(
+			device,
+			queue,
+			0,
+			first_vertex,
+			bytemuck::cast_slice(vertices),
+		);
+	}
+	/// Overwrites the raw vertices of one stream of the layout from
+	/// `first_vertex` on. All streams grow together. Bounds only ever grow with
+	/// written positions, `set_geometry` recomputes them.
+	pub fn write_vertex_stream(
+		&mut self,
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		stream: usize,
+		first_vertex: usize,
+		bytes: &[u8],
+	) {
+		assert!(self.dynamic, "only dynamic meshes can be written");
+		let stride = self.layout.streams[stream].stride;
+		let count = bytes.len() / stride as usize;
+		let range = element_range(first_vertex, count, stride);
+		if let Some(capacity) = grown_capacity(self.vertex_capacity, first_vertex + count) {
+			for (buffer, layout) in self.vertex_buffers.iter_mut().zip(&self.layout.streams) {
+				grow_buffer(
+					device,
+					queue,
+					buffer,
+					self.vertex_buffer_label.as_deref(),
+					wgpu::BufferUsages::VERTEX,
+					layout.stride * self.vertex_capacity as wgpu::BufferAddress,
+					layout.stride * capacity as wgpu::BufferAddress,
+				);
+			}
+			self.vertex_capacity = capacity;
+		}
+		queue.write_buffer(&self.vertex_buffers[stream], range.start, bytes);
+
+		let mut streams = vec![&[][..]; self.layout.streams.len()];
+		streams[stream] = bytes;
+		if let Some(positions) = self.layout.read_positions(&streams) {
+			let mut positions: Vec<Vec3> = positions.into_iter().map(Vec3::from).collect();
+			if let Some(bounds) = self.bounds {
+				positions.extend([bounds.aabb.min, bounds.aabb.max]);
+			}
+			if let Some(bounds) = MeshBounds::from_points(&positions) {
+				// the sphere of the merged box is not tight, but covers both
+				self.bounds = Some(bounds);
+			}
+		}
+	}
+	/// Overwrites the indices from `first_index` on, growing the buffer when they
+	/// do not fit. Draws cover at least up to the last written index, use
+	/// `num_indices` to draw fewer.
+	pub fn write_indices(
+		&mut self,
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		first_index: usize,
+		indices: &[u32],
+	) {
+		assert!(self.dynamic, "only dynamic meshes can be written");
+		let index_count = first_index + indices.len();
+		if let Some(capacity) = grown_capacity(self.index_capacity, index_count) {
+			grow_buffer(
+				device,
+				queue,
+				&mut self.index_buffer,
+				self.index_buffer_label.as_deref(),
+				wgpu::BufferUsages::INDEX,
+				INDEX_SIZE * self.index_capacity as wgpu::BufferAddress,
+				INDEX_SIZE * capacity as wgpu::BufferAddress,
+			);
+			self.index_capacity = capacity;
+		}
+		queue.write_buffer(
+			&self.index_buffer,
+			element_range(first_index, indices.len(), INDEX_SIZE).start,
+			bytemuck::cast_slice(indices),
+		);
+		self.num_indices = self.num_indices.max(index_count as u32);
+	}
+	/// Replaces all geometry, only reallocating when it outgrows the buffers.
+	pub fn set_geometry(
+		&mut self,
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		vertices: &[Vertex],
+		indices: &[u32],
+	) {
+		self.bounds = None;
+		self.num_indices = 0;
+		self.write_vertices(device, queue, 0, vertices);
+		self.write_indices(device, queue, 0, indices);
+	}
+}
+
+const INDEX_SIZE: wgpu::BufferAddress = std::mem::size_of::<u32>() as wgpu::BufferAddress;
+
+/// The capacity a buffer grows to so `needed` elements fit, at least twice the
+/// old one to keep repeated growth cheap. `None` when they already fit.
+fn grown_capacity(capacity: usize, needed: usize) -> Option<usize> {
+	if needed > capacity {
+		Some(needed.max(capacity * 2))
+	} else {
+		None
+	}
+}
+
+/// The bytes of `count` elements of `size` bytes, starting at element `first`.
+fn element_range(
+	first: usize,
+	count: usize,
+	size: wgpu::BufferAddress,
+) -> std::ops::Range<wgpu::BufferAddress> {
+	let start = first as wgpu::BufferAddress * size;
+	start..start + count as wgpu::BufferAddress * size
+}
+
+fn create_dynamic_buffer(
+	device: &wgpu::Device,
+	label: Option<&str>,
+	size: wgpu::BufferAddress,
+	usage: wgpu::BufferUsages,
+) -> wgpu::Buffer {
+	device.create_buffer(&wgpu::BufferDescriptor {
+		label,
+		size,
+		// COPY_SRC lets the contents move along when the buffer grows
+		usage: usage | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+		mapped_at_creation: false,
+	})
+}
+
+/// Replaces `buffer` by a bigger one holding the same contents.
+fn grow_buffer(
+	device: &wgpu::Device,
+	queue: &wgpu::Queue,
+	buffer: &mut wgpu::Buffer,
+	label: Option<&str>,
+	usage: wgpu::BufferUsages,
+	size: wgpu::BufferAddress,
+	new_size: wgpu::BufferAddress,
+) {
+	let grown = create_dynamic_buffer(device, label, new_size, usage);
+	let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+		label: Some("grow_buffer_encoder"),
+	});
+	encoder.copy_buffer_to_buffer(buffer, 0, &grown, 0, size);
+	queue.submit(std::iter::once(encoder.finish()));
+	*buffer = grown;
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::render_state::RenderState;
+	use crate::settings::RendererSettings;
+
+	#[test]
+	fn capacity_doubles_at_least() {
+		assert_eq!(grown_capacity(8, 8), None);
+		assert_eq!(grown_capacity(8, 3), None);
+		assert_eq!(grown_capacity(8, 9), Some(16));
+		// writes far past the end grow to exactly what they need
+		assert_eq!(grown_capacity(8, 40), Some(40));
+		assert_eq!(grown_capacity(1, 2), Some(2));
+	}
+
+	#[test]
+	fn element_ranges_are_in_bytes() {
+		let stride = std::mem::size_of::<Vertex>() as wgpu::BufferAddress;
+		assert_eq!(element_range(0, 3, stride), 0..3 * stride);
+		assert_eq!(element_range(5, 2, stride), 5 * stride..7 * stride);
+		assert_eq!(element_range(4, 6, INDEX_SIZE), 16..40);
+		assert_eq!(element_range(7, 0, INDEX_SIZE), 28..28);
+	}
+
+	fn read_buffer(renderer: &RenderState, buffer: &wgpu::Buffer, size: u64) -> Vec<u8> {
+		let staging = renderer.device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("dynamic_test_staging"),
+			size,
+			usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+			mapped_at_creation: false,
+		});
+		let mut encoder = renderer
+			.device
+			.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+		encoder.copy_buffer_to_buffer(buffer, 0, &staging, 0, size);
+		renderer.queue.submit(std::iter::once(encoder.finish()));
+
+		let slice = staging.slice(..);
+		let mapping = slice.map_async(wgpu::MapMode::Read);
+		renderer.device.poll(wgpu::Maintain::Wait);
+		pollster::block_on(mapping).unwrap();
+		let bytes = slice.get_mapped_range().to_vec();
+		bytes
+	}
+
+	#[test]
+	#[ignore = "needs an adapter"]
+	fn growing_keeps_the_contents() {
+		let renderer = pollster::block_on(RenderState::new_offscreen(
+			4,
+			4,
+			wgpu::TextureFormat::Rgba8UnormSrgb,
+			&RendererSettings::default(),
+		))
+		.unwrap();
+		let (device, queue) = (&renderer.device, &renderer.queue);
+
+		let vertex = |x: f32| Vertex {
+			position: [x, 0.0, 0.0],
+			..Default::default()
+		};
+		let mut mesh = Mesh::new_dynamic(device, Some("dynamic_test"), 2, 3);
+		mesh.write_vertices(device, queue, 0, &[vertex(0.0), vertex(1.0)]);
+		mesh.write_indices(device, queue, 0, &[0, 1, 0]);
+		// both writes start past the old capacity, so the first ones have to move
+		mesh.write_vertices(device, queue, 2, &[vertex(2.0), vertex(3.0), vertex(4.0)]);
+		mesh.write_indices(device, queue, 3, &[1, 2, 3, 4]);
+		assert_eq!((mesh.vertex_capacity, mesh.index_capacity), (5, 7));
+		assert_eq!(mesh.num_indices, 7);
+
+		let stride = std::mem::size_of::<Vertex>() as u64;
+		let bytes = read_buffer(&renderer, &mesh.vertex_buffers[0], 5 * stride);
+		let vertices: &[Vertex] = bytemuck::cast_slice(&bytes);
+		let xs: Vec<f32> = vertices.iter().map(|vertex| vertex.position[0]).collect();
+		assert_eq!(xs, [0.0, 1.0, 2.0, 3.0, 4.0]);
+
+		let bytes = read_buffer(&renderer, &mesh.index_buffer, 7 * INDEX_SIZE);
+		let indices: &[u32] = bytemuck::cast_slice(&bytes);
+		assert_eq!(indices, [0, 1, 0, 1, 2, 3, 4]);
+
+		let bounds = mesh.bounds.unwrap();
+		assert_eq!(bounds.aabb.min.x, 0.0);
+		assert_eq!(bounds.aabb.max.x, 4.0);
+	}
+}
@@ -0,0 +1,64 @@
+// File: mesh/lod.rs
+
+use super::{MeshData, MeshGenerator};
+
+use bevy::asset::{Assets, Handle};
+use bevy::ecs::component::Component;
+
+/// Levels of detail for a `ShouldDraw` entity, drawn instead of its `Mesh` or
+/// `Handle<MeshData>`. The renderer plugin picks a level every frame from the
+/// screen size of the first level's bounds, see `Camera::screen_size`.
+#[derive(Component, Clone, Debug)]
+pub struct Lod {
+	/// From the most to the least detailed.
+	pub levels: Vec<Handle<MeshData>>,
+	/// The smallest screen size each level is drawn at, decreasing. Smaller
+	/// sizes use the last level.
+	pub screen_sizes: Vec<f32>,
+	/// The level picked for the current frame.
+	pub current: usize,
+}
+impl Lod {
+	/// Panics without levels or unless there is one screen size per level.
+	pub fn new(levels: Vec<Handle<MeshData>>, screen_sizes: Vec<f32>) -> Self {
+		assert!(!levels.is_empty(), "a lod needs at least one level");
+		assert_eq!(
+			levels.len(),
+			screen_sizes.len(),
+			"every level needs a screen size"
+		);
+		Self {
+			levels,
+			screen_sizes,
+			current: 0,
+		}
+	}
+	/// `generator`, drawn down to `screen_size`, followed by its simplified
+	/// `lod_chain` given as `(ratio, screen_size)` pairs.
+	pub fn from_generator(
+		generator: &MeshGenerator,
+		screen_size: f32,
+		simplified: &[(f32, f32)],
+		mesh_assets: &mut Assets<MeshData>,
+	) -> Self {
+		let ratios: Vec<f32> = simplified.iter().map(|(ratio, _)| *ratio).collect();
+		let levels = std::iter::once(generator.clone())
+			.chain(generator.lod_chain(&ratios))
+			.map(|level| mesh_assets.add(MeshData::from(level)))
+			.collect();
+		let screen_sizes = std::iter::once(screen_size)
+			.chain(simplified.iter().map(|(_, screen_size)| *screen_size))
+			.collect();
+		Self::new(levels, screen_sizes)
+	}
+	/// The level for a mesh covering `screen_size` of the screen height.
+	pub fn select(&self, screen_size: f32) -> usize {
+		self.screen_sizes
+			.iter()
+			.position(|min_size| screen_size >= *min_size)
+			.unwrap_or(self.levels.len() - 1)
+	}
+	pub fn current_level(&self) -> &Handle<MeshData> {
+		&self.levels[self.current.min(self.levels.len() - 1)]
+	}
+}
@@ -0,0 +1,678 @@
+// File: mesh/mod.rs
+
+mod bounds;
+mod cache;
+mod dynamic;
+mod lod;
+mod optimize;
+mod shapes;
+mod simplify;
+mod sphere;
+mod terrain;
+mod validate;
+
+pub use bounds::{Aabb, BoundingSphere, MeshBounds};
+pub use cache::{CachedLevel, MeshCache, MeshCacheError, MESH_CACHE_VERSION};
+pub use lod::Lod;
+pub use optimize::{OptimizeSettings, OptimizeStats};
+pub use sphere::{CubeSphereMapping, SphereUvs};
+pub use terrain::{Heightmap, TerrainChunk, TerrainSettings};
+pub use validate::MeshReport;
+
+use crate::vertex::*;
+use crate::vertex_layout::VertexLayout;
+
+use wgpu::util::DeviceExt;
+
+use bevy::asset::HandleId;
+use bevy::ecs::component::Component;
+use bevy::math::{Vec2, Vec3};
+use bevy::reflect::TypeUuid;
+
+use core::f32::consts::PI;
+use std::collections::HashMap;
+use std::time::Instant;
+
+#[derive(Component)]
+pub struct ShouldDraw;
+
+#[derive(Component)]
+pub struct Mesh {
+	pub layout: VertexLayout,
+	pub vertex_buffer_label: Option<String>,
+	/// One buffer per stream of `layout`.
+	pub vertex_buffers: Vec<wgpu::Buffer>,
+
+	pub index_buffer_label: Option<String>,
+	pub index_buffer: wgpu::Buffer,
+	pub index_format: wgpu::IndexFormat,
+	pub num_indices: u32,
+
+	/// Local bounds for frustum culling, `None` when the positions could not be
+	/// read, the mesh is never culled then.
+	pub bounds: Option<MeshBounds>,
+
+	/// Vertices and indices the buffers have room for. Only dynamic meshes have
+	/// room to spare.
+	pub vertex_capacity: usize,
+	pub index_capacity: usize,
+	/// Created by `Mesh::new_dynamic`, the buffers can be written and grow.
+	pub dynamic: bool,
+}
+impl Mesh {
+	pub fn new(
+		device: &wgpu::Device,
+		label: Option<&str>,
+		vertices: &[Vertex],
+		indices: &[u32],
+		num_indices: u32,
+	) -> Self {
+		Self::with_layout(
+			device,
+			label,
+			Vertex::layout(),
+			&[bytemuck::cast_slice(vertices)],
+			&indices[..num_indices as usize],
+		)
+	}
+	/// Creates a mesh from raw vertex `streams`, one per stream of `layout`. The
+	/// renderer supplies defaults for shader inputs the layout lacks, so e.g. a
+	/// position only debug mesh draws white.
+	pub fn with_layout(
+		device: &wgpu::Device,
+		label: Option<&str>,
+		layout: VertexLayout,
+		streams: &[&[u8]],
+		indices: &[u32],
+	) -> Self {
+		assert_eq!(
+			streams.len(),
+			layout.streams.len(),
+			"one vertex buffer per stream is required"
+		);
+		let (vertex_buffer_label, index_buffer_label) = match label {
+			Some(l) => (
+				Some(format!("{} vertex buffer label", l)),
+				Some(format!("{} index buffer label", l)),
+			),
+			None => (None, None),
+		};
+
+		let vertex_buffers = streams
+			.iter()
+			.map(|contents| {
+				device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+					label: vertex_buffer_label.as_deref(),
+					contents,
+					usage: wgpu::BufferUsages::VERTEX,
+				})
+			})
+			.collect();
+
+		let vertex_count = match (streams.first(), layout.streams.first()) {
+			(Some(contents), Some(stream)) if stream.stride > 0 => {
+				contents.len() / stream.stride as usize
+			}
+			_ => 0,
+		};
+		let index_format = Self::index_format(vertex_count);
+		let indices_u16: Vec<u16>;
+		let contents = match index_format {
+			wgpu::IndexFormat::Uint16 => {
+				indices_u16 = indices.iter().map(|index| *index as u16).collect();
+				bytemuck::cast_slice(&indices_u16)
+			}
+			wgpu::IndexFormat::Uint32 => bytemuck::cast_slice(indices),
+		};
+		let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: index_buffer_label.as_deref(),
+			contents,
+			usage: wgpu::BufferUsages::INDEX,
+		});
+
+		let bounds = layout.read_positions(streams).and_then(|positions| {
+			let positions: Vec<Vec3> = positions.into_iter().map(Vec3::from).collect();
+			MeshBounds::from_points(&positions)
+		});
+
+		Self {
+			layout,
+			vertex_buffer_label,
+			vertex_buffers,
+			index_buffer_label,
+			index_buffer,
+			index_format,
+			num_indices: indices.len() as u32,
+			bounds,
+			vertex_capacity: vertex_count,
+			index_capacity: indices.len(),
+			dynamic: false,
+		}
+	}
+	/// 16 bit indices when they can address every vertex, 32 bit otherwise.
+	pub fn index_format(vertex_count: usize) -> wgpu::IndexFormat {
+		if vertex_count <= u16::MAX as usize + 1 {
+			wgpu::IndexFormat::Uint16
+		} else {
+			wgpu::IndexFormat::Uint32
+		}
+	}
+}
+
+/// Cpu side geometry, shared between entities through a `Handle<MeshData>`. The
+/// renderer plugin uploads it the first time a `ShouldDraw` entity uses it.
+#[derive(Clone, Debug, Default, TypeUuid)]
+#[uuid = "8a3c1d52-7e4f-4b0a-b6d9-2f5e8c7a1b43"]
+pub struct MeshData {
+	pub vertices: Vec<Vertex>,
+	pub indices: Vec<u32>,
+	/// Uploads a dynamic `Mesh`, so modifying the asset rewrites its buffers
+	/// instead of creating new ones.
+	pub dynamic: bool,
+}
+impl MeshData {
+	pub fn build(&self, device: &wgpu::Device, queue: &wgpu::Queue, label: Option<&str>) -> Mesh {
+		if self.dynamic {
+			let mut mesh =
+				Mesh::new_dynamic(device, label, self.vertices.len(), self.indices.len());
+			mesh.set_geometry(device, queue, &self.vertices, &self.indices);
+			mesh
+		} else {
+			validate::debug_validate(&self.vertices, &self.indices, label);
+			Mesh::new(
+				device,
+				label,
+				&self.vertices,
+				&self.indices,
+				self.indices.len() as u32,
+			)
+		}
+	}
+}
+impl From<MeshGenerator> for MeshData {
+	fn from(generator: MeshGenerator) -> Self {
+		Self {
+			vertices: generator.vertices,
+			indices: generator.indices,
+			dynamic: false,
+		}
+	}
+}
+
+/// Uploaded `MeshData` assets, kept up to date by the renderer plugin. Buffers
+/// are dropped together with their asset.
+#[derive(Default)]
+pub struct GpuMeshes {
+	pub meshes: HashMap<HandleId, Mesh>,
+}
+
+#[derive(Clone, Default)]
+/// Indices are always generated as `u32`, `build` uploads them as `u16` when
+/// the mesh is small enough.
+pub struct MeshGenerator {
+	pub vertices: Vec<Vertex>,
+	pub indices: Vec<u32>,
+}
+impl MeshGenerator {
+	fn add_vertex(&mut self, vertex: Vertex) {
+		self.vertices.push(vertex);
+	}
+	fn add_triangle(&mut self, a: u32, b: u32, c: u32) {
+		self.indices.extend_from_slice(&[a, b, c]);
+	}
+
+	fn clear(&mut self) {
+		self.vertices.clear();
+		self.indices.clear();
+	}
+	/// Uploads the geometry. The mesh's bounds are computed from the vertices.
+	/// Debug builds log what `validate` finds.
+	pub fn build(&mut self, device: &wgpu::Device, label: Option<&str>) -> Mesh {
+		validate::debug_validate(&self.vertices, &self.indices, label);
+		let num_indices = self.indices.len() as u32;
+		Mesh::new(
+			device,
+			label,
+			&self.vertices[..],
+			&self.indices[..],
+			num_indices,
+		)
+	}
+	/// Local bounds of the generated vertices, `None` without vertices.
+	pub fn bounds(&self) -> Option<MeshBounds> {
+		let positions: Vec<Vec3> = self
+			.vertices
+			.iter()
+			.map(|vertex| Vec3::from(vertex.position))
+			.collect();
+		MeshBounds::from_points(&positions)
+	}
+	/// Copies the generated geometry into a `MeshData` asset.
+	pub fn to_mesh_data(&self) -> MeshData {
+		MeshData {
+			vertices: self.vertices.clone(),
+			indices: self.indices.clone(),
+			dynamic: false,
+		}
+	}
+	/// Sets every normal to the area weighted average of the normals of the
+	/// triangles using the vertex.
+	pub fn compute_smooth_normals(&mut self) -> &mut Self {
+		let mut normals = vec![Vec3::ZERO; self.vertices.len()];
+		for triangle in self.indices.chunks_exact(3) {
+			let corners = [
+				triangle[0] as usize,
+				triangle[1] as usize,
+				triangle[2] as usize,
+			];
+			// the cross product's length is twice the triangle's area
+			let normal = self.face_normal(corners);
+			for corner in corners {
+				normals[corner] += normal;
+			}
+		}
+		for (vertex, normal) in self.vertices.iter_mut().zip(normals) {
+			vertex.normal = normal.normalize_or_zero().into();
+		}
+		self
+	}
+	/// Gives every triangle its own vertices with the triangle's normal, for a
+	/// faceted look.
+	pub fn compute_flat_normals(&mut self) -> &mut Self {
+		let mut vertices = Vec::with_capacity(self.indices.len());
+		for triangle in self.indices.chunks_exact(3) {
+			let corners = [
+				triangle[0] as usize,
+				triangle[1] as usize,
+				triangle[2] as usize,
+			];
+			let normal = self.face_normal(corners).normalize_or_zero().into();
+			for corner in corners {
+				vertices.push(Vertex {
+					normal,
+					..self.vertices[corner]
+				});
+			}
+		}
+		self.indices = (0..vertices.len() as u32).collect();
+		self.vertices = vertices;
+		self
+	}
+	/// Computes tangents from the uvs and normals. Each vertex sums the uv
+	/// tangents of its triangles weighted by the corner angle, orthogonalized
+	/// against its normal, and `w` holds the handedness. This only approximates
+	/// MikkTSpace: vertices are not split where the tangent spaces of their
+	/// triangles disagree and the result depends on how vertices are shared, so
+	/// normal maps baked with MikkTSpace can show seams.
+	pub fn compute_angle_weighted_tangents(&mut self) -> &mut Self {
+		let mut tangents = vec![Vec3::ZERO; self.vertices.len()];
+		let mut bitangents = vec![Vec3::ZERO; self.vertices.len()];
+
+		for triangle in self.indices.chunks_exact(3) {
+			let corners = [
+				triangle[0] as usize,
+				triangle[1] as usize,
+				triangle[2] as usize,
+			];
+			let positions = corners.map(|i| Vec3::from(self.vertices[i].position));
+			let uvs = corners.map(|i| Vec2::from(self.vertices[i].uv));
+
+			let edge1 = positions[1] - positions[0];
+			let edge2 = positions[2] - positions[0];
+			let duv1 = uvs[1] - uvs[0];
+			let duv2 = uvs[2] - uvs[0];
+			let determinant = duv1.x * duv2.y - duv2.x * duv1.y;
+			if determinant.abs() <= f32::EPSILON {
+				// no usable uv mapping
+				continue;
+			}
+			let tangent = (edge1 * duv2.y - edge2 * duv1.y) / determinant;
+			let bitangent = (edge2 * duv1.x - edge1 * duv2.x) / determinant;
+
+			for (k, corner) in corners.iter().enumerate() {
+				let to_next = positions[(k + 1) % 3] - positions[k];
+				let to_previous = positions[(k + 2) % 3] - positions[k];
+				let angle = to_next.angle_between(to_previous);
+				if angle.is_finite() {
+					tangents[*corner] += tangent * angle;
+					bitangents[*corner] += bitangent * angle;
+				}
+			}
+		}
+
+		for ((vertex, tangent), bitangent) in self.vertices.iter_mut().zip(tangents).zip(bitangents)
+		{
+			let normal = Vec3::from(vertex.normal);
+			let mut tangent = (tangent - normal * normal.dot(tangent)).normalize_or_zero();
+			if tangent == Vec3::ZERO {
+				tangent = any_orthogonal(normal);
+			}
+			let handedness = if normal.cross(tangent).dot(bitangent) < 0.0 {
+				-1.0
+			} else {
+				1.0
+			};
+			vertex.tangent = [tangent.x, tangent.y, tangent.z, handedness];
+		}
+		self
+	}
+	/// Not normalized, its length is twice the triangle's area.
+	fn face_normal(&self, corners: [usize; 3]) -> Vec3 {
+		let [a, b, c] = corners.map(|i| Vec3::from(self.vertices[i].position));
+		(b - a).cross(c - a)
+	}
+	// pub fn quad(&mut self) -> &mut Self {
+	// 	self.clear();
+	// 	self.vertices.extend_from_slice(&QUAD_VERTICES);
+	// 	self.indices.extend_from_slice(&QUAD_INDICES);
+	// 	self
+	// }
+	pub fn uv_sphere(
+		&mut self,
+		radius: f32,
+		vertical_subdivisions: usize,
+		horizontal_subdivisions: usize,
+	) -> &mut Self {
+		let start = Instant::now();
+		self.clear();
+
+		let vertex_count = (vertical_subdivisions + 1) * (horizontal_subdivisions + 1);
+		let index_count = (vertical_subdivisions - 1) * horizontal_subdivisions * 6;
+
+		self.vertices.reserve(vertex_count);
+		self.indices.reserve(index_count);
+
+		let mut sector_angle_cos = Vec::with_capacity(horizontal_subdivisions + 1);
+		let mut sector_angle_sin = Vec::with_capacity(horizontal_subdivisions + 1);
+
+		let stack_count = vertical_subdivisions;
+		let sector_count = horizontal_subdivisions;
+
+		let stack_count_f = stack_count as f32;
+		let sector_count_f = sector_count as f32;
+
+		let radius_inverse = 1.0 / radius;
+		// bottom to top, 0 to PI
+		let stack_step = PI / stack_count_f;
+		// all the way around, 0 to 2 PI
+		let sector_step = 2.0 * PI / sector_count_f;
+
+		let mut stack_angle: f32 = PI * 0.5;
+		for stack in 0..(stack_count + 1) {
+			let y = radius * stack_angle.sin();
+			let xz = radius * stack_angle.cos();
+
+			let mut sector_angle: f32 = -PI;
+			let v = stack as f32 / (stack_count_f + 1.0);
+			for sector in 0..(sector_count + 1) {
+				let (cos, sin) = if stack == 0 {
+					let cos = sector_angle.cos();
+					let sin = sector_angle.sin();
+					sector_angle += sector_step;
+					sector_angle_cos.push(cos);
+					sector_angle_sin.push(sin);
+					(cos, sin)
+				} else {
+					(sector_angle_cos[sector], sector_angle_sin[sector])
+				};
+
+				let z = xz * cos;
+				let x = xz * sin;
+				let position = [x, y, z];
+
+				let nx = x * radius_inverse;
+				let ny = y * radius_inverse;
+				let nz = z * radius_inverse;
+				let normal = [nx, ny, nz];
+
+				let u = sector as f32 / (sector_count_f);
+				let uv = [u, v];
+
+				let vertex = Vertex {
+					position,
+					color: [1.0, 1.0, 1.0],
+					uv,
+					normal,
+					..Default::default()
+				};
+				self.add_vertex(vertex);
+			}
+			stack_angle -= stack_step;
+		}
+
+		for i in 0..stack_count {
+			let k1_start = (i * (sector_count + 1)) as u32;
+			let k2_start = k1_start + sector_count as u32 + 1;
+
+			for j in 0..sector_count as u32 {
+				let k1 = k1_start + j;
+				let k2 = k2_start + j;
+
+				// not bottom
+				if i != 0 {
+					self.add_triangle(k1, k2, k1 + 1)
+				}
+
+				// not top
+				if i != (stack_count - 1) {
+					self.add_triangle(k1 + 1, k2, k2 + 1)
+				}
+			}
+		}
+
+		self.compute_angle_weighted_tangents();
+
+		log::debug!("uv_sphere generated in {} us", start.elapsed().as_micros());
+
+		self
+	}
+}
+
+/// A unit vector perpendicular to `normal`, or +x when `normal` is zero.
+fn any_orthogonal(normal: Vec3) -> Vec3 {
+	let axis = if normal.x.abs() < 0.9 {
+		Vec3::X
+	} else {
+		Vec3::Y
+	};
+	let orthogonal = normal.cross(axis).normalize_or_zero();
+	if orthogonal == Vec3::ZERO {
+		Vec3::X
+	} else {
+		orthogonal
+	}
+}
+
+#[allow(unused)]
+pub const TRIANGLE_VERTICES: &[Vertex] = &[
+	Vertex {
+		position: [0.0, 0.5, 0.0],
+		color: [1.0, 0.0, 0.0],
+		uv: [0.0, 0.0],
+		normal: [0.0, 0.0, 1.0],
+		tangent: [1.0, 0.0, 0.0, 1.0],
+	},
+	Vertex {
+		position: [-0.5, -0.5, 0.0],
+		color: [0.0, 1.0, 0.0],
+		uv: [0.0, 0.0],
+		normal: [0.0, 0.0, 1.0],
+		tangent: [1.0, 0.0, 0.0, 1.0],
+	},
+	Vertex {
+		position: [0.5, -0.5, 0.0],
+		color: [0.0, 0.0, 1.0],
+		uv: [0.0, 0.0],
+		normal: [0.0, 0.0, 1.0],
+		tangent: [1.0, 0.0, 0.0, 1.0],
+	},
+];
+#[allow(unused)]
+pub const TRIANGLE_INDICES: &[u32] = &[0, 1, 2];
+
+#[allow(unused)]
+pub const QUAD_VERTICES: &[Vertex] = &[
+	Vertex {
+		// lower left
+		position: [-0.5, -0.5, 0.0],
+		color: [0.0, 0.0, 1.0],
+		uv: [0.0, 0.0],
+		normal: [0.0, 0.0, 1.0],
+		tangent: [0.0, 1.0, 0.0, -1.0],
+	},
+	Vertex {
+		// lower right
+		position: [0.5, -0.5, 0.0],
+		color: [0.0, 1.0, 0.0],
+		uv: [0.0, 1.0],
+		normal: [0.0, 0.0, 1.0],
+		tangent: [0.0, 1.0, 0.0, -1.0],
+	},
+	Vertex {
+		// upper right
+		position: [0.5, 0.5, 0.0],
+		color: [1.0, 0.0, 0.0],
+		uv: [1.0, 1.0],
+		normal: [0.0, 0.0, 1.0],
+		tangent: [0.0, 1.0, 0.0, -1.0],
+	},
+	Vertex {
+		// upper left
+		position: [-0.5, 0.5, 0.0],
+		color: [0.0, 0.0, 1.0],
+		uv: [1.0, 0.0],
+		normal: [0.0, 0.0, 1.0],
+		tangent: [0.0, 1.0, 0.0, -1.0],
+	},
+];
+#[allow(unused)]
+pub const QUAD_INDICES: &[u32] = &[0, 1, 2, 0, 2, 3];
+
+/// Panics unless every triangle with an area faces the same way as the normals
+/// of its corners.
+#[cfg(test)]
+fn assert_faces_match_normals(name: &str, generator: &MeshGenerator) {
+	for (number, triangle) in generator.indices.chunks_exact(3).enumerate() {
+		let corners = [0, 1, 2].map(|k| triangle[k] as usize);
+		let face_normal = generator.face_normal(corners);
+		if face_normal.length_squared() < 1e-12 {
+			continue;
+		}
+		for corner in corners {
+			let normal = Vec3::from(generator.vertices[corner].normal);
+			assert!(
+				face_normal.dot(normal) > 0.0,
+				"{}: triangle {} faces {} but vertex {} has the normal {}",
+				name,
+				number,
+				face_normal.normalize(),
+				corner,
+				normal
+			);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn vertex(position: [f32; 3], uv: [f32; 2]) -> Vertex {
+		Vertex {
+			position,
+			uv,
+			normal: [0.0, 0.0, 1.0],
+			..Default::default()
+		}
+	}
+
+	/// A unit quad in the xy plane facing +z, v pointing down like in wgpu.
+	fn quad() -> MeshGenerator {
+		MeshGenerator {
+			vertices: vec![
+				vertex([0.0, 0.0, 0.0], [0.0, 1.0]),
+				vertex([1.0, 0.0, 0.0], [1.0, 1.0]),
+				vertex([1.0, 1.0, 0.0], [1.0, 0.0]),
+				vertex([0.0, 1.0, 0.0], [0.0, 0.0]),
+			],
+			indices: vec![0, 1, 2, 0, 2, 3],
+		}
+	}
+
+	#[test]
+	fn flat_normals_of_a_quad() {
+		let mut generator = quad();
+		for vertex in &mut generator.vertices {
+			vertex.normal = [0.0; 3];
+		}
+		generator.compute_flat_normals();
+		assert_eq!(generator.vertices.len(), 6);
+		assert_eq!(generator.indices, [0, 1, 2, 3, 4, 5]);
+		assert_eq!(generator.vertices[4].position, [1.0, 1.0, 0.0]);
+		assert!(generator
+			.vertices
+			.iter()
+			.all(|vertex| vertex.normal == [0.0, 0.0, 1.0]));
+	}
+
+	#[test]
+	fn smooth_normals_of_a_cube_corner() {
+		// three faces of the cube filling the positive octant meet at the origin
+		let mut generator = MeshGenerator {
+			vertices: vec![
+				vertex([0.0, 0.0, 0.0], [0.0; 2]),
+				vertex([1.0, 0.0, 0.0], [0.0; 2]),
+				vertex([0.0, 1.0, 0.0], [0.0; 2]),
+				vertex([0.0, 0.0, 1.0], [0.0; 2]),
+			],
+			indices: vec![0, 2, 1, 0, 3, 2, 0, 1, 3],
+		};
+		generator.compute_smooth_normals();
+		let normals: Vec<Vec3> = generator
+			.vertices
+			.iter()
+			.map(|vertex| vertex.normal.into())
+			.collect();
+		let expected = [
+			Vec3::new(-1.0, -1.0, -1.0),
+			Vec3::new(0.0, -1.0, -1.0),
+			Vec3::new(-1.0, 0.0, -1.0),
+			Vec3::new(-1.0, -1.0, 0.0),
+		];
+		for (normal, expected) in normals.iter().zip(expected) {
+			assert!(normal.distance(expected.normalize()) < 1e-6);
+		}
+	}
+
+	#[test]
+	fn tangents_of_a_uv_mapped_quad() {
+		let mut generator = quad();
+		generator.compute_angle_weighted_tangents();
+		for vertex in &generator.vertices {
+			assert_eq!(vertex.tangent, [1.0, 0.0, 0.0, -1.0]);
+		}
+
+		// mirroring u flips the tangent and the handedness
+		let mut mirrored = quad();
+		for vertex in &mut mirrored.vertices {
+			vertex.uv[0] = 1.0 - vertex.uv[0];
+		}
+		mirrored.compute_angle_weighted_tangents();
+		for vertex in &mirrored.vertices {
+			assert_eq!(vertex.tangent, [-1.0, 0.0, 0.0, 1.0]);
+		}
+	}
+
+	#[test]
+	fn tangents_are_unit_and_orthogonal_to_the_normal() {
+		let mut generator = MeshGenerator::default();
+		generator.icosphere(1.0, 2, SphereUvs::Equirectangular);
+		for vertex in &generator.vertices {
+			let normal = Vec3::from(vertex.normal);
+			let tangent = Vec3::new(vertex.tangent[0], vertex.tangent[1], vertex.tangent[2]);
+			assert!((tangent.length() - 1.0).abs() < 1e-5);
+			assert!(tangent.dot(normal).abs() < 1e-5);
+			assert!(vertex.tangent[3] == 1.0 || vertex.tangent[3] == -1.0);
+		}
+	}
+}
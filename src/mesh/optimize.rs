@@ -0,0 +1,486 @@
+// File: mesh/optimize.rs
+
+use super::MeshGenerator;
+use crate::vertex::Vertex;
+
+use bevy::math::Vec3;
+
+use std::collections::{HashMap, VecDeque};
+
+/// What `MeshGenerator::optimize` does.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct OptimizeSettings {
+	/// Merges vertices whose attributes all differ by at most this much, `None`
+	/// keeps every vertex.
+	pub weld_tolerance: Option<f32>,
+	/// Vertices the reordering expects the gpu to keep around.
+	pub cache_size: usize,
+	/// Draws the outward facing clusters of triangles first, so they hide the
+	/// ones behind them.
+	pub reduce_overdraw: bool,
+}
+impl Default for OptimizeSettings {
+	fn default() -> Self {
+		Self {
+			weld_tolerance: Some(0.0),
+			cache_size: 16,
+			reduce_overdraw: true,
+		}
+	}
+}
+
+/// What an `optimize` pass changed. The average cache miss ratio (ACMR) is the
+/// number of vertex shader runs per triangle with a FIFO cache of the settings'
+/// size, between 0.5 and 3 with lower being better.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct OptimizeStats {
+	pub vertices_before: usize,
+	pub vertices_after: usize,
+	pub acmr_before: f32,
+	pub acmr_after: f32,
+}
+
+impl MeshGenerator {
+	/// Welds duplicate vertices, reorders the triangles for the vertex cache and
+	/// the vertices in the order they are used. Triangles and their winding are
+	/// kept, except ones welding made degenerate.
+	pub fn optimize(&mut self, settings: OptimizeSettings) -> OptimizeStats {
+		let vertices_before = self.vertices.len();
+		let acmr_before = self.acmr(settings.cache_size);
+		if let Some(tolerance) = settings.weld_tolerance {
+			self.weld_vertices(tolerance);
+		}
+		if settings.reduce_overdraw {
+			self.optimize_overdraw(settings.cache_size);
+		} else {
+			self.optimize_vertex_cache(settings.cache_size);
+		}
+		self.optimize_vertex_fetch();
+
+		let stats = OptimizeStats {
+			vertices_before,
+			vertices_after: self.vertices.len(),
+			acmr_before,
+			acmr_after: self.acmr(settings.cache_size),
+		};
+		log::info!(
+			"optimized mesh: {} -> {} vertices, acmr {:.3} -> {:.3}",
+			stats.vertices_before,
+			stats.vertices_after,
+			stats.acmr_before,
+			stats.acmr_after
+		);
+		stats
+	}
+
+	/// Merges vertices whose position, color, uv, normal and tangent components
+	/// all differ by at most `tolerance`, 0 only merges exact duplicates.
+	/// Triangles left with two equal corners are removed.
+	pub fn weld_vertices(&mut self, tolerance: f32) -> &mut Self {
+		let tolerance = tolerance.max(0.0);
+		let cell = |position: [f32; 3]| -> [i64; 3] {
+			if tolerance > 0.0 {
+				position.map(|x| (x / tolerance).floor() as i64)
+			} else {
+				// 0.0 and -0.0 have different bits but are the same position
+				position.map(|x| (x + 0.0).to_bits() as i64)
+			}
+		};
+		// with a tolerance, matches can sit in the neighboring cells
+		let reach = if tolerance > 0.0 { 1 } else { 0 };
+
+		let mut cells: HashMap<[i64; 3], Vec<u32>> = HashMap::new();
+		let mut vertices: Vec<Vertex> = Vec::with_capacity(self.vertices.len());
+		let mut remap = Vec::with_capacity(self.vertices.len());
+		for vertex in &self.vertices {
+			let [x, y, z] = cell(vertex.position);
+			let mut found = None;
+			'search: for dx in -reach..=reach {
+				for dy in -reach..=reach {
+					for dz in -reach..=reach {
+						let candidates = match cells.get(&[x + dx, y + dy, z + dz]) {
+							Some(candidates) => candidates,
+							None => continue,
+						};
+						for candidate in candidates {
+							if vertices_match(&vertices[*candidate as usize], vertex, tolerance) {
+								found = Some(*candidate);
+								break 'search;
+							}
+						}
+					}
+				}
+			}
+			let index = match found {
+				Some(index) => index,
+				None => {
+					vertices.push(*vertex);
+					let index = vertices.len() as u32 - 1;
+					cells.entry([x, y, z]).or_default().push(index);
+					index
+				}
+			};
+			remap.push(index);
+		}
+
+		let mut indices = Vec::with_capacity(self.indices.len());
+		for triangle in self.indices.chunks_exact(3) {
+			let [a, b, c] = [0, 1, 2].map(|k| remap[triangle[k] as usize]);
+			if a != b && b != c && c != a {
+				indices.extend_from_slice(&[a, b, c]);
+			}
+		}
+		self.vertices = vertices;
+		self.indices = indices;
+		self
+	}
+
+	/// Reorders the triangles so recently transformed vertices are reused, with
+	/// Tipsify (Sander, Nehab and Barczak 2007).
+	pub fn optimize_vertex_cache(&mut self, cache_size: usize) -> &mut Self {
+		let (order, _) = tipsify(&self.indices, self.vertices.len(), cache_size);
+		self.indices = reorder_triangles(&self.indices, &order);
+		self
+	}
+	/// Reorders the triangles for the vertex cache like `optimize_vertex_cache`,
+	/// then sorts the clusters Tipsify jumps between so the ones facing away from
+	/// the mesh's center are drawn first.
+	pub fn optimize_overdraw(&mut self, cache_size: usize) -> &mut Self {
+		let (order, cluster_starts) = tipsify(&self.indices, self.vertices.len(), cache_size);
+		let position = |index: u32| Vec3::from(self.vertices[index as usize].position);
+
+		// area weighted centroid, summed normal and area of each cluster
+		let mut clusters: Vec<(Vec3, Vec3, f32, &[usize])> = cluster_starts
+			.iter()
+			.zip(cluster_starts.iter().skip(1).chain([&order.len()]))
+			.map(|(start, end)| {
+				let triangles = &order[*start..*end];
+				let mut centroid = Vec3::ZERO;
+				let mut normal = Vec3::ZERO;
+				let mut area = 0.0;
+				for triangle in triangles {
+					let [a, b, c] = [0, 1, 2].map(|k| position(self.indices[triangle * 3 + k]));
+					let cross = (b - a).cross(c - a);
+					centroid += (a + b + c) / 3.0 * cross.length();
+					normal += cross;
+					area += cross.length();
+				}
+				(centroid, normal, area, triangles)
+			})
+			.collect();
+		let (centroid_sum, area_sum) = clusters
+			.iter()
+			.fold((Vec3::ZERO, 0.0), |(centroid, area), cluster| {
+				(centroid + cluster.0, area + cluster.2)
+			});
+		let mesh_centroid = centroid_sum / area_sum.max(f32::EPSILON);
+		let facing_out = |(centroid, normal, area, _): &(Vec3, Vec3, f32, &[usize])| {
+			let centroid = *centroid / area.max(f32::EPSILON);
+			(centroid - mesh_centroid).dot(normal.normalize_or_zero())
+		};
+		clusters.sort_by(|a, b| {
+			facing_out(b)
+				.partial_cmp(&facing_out(a))
+				.unwrap_or(std::cmp::Ordering::Equal)
+		});
+		let order: Vec<usize> = clusters
+			.iter()
+			.flat_map(|(_, _, _, triangles)| triangles.iter().copied())
+			.collect();
+		self.indices = reorder_triangles(&self.indices, &order);
+		self
+	}
+	/// Reorders the vertices in the order the triangles first use them and drops
+	/// unused ones, so the gpu reads the vertex buffer front to back.
+	pub fn optimize_vertex_fetch(&mut self) -> &mut Self {
+		let mut remap = vec![u32::MAX; self.vertices.len()];
+		let mut vertices = Vec::with_capacity(self.vertices.len());
+		for index in &mut self.indices {
+			let new_index = &mut remap[*index as usize];
+			if *new_index == u32::MAX {
+				vertices.push(self.vertices[*index as usize]);
+				*new_index = vertices.len() as u32 - 1;
+			}
+			*index = *new_index;
+		}
+		self.vertices = vertices;
+		self
+	}
+
+	/// Average cache miss ratio of the current triangle order, simulated with a
+	/// FIFO cache holding `cache_size` vertices. 0 without triangles.
+	pub fn acmr(&self, cache_size: usize) -> f32 {
+		let triangle_count = self.indices.len() / 3;
+		if triangle_count == 0 {
+			return 0.0;
+		}
+		let mut cache = VecDeque::with_capacity(cache_size + 1);
+		let mut misses = 0;
+		for index in &self.indices[..triangle_count * 3] {
+			if cache.contains(index) {
+				continue;
+			}
+			misses += 1;
+			cache.push_back(*index);
+			if cache.len() > cache_size {
+				cache.pop_front();
+			}
+		}
+		misses as f32 / triangle_count as f32
+	}
+}
+
+fn vertices_match(a: &Vertex, b: &Vertex, tolerance: f32) -> bool {
+	let close = |a: &[f32], b: &[f32]| a.iter().zip(b).all(|(a, b)| (a - b).abs() <= tolerance);
+	Vec3::from(a.position).distance(Vec3::from(b.position)) <= tolerance
+		&& close(&a.color, &b.color)
+		&& close(&a.uv, &b.uv)
+		&& close(&a.normal, &b.normal)
+		&& close(&a.tangent, &b.tangent)
+}
+
+fn reorder_triangles(indices: &[u32], order: &[usize]) -> Vec<u32> {
+	order
+		.iter()
+		.flat_map(|triangle| indices[triangle * 3..triangle * 3 + 3].iter().copied())
+		.collect()
+}
+
+/// Tipsify's triangle order, and where it jumped to a vertex outside the cache,
+/// which splits the order into clusters.
+fn tipsify(indices: &[u32], vertex_count: usize, cache_size: usize) -> (Vec<usize>, Vec<usize>) {
+	let triangle_count = indices.len() / 3;
+	let cache_size = cache_size.max(3) as i64;
+
+	// triangles around each vertex
+	let mut live = vec![0usize; vertex_count];
+	for index in &indices[..triangle_count * 3] {
+		live[*index as usize] += 1;
+	}
+	let mut offsets = Vec::with_capacity(vertex_count + 1);
+	offsets.push(0);
+	for count in &live {
+		offsets.push(offsets.last().unwrap() + count);
+	}
+	let mut adjacency = vec![0; offsets[vertex_count]];
+	let mut filled = offsets.clone();
+	for triangle in 0..triangle_count {
+		for k in 0..3 {
+			let vertex = indices[triangle * 3 + k] as usize;
+			adjacency[filled[vertex]] = triangle;
+			filled[vertex] += 1;
+		}
+	}
+
+	let mut cache_time = vec![0i64; vertex_count];
+	let mut emitted = vec![false; triangle_count];
+	let mut dead_ends: Vec<usize> = vec![];
+	let mut order = Vec::with_capacity(triangle_count);
+	let mut cluster_starts = vec![];
+	let mut time = cache_size + 1;
+	let mut cursor = 0;
+
+	let mut fanning = (0..vertex_count).find(|vertex| live[*vertex] > 0);
+	let mut jumped = true;
+	while let Some(vertex) = fanning {
+		if jumped {
+			cluster_starts.push(order.len());
+		}
+		let mut candidates = vec![];
+		for triangle in &adjacency[offsets[vertex]..offsets[vertex + 1]] {
+			if emitted[*triangle] {
+				continue;
+			}
+			emitted[*triangle] = true;
+			order.push(*triangle);
+			for k in 0..3 {
+				let corner = indices[triangle * 3 + k] as usize;
+				dead_ends.push(corner);
+				candidates.push(corner);
+				live[corner] -= 1;
+				if time - cache_time[corner] > cache_size {
+					cache_time[corner] = time;
+					time += 1;
+				}
+			}
+		}
+
+		// the candidate staying in the cache longest after fanning around it
+		let mut best = None;
+		let mut best_priority = -1;
+		for candidate in candidates {
+			if live[candidate] == 0 {
+				continue;
+			}
+			let age = time - cache_time[candidate];
+			let priority = if age + 2 * live[candidate] as i64 <= cache_size {
+				age
+			} else {
+				0
+			};
+			if priority > best_priority {
+				best_priority = priority;
+				best = Some(candidate);
+			}
+		}
+		jumped = best.is_none();
+		fanning = match best {
+			Some(vertex) => Some(vertex),
+			None => {
+				let mut next = None;
+				while let Some(vertex) = dead_ends.pop() {
+					if live[vertex] > 0 {
+						next = Some(vertex);
+						break;
+					}
+				}
+				if next.is_none() {
+					while cursor < vertex_count && live[cursor] == 0 {
+						cursor += 1;
+					}
+					if cursor < vertex_count {
+						next = Some(cursor);
+					}
+				}
+				next
+			}
+		};
+	}
+	(order, cluster_starts)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::mesh::SphereUvs;
+
+	fn vertex(position: [f32; 3]) -> Vertex {
+		Vertex {
+			position,
+			normal: [0.0, 0.0, 1.0],
+			..Vertex::default()
+		}
+	}
+	/// A shuffled icosphere, so the cache has something to improve.
+	fn shuffled_icosphere() -> MeshGenerator {
+		let mut generator = MeshGenerator::default();
+		generator.icosphere(1.0, 3, SphereUvs::CubeFaces);
+		let mut triangles: Vec<[u32; 3]> = generator
+			.indices
+			.chunks_exact(3)
+			.map(|triangle| [triangle[0], triangle[1], triangle[2]])
+			.collect();
+		let mut state = 12345u32;
+		for i in (1..triangles.len()).rev() {
+			state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+			triangles.swap(i, (state >> 8) as usize % (i + 1));
+		}
+		generator.indices = triangles.concat();
+		generator
+	}
+	/// Each triangle as its corners' positions and uvs, starting at the
+	/// smallest corner so the winding is kept, sorted.
+	fn triangle_set(generator: &MeshGenerator) -> Vec<[[u32; 5]; 3]> {
+		let mut triangles: Vec<[[u32; 5]; 3]> = generator
+			.indices
+			.chunks_exact(3)
+			.map(|triangle| {
+				let corners = [0, 1, 2].map(|k| {
+					let vertex = &generator.vertices[triangle[k] as usize];
+					let [x, y, z] = vertex.position;
+					let [u, v] = vertex.uv;
+					[x, y, z, u, v].map(f32::to_bits)
+				});
+				let first = (0..3).min_by_key(|k| corners[*k]).unwrap();
+				[0, 1, 2].map(|k| corners[(first + k) % 3])
+			})
+			.collect();
+		triangles.sort_unstable();
+		triangles
+	}
+
+	#[test]
+	fn optimize_lowers_acmr() {
+		let mut generator = shuffled_icosphere();
+		let settings = OptimizeSettings::default();
+		let acmr = generator.acmr(settings.cache_size);
+		let stats = generator.optimize(settings);
+		assert_eq!(stats.acmr_before, acmr);
+		assert!(stats.acmr_after <= stats.acmr_before);
+		assert!(stats.acmr_after < 1.0, "acmr {}", stats.acmr_after);
+		assert_eq!(stats.acmr_after, generator.acmr(settings.cache_size));
+
+		let mut generator = shuffled_icosphere();
+		let acmr = generator.acmr(16);
+		generator.optimize_vertex_cache(16);
+		assert!(generator.acmr(16) <= acmr);
+	}
+
+	#[test]
+	fn optimize_keeps_triangles_and_winding() {
+		for reduce_overdraw in [false, true] {
+			let mut generator = shuffled_icosphere();
+			let before = triangle_set(&generator);
+			generator.optimize(OptimizeSettings {
+				reduce_overdraw,
+				..OptimizeSettings::default()
+			});
+			assert_eq!(triangle_set(&generator), before);
+			assert!(generator.validate().is_valid(), "{}", generator.validate());
+		}
+	}
+
+	#[test]
+	fn zero_tolerance_welds_exact_duplicates() {
+		let mut generator = MeshGenerator {
+			vertices: vec![
+				vertex([0.0, 0.0, 0.0]),
+				vertex([1.0, 0.0, 0.0]),
+				vertex([0.0, 1.0, 0.0]),
+				vertex([-0.0, 0.0, 0.0]),
+				vertex([1.0, 1e-6, 0.0]),
+				vertex([0.0, 1.0, 0.0]),
+			],
+			indices: vec![0, 1, 2, 3, 4, 5],
+		};
+		generator.weld_vertices(0.0);
+		assert_eq!(generator.vertices.len(), 4);
+		assert_eq!(generator.indices, [0, 1, 2, 0, 3, 2]);
+	}
+
+	#[test]
+	fn tolerance_welds_across_cells() {
+		let mut generator = MeshGenerator {
+			vertices: vec![
+				// on both sides of a cell boundary at 0.01
+				vertex([0.0099, 0.0, 0.0]),
+				vertex([1.0, 0.0, 0.0]),
+				vertex([0.0, 1.0, 0.0]),
+				vertex([0.0101, 0.0, 0.0]),
+				vertex([1.0, 1.0, 0.0]),
+				vertex([0.0, 1.0, 0.005]),
+			],
+			indices: vec![0, 1, 2, 3, 4, 5],
+		};
+		generator.weld_vertices(0.01);
+		assert_eq!(generator.vertices.len(), 4);
+		assert_eq!(generator.indices, [0, 1, 2, 0, 3, 2]);
+
+		// welding can collapse triangles, which are removed
+		generator.weld_vertices(2.0);
+		assert_eq!(generator.vertices.len(), 1);
+		assert!(generator.indices.is_empty());
+	}
+
+	#[test]
+	fn vertex_fetch_orders_and_drops_vertices() {
+		let mut generator = MeshGenerator {
+			vertices: (0..5).map(|i| vertex([i as f32, 0.0, 0.0])).collect(),
+			indices: vec![3, 1, 4, 4, 1, 0],
+		};
+		generator.optimize_vertex_fetch();
+		let positions: Vec<f32> = generator.vertices.iter().map(|v| v.position[0]).collect();
+		assert_eq!(positions, [3.0, 1.0, 4.0, 0.0]);
+		assert_eq!(generator.indices, [0, 1, 2, 2, 1, 3]);
+	}
+}
@@ -0,0 +1,378 @@
+// File: mesh/shapes.rs
+
+use super::MeshGenerator;
+use crate::vertex::Vertex;
+
+use bevy::math::{Vec2, Vec3};
+
+use core::f32::consts::{FRAC_PI_2, PI};
+
+/// A point on the outline of a surface of revolution, see
+/// `MeshGenerator::add_revolution`.
+struct ProfilePoint {
+	/// Distance from the y axis.
+	radius: f32,
+	y: f32,
+	/// Outward normal in the (radius, y) plane.
+	normal: Vec2,
+	v: f32,
+}
+
+/// The faces of a cube as (normal, right, up) with right x up = normal, in the
+/// order +x, -x, +y, -y, +z, -z.
+pub(super) fn cube_faces() -> [(Vec3, Vec3, Vec3); 6] {
+	[
+		(Vec3::X, -Vec3::Z, Vec3::Y),
+		(-Vec3::X, Vec3::Z, Vec3::Y),
+		(Vec3::Y, Vec3::X, -Vec3::Z),
+		(-Vec3::Y, Vec3::X, Vec3::Z),
+		(Vec3::Z, Vec3::X, Vec3::Y),
+		(-Vec3::Z, -Vec3::X, Vec3::Y),
+	]
+}
+
+/// Shapes are centered on the origin with +y up. Triangles are counter clockwise
+/// seen from outside, matching the pipeline's `FrontFace::Ccw` and back face
+/// culling. Round shapes duplicate the vertices of their seam so `u` runs from 0
+/// to 1 around them.
+impl MeshGenerator {
+	/// A box with separate vertices per face, so each face has its own normal
+	/// and the full 0..1 uv range.
+	pub fn cuboid(&mut self, width: f32, height: f32, depth: f32) -> &mut Self {
+		self.clear();
+		let size = Vec3::new(width, height, depth);
+		for (normal, right, up) in cube_faces() {
+			let first = self.vertices.len() as u32;
+			for (s, t) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+				let position = (normal * 0.5 + right * (s - 0.5) + up * (t - 0.5)) * size;
+				self.add_vertex(Vertex {
+					position: position.into(),
+					color: [1.0, 1.0, 1.0],
+					uv: [s, 1.0 - t],
+					normal: normal.into(),
+					..Default::default()
+				});
+			}
+			self.add_quad(first, first + 1, first + 2, first + 3);
+		}
+		self.compute_angle_weighted_tangents();
+		self
+	}
+	/// A plane in xz facing +y, split into `x_segments` by `z_segments` quads.
+	pub fn plane(
+		&mut self,
+		width: f32,
+		depth: f32,
+		x_segments: usize,
+		z_segments: usize,
+	) -> &mut Self {
+		assert!(
+			x_segments > 0 && z_segments > 0,
+			"a plane needs at least one segment"
+		);
+		self.clear();
+		for i in 0..=x_segments {
+			let u = i as f32 / x_segments as f32;
+			for j in 0..=z_segments {
+				let v = j as f32 / z_segments as f32;
+				self.add_vertex(Vertex {
+					position: [(u - 0.5) * width, 0.0, (v - 0.5) * depth],
+					color: [1.0, 1.0, 1.0],
+					uv: [u, v],
+					normal: [0.0, 1.0, 0.0],
+					..Default::default()
+				});
+			}
+		}
+		let column = z_segments as u32 + 1;
+		for i in 0..x_segments as u32 {
+			for j in 0..z_segments as u32 {
+				let a = i * column + j;
+				let b = a + column;
+				self.add_quad(a, a + 1, b + 1, b);
+			}
+		}
+		self.compute_angle_weighted_tangents();
+		self
+	}
+	/// A disk in xz facing +y.
+	pub fn disk(&mut self, radius: f32, segments: usize) -> &mut Self {
+		assert!(segments >= 3, "a disk needs at least 3 segments");
+		self.clear();
+		self.add_cap(0.0, radius, segments, true);
+		self.compute_angle_weighted_tangents();
+		self
+	}
+	/// A closed cylinder along y.
+	pub fn cylinder(
+		&mut self,
+		radius: f32,
+		height: f32,
+		segments: usize,
+		height_segments: usize,
+	) -> &mut Self {
+		self.truncated_cone(radius, radius, height, segments, height_segments)
+	}
+	/// A closed cone along y with its tip at the top.
+	pub fn cone(
+		&mut self,
+		radius: f32,
+		height: f32,
+		segments: usize,
+		height_segments: usize,
+	) -> &mut Self {
+		self.truncated_cone(radius, 0.0, height, segments, height_segments)
+	}
+	/// A cone along y cut off at `top_radius`. Ends with a radius of zero get
+	/// no cap.
+	pub fn truncated_cone(
+		&mut self,
+		bottom_radius: f32,
+		top_radius: f32,
+		height: f32,
+		segments: usize,
+		height_segments: usize,
+	) -> &mut Self {
+		assert!(segments >= 3, "a cone needs at least 3 segments");
+		assert!(
+			height_segments > 0,
+			"a cone needs at least one height segment"
+		);
+		self.clear();
+
+		let normal = Vec2::new(height, bottom_radius - top_radius).normalize_or_zero();
+		let profile: Vec<ProfilePoint> = (0..=height_segments)
+			.map(|row| {
+				let v = row as f32 / height_segments as f32;
+				ProfilePoint {
+					radius: top_radius + (bottom_radius - top_radius) * v,
+					y: height * (0.5 - v),
+					normal,
+					v,
+				}
+			})
+			.collect();
+		self.add_revolution(&profile, segments);
+
+		if top_radius > 0.0 {
+			self.add_cap(height * 0.5, top_radius, segments, true);
+		}
+		if bottom_radius > 0.0 {
+			self.add_cap(-height * 0.5, bottom_radius, segments, false);
+		}
+		self.compute_angle_weighted_tangents();
+		self
+	}
+	/// A ring around y. `major_radius` is measured to the center of the tube.
+	pub fn torus(
+		&mut self,
+		major_radius: f32,
+		minor_radius: f32,
+		major_segments: usize,
+		minor_segments: usize,
+	) -> &mut Self {
+		assert!(
+			major_segments >= 3 && minor_segments >= 3,
+			"a torus needs at least 3 segments each way"
+		);
+		self.clear();
+		// around the tube starting outside, heading down first
+		let profile: Vec<ProfilePoint> = (0..=minor_segments)
+			.map(|row| {
+				let v = row as f32 / minor_segments as f32;
+				let angle = v * 2.0 * PI;
+				let normal = Vec2::new(angle.cos(), -angle.sin());
+				ProfilePoint {
+					radius: major_radius + minor_radius * normal.x,
+					y: minor_radius * normal.y,
+					normal,
+					v,
+				}
+			})
+			.collect();
+		self.add_revolution(&profile, major_segments);
+		self.compute_angle_weighted_tangents();
+		self
+	}
+	/// A cylinder along y with hemispheres on both ends. `height` excludes the
+	/// hemispheres, `rings` is the number of rows per hemisphere.
+	pub fn capsule(
+		&mut self,
+		radius: f32,
+		height: f32,
+		segments: usize,
+		rings: usize,
+	) -> &mut Self {
+		assert!(segments >= 3, "a capsule needs at least 3 segments");
+		assert!(rings > 0, "a capsule needs at least one ring");
+		self.clear();
+
+		let half_height = height * 0.5;
+		let total_height = height + 2.0 * radius;
+		let mut profile = Vec::with_capacity(2 * rings + 2);
+		for (center, first_angle) in [(half_height, 0.0), (-half_height, FRAC_PI_2)] {
+			for ring in 0..=rings {
+				// measured from the top pole
+				let angle = first_angle + FRAC_PI_2 * ring as f32 / rings as f32;
+				let normal = Vec2::new(angle.sin(), angle.cos());
+				let y = center + radius * normal.y;
+				profile.push(ProfilePoint {
+					// sin(PI) is not quite 0, keep the bottom pole on the axis
+					radius: (radius * normal.x).max(0.0),
+					y,
+					normal,
+					v: (half_height + radius - y) / total_height,
+				});
+			}
+		}
+		self.add_revolution(&profile, segments);
+		self.compute_angle_weighted_tangents();
+		self
+	}
+
+	/// Two triangles, skipping any that collapse onto a point like at the tip
+	/// of a cone.
+	pub(super) fn add_quad(&mut self, a: u32, b: u32, c: u32, d: u32) {
+		for [a, b, c] in [[a, b, c], [a, c, d]] {
+			let position = |i: u32| self.vertices[i as usize].position;
+			if position(a) != position(b)
+				&& position(b) != position(c)
+				&& position(c) != position(a)
+			{
+				self.add_triangle(a, b, c);
+			}
+		}
+	}
+	/// Sweeps `profile`, ordered top to bottom, around the y axis. Rows of
+	/// `segments + 1` vertices start at +z and turn towards +x.
+	fn add_revolution(&mut self, profile: &[ProfilePoint], segments: usize) {
+		let first = self.vertices.len() as u32;
+		for point in profile {
+			for segment in 0..=segments {
+				let u = segment as f32 / segments as f32;
+				let angle = u * 2.0 * PI;
+				let radial = Vec3::new(angle.sin(), 0.0, angle.cos());
+				let position = radial * point.radius + Vec3::Y * point.y;
+				let normal = radial * point.normal.x + Vec3::Y * point.normal.y;
+				self.add_vertex(Vertex {
+					position: position.into(),
+					color: [1.0, 1.0, 1.0],
+					uv: [u, point.v],
+					normal: normal.into(),
+					..Default::default()
+				});
+			}
+		}
+		let row = segments as u32 + 1;
+		for i in 0..profile.len() as u32 - 1 {
+			for j in 0..segments as u32 {
+				let a = first + i * row + j;
+				let b = a + row;
+				self.add_quad(a, b, b + 1, a + 1);
+			}
+		}
+	}
+	/// A flat disk at height `y` facing up or down, with planar uvs.
+	fn add_cap(&mut self, y: f32, radius: f32, segments: usize, up: bool) {
+		let (normal, flip) = if up { (1.0, 1.0) } else { (-1.0, -1.0) };
+		let center = self.vertices.len() as u32;
+		self.add_vertex(Vertex {
+			position: [0.0, y, 0.0],
+			color: [1.0, 1.0, 1.0],
+			uv: [0.5, 0.5],
+			normal: [0.0, normal, 0.0],
+			..Default::default()
+		});
+		for segment in 0..segments {
+			let angle = segment as f32 / segments as f32 * 2.0 * PI;
+			let (x, z) = (angle.sin(), angle.cos());
+			self.add_vertex(Vertex {
+				position: [x * radius, y, z * radius],
+				color: [1.0, 1.0, 1.0],
+				uv: [0.5 + x * 0.5, 0.5 + flip * z * 0.5],
+				normal: [0.0, normal, 0.0],
+				..Default::default()
+			});
+		}
+		for segment in 0..segments as u32 {
+			let current = center + 1 + segment;
+			let next = center + 1 + (segment + 1) % segments as u32;
+			if up {
+				self.add_triangle(center, current, next);
+			} else {
+				self.add_triangle(center, next, current);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn generators() -> Vec<(&'static str, MeshGenerator)> {
+		let shape = MeshGenerator::default;
+		vec![
+			("cuboid", shape().cuboid(1.0, 2.0, 3.0).clone()),
+			("plane", shape().plane(2.0, 1.0, 3, 2).clone()),
+			("disk", shape().disk(1.0, 8).clone()),
+			("cylinder", shape().cylinder(1.0, 2.0, 8, 2).clone()),
+			("cone", shape().cone(1.0, 2.0, 8, 2).clone()),
+			(
+				"truncated_cone",
+				shape().truncated_cone(1.0, 0.5, 2.0, 8, 2).clone(),
+			),
+			(
+				"inverted_cone",
+				shape().truncated_cone(0.0, 1.0, 2.0, 8, 2).clone(),
+			),
+			("torus", shape().torus(1.0, 0.25, 12, 6).clone()),
+			("capsule", shape().capsule(0.5, 1.0, 8, 3).clone()),
+			("uv_sphere", shape().uv_sphere(1.0, 6, 8).clone()),
+		]
+	}
+
+	#[test]
+	fn faces_match_normals() {
+		for (name, generator) in generators() {
+			super::super::assert_faces_match_normals(name, &generator);
+			assert!(
+				generator.validate().is_valid(),
+				"{}: {}",
+				name,
+				generator.validate()
+			);
+		}
+	}
+
+	#[test]
+	fn vertex_and_index_counts() {
+		let counts: Vec<_> = generators()
+			.iter()
+			.map(|(name, generator)| (*name, generator.vertices.len(), generator.indices.len()))
+			.collect();
+		assert_eq!(
+			counts,
+			[
+				// 4 vertices and 2 triangles per face
+				("cuboid", 24, 36),
+				// (3 + 1) x (2 + 1) vertices, 3 x 2 quads
+				("plane", 12, 36),
+				// center and rim
+				("disk", 9, 24),
+				// 3 rows of 8 + 1 around, 2 rows of quads and two disks
+				("cylinder", 45, 144),
+				// the top row of quads loses a triangle each at the tip
+				("cone", 36, 96),
+				("truncated_cone", 45, 144),
+				("inverted_cone", 36, 96),
+				// (12 + 1) x (6 + 1) vertices, 12 x 6 quads
+				("torus", 91, 432),
+				// 2 x (3 + 1) rows of 8 + 1, 7 rows of quads less a triangle per pole
+				("capsule", 72, 288),
+				// (6 + 1) x (8 + 1) vertices, the pole rows are single triangles
+				("uv_sphere", 63, 240),
+			]
+		);
+	}
+}
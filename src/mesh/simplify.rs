@@ -0,0 +1,496 @@
+// File: mesh/simplify.rs
+
+use super::MeshGenerator;
+
+use bevy::math::Vec3;
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+/// How much moving off a border or seam costs compared to moving off a face.
+const BORDER_WEIGHT: f64 = 100.0;
+
+impl MeshGenerator {
+	/// Collapses edges by their quadric error (Garland and Heckbert) until at most
+	/// `target_ratio` of the triangles are left, or no collapse is possible.
+	/// Vertices only move onto their neighbors and keep their attributes.
+	/// Borders stay in place and uv or normal seams only collapse along
+	/// themselves.
+	pub fn simplify(&mut self, target_ratio: f32) -> &mut Self {
+		let target = (self.indices.len() / 3) as f32 * target_ratio.clamp(0.0, 1.0);
+		self.simplify_to(target as usize);
+		self
+	}
+	/// Levels of detail with `ratios` of this mesh's triangles, from the most to
+	/// the least detailed. Each level is simplified from the one before it.
+	pub fn lod_chain(&self, ratios: &[f32]) -> Vec<MeshGenerator> {
+		let triangle_count = (self.indices.len() / 3) as f32;
+		let mut levels: Vec<MeshGenerator> = Vec::with_capacity(ratios.len());
+		for ratio in ratios {
+			let mut level = levels.last().unwrap_or(self).clone();
+			level.simplify_to((triangle_count * ratio.clamp(0.0, 1.0)) as usize);
+			levels.push(level);
+		}
+		levels
+	}
+
+	fn simplify_to(&mut self, target_triangles: usize) {
+		if self.indices.len() / 3 <= target_triangles {
+			return;
+		}
+		let mut simplifier = Simplifier::new(self);
+		simplifier.run(target_triangles);
+		simplifier.finish(self);
+	}
+}
+
+/// Symmetric 4x4 matrix summing squared distances to planes.
+#[derive(Copy, Clone, Default)]
+struct Quadric([f64; 10]);
+impl Quadric {
+	fn plane(normal: Vec3, point: Vec3, weight: f64) -> Self {
+		let [a, b, c] = [normal.x as f64, normal.y as f64, normal.z as f64];
+		let d = -(normal.dot(point) as f64);
+		Self([
+			a * a,
+			a * b,
+			a * c,
+			a * d,
+			b * b,
+			b * c,
+			b * d,
+			c * c,
+			c * d,
+			d * d,
+		])
+		.scaled(weight)
+	}
+	fn scaled(self, weight: f64) -> Self {
+		Self(self.0.map(|value| value * weight))
+	}
+	fn add(&mut self, other: &Quadric) {
+		for (value, other) in self.0.iter_mut().zip(other.0) {
+			*value += other;
+		}
+	}
+	fn error(&self, point: Vec3) -> f64 {
+		let [x, y, z] = [point.x as f64, point.y as f64, point.z as f64];
+		let q = &self.0;
+		q[0] * x * x
+			+ 2.0 * q[1] * x * y
+			+ 2.0 * q[2] * x * z
+			+ 2.0 * q[3] * x
+			+ q[4] * y * y
+			+ 2.0 * q[5] * y * z
+			+ 2.0 * q[6] * y
+			+ q[7] * z * z
+			+ 2.0 * q[8] * z
+			+ q[9]
+	}
+}
+
+/// Cost bits, the position collapsed, the position it moves onto, and their
+/// versions when the cost was computed.
+type Collapse = (u64, usize, usize, u32, u32);
+
+/// Collapses work on positions, so vertices split along seams move together.
+/// Each position keeps the vertices placed on it as its copies.
+struct Simplifier {
+	/// Position of each vertex.
+	position_of: Vec<usize>,
+	positions: Vec<Vec3>,
+	copies: Vec<Vec<u32>>,
+	quadrics: Vec<Quadric>,
+	/// Bumped when a position's quadric changes, older queue entries are stale.
+	versions: Vec<u32>,
+	removed: Vec<bool>,
+	/// Positions joined by border edges, a border position only collapses
+	/// along them.
+	border_neighbors: Vec<Vec<usize>>,
+
+	triangles: Vec<[u32; 3]>,
+	alive: Vec<bool>,
+	live_triangles: usize,
+	/// Triangles around each position.
+	triangles_of: Vec<Vec<usize>>,
+
+	queue: BinaryHeap<Reverse<Collapse>>,
+}
+impl Simplifier {
+	fn new(generator: &MeshGenerator) -> Self {
+		let mut ids: HashMap<[u32; 3], usize> = HashMap::new();
+		let mut positions = vec![];
+		let mut copies: Vec<Vec<u32>> = vec![];
+		let position_of: Vec<usize> = generator
+			.vertices
+			.iter()
+			.enumerate()
+			.map(|(index, vertex)| {
+				let id = *ids
+					.entry(vertex.position.map(f32::to_bits))
+					.or_insert_with(|| {
+						positions.push(Vec3::from(vertex.position));
+						copies.push(vec![]);
+						positions.len() - 1
+					});
+				copies[id].push(index as u32);
+				id
+			})
+			.collect();
+
+		let triangles: Vec<[u32; 3]> = generator
+			.indices
+			.chunks_exact(3)
+			.map(|triangle| [triangle[0], triangle[1], triangle[2]])
+			.collect();
+		let mut triangles_of = vec![vec![]; positions.len()];
+		let mut position_edges: HashMap<(usize, usize), usize> = HashMap::new();
+		let mut vertex_edges: HashMap<(u32, u32), usize> = HashMap::new();
+		for (t, triangle) in triangles.iter().enumerate() {
+			for k in 0..3 {
+				let (a, b) = (triangle[k], triangle[(k + 1) % 3]);
+				let (pa, pb) = (position_of[a as usize], position_of[b as usize]);
+				if !triangles_of[pa].contains(&t) {
+					triangles_of[pa].push(t);
+				}
+				*position_edges.entry((pa.min(pb), pa.max(pb))).or_default() += 1;
+				*vertex_edges.entry((a.min(b), a.max(b))).or_default() += 1;
+			}
+		}
+
+		let mut quadrics = vec![Quadric::default(); positions.len()];
+		let mut border_neighbors = vec![vec![]; positions.len()];
+		for triangle in &triangles {
+			let corners = triangle.map(|i| position_of[i as usize]);
+			let [a, b, c] = corners.map(|p| positions[p]);
+			let cross = (b - a).cross(c - a);
+			let area = cross.length() * 0.5;
+			if area <= f32::EPSILON {
+				continue;
+			}
+			let normal = cross / (2.0 * area);
+			let face = Quadric::plane(normal, a, area as f64);
+			for corner in corners {
+				quadrics[corner].add(&face);
+			}
+
+			for k in 0..3 {
+				let (pa, pb) = (corners[k], corners[(k + 1) % 3]);
+				let (va, vb) = (triangle[k], triangle[(k + 1) % 3]);
+				let border = position_edges[&(pa.min(pb), pa.max(pb))] == 1;
+				let seam = vertex_edges[&(va.min(vb), va.max(vb))] == 1;
+				if !border && !seam {
+					continue;
+				}
+				// a plane through the edge, perpendicular to the face
+				let edge = positions[pb] - positions[pa];
+				let constraint = edge.cross(normal).normalize_or_zero();
+				let weight = BORDER_WEIGHT * edge.length_squared() as f64;
+				let quadric = Quadric::plane(constraint, positions[pa], weight);
+				quadrics[pa].add(&quadric);
+				quadrics[pb].add(&quadric);
+				if border {
+					border_neighbors[pa].push(pb);
+					border_neighbors[pb].push(pa);
+				}
+			}
+		}
+
+		let position_count = positions.len();
+		let mut simplifier = Self {
+			position_of,
+			positions,
+			copies,
+			quadrics,
+			versions: vec![0; position_count],
+			removed: vec![false; position_count],
+			border_neighbors,
+			alive: vec![true; triangles.len()],
+			live_triangles: triangles.len(),
+			triangles,
+			triangles_of,
+			queue: BinaryHeap::new(),
+		};
+		for (a, b) in position_edges.keys() {
+			simplifier.push(*a, *b);
+			simplifier.push(*b, *a);
+		}
+		simplifier
+	}
+
+	/// Queues collapsing `from` onto `to` unless it would move a border.
+	fn push(&mut self, from: usize, to: usize) {
+		if from == to {
+			return;
+		}
+		if !self.border_neighbors[from].is_empty() && !self.border_neighbors[from].contains(&to) {
+			return;
+		}
+		let mut quadric = self.quadrics[from];
+		quadric.add(&self.quadrics[to]);
+		let cost = quadric.error(self.positions[to]).max(0.0);
+		self.queue.push(Reverse((
+			cost.to_bits(),
+			from,
+			to,
+			self.versions[from],
+			self.versions[to],
+		)));
+	}
+
+	fn run(&mut self, target_triangles: usize) {
+		while self.live_triangles > target_triangles {
+			let Reverse((_, from, to, from_version, to_version)) = match self.queue.pop() {
+				Some(entry) => entry,
+				None => break,
+			};
+			if self.removed[from]
+				|| self.removed[to]
+				|| self.versions[from] != from_version
+				|| self.versions[to] != to_version
+			{
+				continue;
+			}
+			self.collapse(from, to);
+		}
+	}
+
+	/// Moves every copy of `from` onto a copy of `to` it shares an edge with.
+	/// Refused when a copy has no such partner, when seam copies would merge, or
+	/// when a triangle would flip.
+	fn collapse(&mut self, from: usize, to: usize) -> bool {
+		let mut targets = HashMap::new();
+		for copy in &self.copies[from] {
+			let partner = self.triangles_of[from]
+				.iter()
+				.filter(|t| self.alive[**t])
+				.flat_map(|t| self.triangles[*t])
+				.find(|corner| {
+					self.position_of[*corner as usize] == to && {
+						self.triangles_of[from].iter().any(|t| {
+							self.alive[*t]
+								&& self.triangles[*t].contains(copy)
+								&& self.triangles[*t].contains(corner)
+						})
+					}
+				});
+			match partner {
+				Some(partner) => {
+					targets.insert(*copy, partner);
+				}
+				None => return false,
+			}
+		}
+		let mut partners: Vec<u32> = targets.values().copied().collect();
+		partners.sort_unstable();
+		partners.dedup();
+		if partners.len() != targets.len() {
+			return false;
+		}
+
+		let destination = self.positions[to];
+		for t in &self.triangles_of[from] {
+			let triangle = self.triangles[*t];
+			if !self.alive[*t] || self.touches(&triangle, to) {
+				continue;
+			}
+			let corners = triangle.map(|i| self.positions[self.position_of[i as usize]]);
+			let moved = triangle.map(|i| {
+				if self.position_of[i as usize] == from {
+					destination
+				} else {
+					self.positions[self.position_of[i as usize]]
+				}
+			});
+			let before = (corners[1] - corners[0]).cross(corners[2] - corners[0]);
+			let after = (moved[1] - moved[0]).cross(moved[2] - moved[0]);
+			if before.dot(after) <= 0.0 {
+				return false;
+			}
+		}
+
+		let moved_triangles = std::mem::take(&mut self.triangles_of[from]);
+		for t in moved_triangles {
+			if !self.alive[t] {
+				continue;
+			}
+			if self.touches(&self.triangles[t], to) {
+				self.alive[t] = false;
+				self.live_triangles -= 1;
+				continue;
+			}
+			for corner in &mut self.triangles[t] {
+				if let Some(target) = targets.get(corner) {
+					*corner = *target;
+				}
+			}
+			self.triangles_of[to].push(t);
+		}
+		let alive = &self.alive;
+		self.triangles_of[to].retain(|t| alive[*t]);
+		self.triangles_of[to].sort_unstable();
+		self.triangles_of[to].dedup();
+
+		let from_quadric = self.quadrics[from];
+		self.quadrics[to].add(&from_quadric);
+		self.removed[from] = true;
+		self.copies[from].clear();
+		self.versions[to] += 1;
+
+		for neighbor in std::mem::take(&mut self.border_neighbors[from]) {
+			self.border_neighbors[neighbor].retain(|p| *p != from);
+			if neighbor != to {
+				self.border_neighbors[neighbor].push(to);
+				self.border_neighbors[to].push(neighbor);
+			}
+		}
+
+		let mut neighbors: Vec<usize> = self.triangles_of[to]
+			.iter()
+			.flat_map(|t| self.triangles[*t])
+			.map(|corner| self.position_of[corner as usize])
+			.filter(|position| *position != to)
+			.collect();
+		neighbors.sort_unstable();
+		neighbors.dedup();
+		for neighbor in neighbors {
+			self.push(to, neighbor);
+			self.push(neighbor, to);
+		}
+		true
+	}
+
+	fn touches(&self, triangle: &[u32; 3], position: usize) -> bool {
+		triangle
+			.iter()
+			.any(|corner| self.position_of[*corner as usize] == position)
+	}
+
+	/// Keeps the live triangles and the vertices they use.
+	fn finish(self, generator: &mut MeshGenerator) {
+		let mut remap: HashMap<u32, u32> = HashMap::new();
+		let mut vertices = vec![];
+		let mut indices = Vec::with_capacity(self.live_triangles * 3);
+		for (triangle, alive) in self.triangles.iter().zip(&self.alive) {
+			if !alive {
+				continue;
+			}
+			for corner in triangle {
+				let index = *remap.entry(*corner).or_insert_with(|| {
+					vertices.push(generator.vertices[*corner as usize]);
+					vertices.len() as u32 - 1
+				});
+				indices.push(index);
+			}
+		}
+		generator.vertices = vertices;
+		generator.indices = indices;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::mesh::SphereUvs;
+
+	fn triangle_count(generator: &MeshGenerator) -> usize {
+		generator.indices.len() / 3
+	}
+	fn area(generator: &MeshGenerator) -> f32 {
+		generator
+			.indices
+			.chunks_exact(3)
+			.map(|triangle| {
+				let corners = [0, 1, 2].map(|k| triangle[k] as usize);
+				generator.face_normal(corners).length() * 0.5
+			})
+			.sum()
+	}
+
+	#[test]
+	fn plane_borders_stay_fixed() {
+		let mut generator = MeshGenerator::default();
+		generator.plane(2.0, 2.0, 8, 8);
+		generator.simplify(0.1);
+		assert!(triangle_count(&generator) <= 12);
+
+		// the outline is kept, so the plane still covers the same area
+		assert!((area(&generator) - 4.0).abs() < 1e-4);
+		let positions: Vec<[f32; 3]> = generator.vertices.iter().map(|v| v.position).collect();
+		for corner in [
+			[-1.0, 0.0, -1.0],
+			[1.0, 0.0, -1.0],
+			[1.0, 0.0, 1.0],
+			[-1.0, 0.0, 1.0],
+		] {
+			assert!(positions.contains(&corner), "lost the corner {:?}", corner);
+		}
+		super::super::assert_faces_match_normals("plane", &generator);
+	}
+
+	#[test]
+	fn icosphere_reaches_target_ratio() {
+		let mut generator = MeshGenerator::default();
+		generator.icosphere(1.0, 3, SphereUvs::Equirectangular);
+		let before = triangle_count(&generator);
+		generator.simplify(0.25);
+		let after = triangle_count(&generator);
+		assert!(
+			after <= before / 4,
+			"{} of {} triangles left",
+			after,
+			before
+		);
+		assert!(
+			after >= before / 8,
+			"{} of {} triangles left",
+			after,
+			before
+		);
+
+		let report = generator.validate();
+		assert!(report.is_valid(), "{}", report);
+		super::super::assert_faces_match_normals("icosphere", &generator);
+	}
+
+	#[test]
+	fn cuboid_keeps_its_seams() {
+		let mut generator = MeshGenerator::default();
+		generator.cuboid(1.0, 1.0, 1.0);
+		generator.simplify(0.0);
+
+		// every corner keeps a copy with the normal of each of its faces
+		let mut normals: HashMap<[u32; 3], Vec<[f32; 3]>> = HashMap::new();
+		for vertex in &generator.vertices {
+			let key = vertex.position.map(f32::to_bits);
+			normals.entry(key).or_default().push(vertex.normal);
+		}
+		assert_eq!(normals.len(), 8);
+		for copies in normals.values_mut() {
+			copies.sort_by(|a, b| a.partial_cmp(b).unwrap());
+			copies.dedup();
+			assert_eq!(copies.len(), 3);
+		}
+		assert!((area(&generator) - 6.0).abs() < 1e-4);
+		super::super::assert_faces_match_normals("cuboid", &generator);
+	}
+
+	#[test]
+	fn lod_chain_levels_shrink() {
+		let mut generator = MeshGenerator::default();
+		generator.icosphere(1.0, 3, SphereUvs::Equirectangular);
+		let ratios = [0.5, 0.25, 0.1];
+		let levels = generator.lod_chain(&ratios);
+		assert_eq!(levels.len(), ratios.len());
+
+		let before = triangle_count(&generator);
+		let mut previous = before;
+		for (level, ratio) in levels.iter().zip(ratios) {
+			let count = triangle_count(level);
+			assert!(count <= previous);
+			assert!(count as f32 <= before as f32 * ratio);
+			assert!(level.validate().is_valid(), "{}", level.validate());
+			previous = count;
+		}
+	}
+}
@@ -0,0 +1,390 @@
+// File: mesh/sphere.rs
+
+use super::shapes::cube_faces;
+use super::MeshGenerator;
+use crate::vertex::Vertex;
+
+use bevy::math::{Vec2, Vec3};
+
+use core::f32::consts::PI;
+use std::collections::HashMap;
+
+/// How the sphere generators lay out uvs.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SphereUvs {
+	/// Longitude and latitude like `uv_sphere`, for equirectangular images such
+	/// as `earth.png`. `u` is 0.5 at +z and grows towards +x, `v` is 0 at +y.
+	/// Triangles crossing the seam get `u` slightly outside 0..1, so sample with
+	/// `AddressMode::Repeat`.
+	Equirectangular,
+	/// A 3 by 2 atlas of cube faces, +x, -x, +y in the top row and -y, +z, -z in
+	/// the bottom one. Icosphere triangles on a face's border reach a little
+	/// into the neighboring tile.
+	CubeFaces,
+}
+
+/// How `cube_sphere` moves the points of a cube onto the sphere.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CubeSphereMapping {
+	/// Normalizes the points. Triangles near the face centers end up bigger than
+	/// the ones near the corners.
+	Normalized,
+	/// Spreads the points more evenly than `Normalized`.
+	Spherified,
+}
+
+const ICOSAHEDRON_TRIANGLES: [[u32; 3]; 20] = [
+	[0, 11, 5],
+	[0, 5, 1],
+	[0, 1, 7],
+	[0, 7, 10],
+	[0, 10, 11],
+	[1, 5, 9],
+	[5, 11, 4],
+	[11, 10, 2],
+	[10, 7, 6],
+	[7, 1, 8],
+	[3, 9, 4],
+	[3, 4, 2],
+	[3, 2, 6],
+	[3, 6, 8],
+	[3, 8, 9],
+	[4, 9, 5],
+	[2, 4, 11],
+	[6, 2, 10],
+	[8, 6, 7],
+	[9, 8, 1],
+];
+
+/// Points closer than this to the y axis are poles, where longitude is undefined.
+const POLE_EPSILON: f32 = 1e-6;
+
+impl MeshGenerator {
+	/// A subdivided icosahedron. Every subdivision splits each triangle into
+	/// four, for `20 * 4^subdivisions` triangles of nearly the same size.
+	pub fn icosphere(&mut self, radius: f32, subdivisions: usize, uvs: SphereUvs) -> &mut Self {
+		self.clear();
+
+		let t = (1.0 + 5.0f32.sqrt()) * 0.5;
+		let mut positions: Vec<Vec3> = [
+			[-1.0, t, 0.0],
+			[1.0, t, 0.0],
+			[-1.0, -t, 0.0],
+			[1.0, -t, 0.0],
+			[0.0, -1.0, t],
+			[0.0, 1.0, t],
+			[0.0, -1.0, -t],
+			[0.0, 1.0, -t],
+			[t, 0.0, -1.0],
+			[t, 0.0, 1.0],
+			[-t, 0.0, -1.0],
+			[-t, 0.0, 1.0],
+		]
+		.iter()
+		.map(|position| Vec3::from(*position).normalize())
+		.collect();
+		let mut triangles = ICOSAHEDRON_TRIANGLES.to_vec();
+
+		for _ in 0..subdivisions {
+			// triangles sharing an edge share its midpoint
+			let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+			let mut midpoint = |a: u32, b: u32| {
+				*midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+					let position = (positions[a as usize] + positions[b as usize]).normalize();
+					positions.push(position);
+					positions.len() as u32 - 1
+				})
+			};
+			triangles = triangles
+				.iter()
+				.flat_map(|&[a, b, c]| {
+					let ab = midpoint(a, b);
+					let bc = midpoint(b, c);
+					let ca = midpoint(c, a);
+					[[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+				})
+				.collect();
+		}
+
+		self.add_sphere(radius, &positions, &triangles, uvs);
+		self
+	}
+	/// A cube with `segments` by `segments` quads per face, blown up into a
+	/// sphere. Vertices on the cube's edges are shared between faces.
+	pub fn cube_sphere(
+		&mut self,
+		radius: f32,
+		segments: usize,
+		mapping: CubeSphereMapping,
+		uvs: SphereUvs,
+	) -> &mut Self {
+		assert!(segments > 0, "a cube sphere needs at least one segment");
+		self.clear();
+
+		let mut positions = vec![];
+		let mut triangles = vec![];
+		// keyed by the point on the cube scaled to integers, so edges weld exactly
+		let mut welded: HashMap<[i32; 3], u32> = HashMap::new();
+		let row = segments + 1;
+		for (normal, right, up) in cube_faces() {
+			let mut grid = Vec::with_capacity(row * row);
+			for j in 0..=segments {
+				let t = 2.0 * j as f32 / segments as f32 - 1.0;
+				for i in 0..=segments {
+					let s = 2.0 * i as f32 / segments as f32 - 1.0;
+					let point = normal + right * s + up * t;
+					let scaled = (point * segments as f32).round();
+					let key = [scaled.x as i32, scaled.y as i32, scaled.z as i32];
+					let index = *welded.entry(key).or_insert_with(|| {
+						positions.push(match mapping {
+							CubeSphereMapping::Normalized => point.normalize(),
+							CubeSphereMapping::Spherified => spherify(point),
+						});
+						positions.len() as u32 - 1
+					});
+					grid.push(index);
+				}
+			}
+			for j in 0..segments {
+				for i in 0..segments {
+					let a = grid[j * row + i];
+					let b = grid[j * row + i + 1];
+					let c = grid[(j + 1) * row + i + 1];
+					let d = grid[(j + 1) * row + i];
+					triangles.push([a, b, c]);
+					triangles.push([a, c, d]);
+				}
+			}
+		}
+
+		self.add_sphere(radius, &positions, &triangles, uvs);
+		self
+	}
+
+	/// Adds the triangles of a welded unit sphere. Vertices are duplicated where
+	/// the uvs of their triangles disagree: along the seam and at the poles for
+	/// `Equirectangular`, along the face edges for `CubeFaces`.
+	fn add_sphere(
+		&mut self,
+		radius: f32,
+		positions: &[Vec3],
+		triangles: &[[u32; 3]],
+		uvs: SphereUvs,
+	) {
+		let mut split: HashMap<(u32, [u32; 2]), u32> = HashMap::new();
+		self.indices.reserve(triangles.len() * 3);
+		for triangle in triangles {
+			let corners = triangle.map(|i| positions[i as usize]);
+			let corner_uvs = match uvs {
+				SphereUvs::Equirectangular => equirectangular_uvs(corners),
+				SphereUvs::CubeFaces => cube_face_uvs(corners),
+			};
+			for ((index, direction), uv) in triangle.iter().zip(corners).zip(corner_uvs) {
+				let next = self.vertices.len() as u32;
+				let vertex = *split
+					.entry((*index, [uv.x.to_bits(), uv.y.to_bits()]))
+					.or_insert(next);
+				if vertex == next {
+					self.add_vertex(Vertex {
+						position: (direction * radius).into(),
+						color: [1.0, 1.0, 1.0],
+						uv: uv.into(),
+						normal: direction.into(),
+						..Default::default()
+					});
+				}
+				self.indices.push(vertex);
+			}
+		}
+		self.compute_angle_weighted_tangents();
+	}
+}
+
+/// Moves a point of the [-1, 1] cube onto the unit sphere keeping the points of
+/// a face's grid evenly spread.
+fn spherify(point: Vec3) -> Vec3 {
+	let squared = point * point;
+	Vec3::new(
+		point.x * (1.0 - squared.y * 0.5 - squared.z * 0.5 + squared.y * squared.z / 3.0).sqrt(),
+		point.y * (1.0 - squared.z * 0.5 - squared.x * 0.5 + squared.z * squared.x / 3.0).sqrt(),
+		point.z * (1.0 - squared.x * 0.5 - squared.y * 0.5 + squared.x * squared.y / 3.0).sqrt(),
+	)
+}
+
+fn equirectangular_uv(direction: Vec3) -> Vec2 {
+	let direction = direction.normalize();
+	Vec2::new(
+		(direction.x.atan2(direction.z) + PI) / (2.0 * PI),
+		direction.y.clamp(-1.0, 1.0).acos() / PI,
+	)
+}
+fn equirectangular_uvs(corners: [Vec3; 3]) -> [Vec2; 3] {
+	let is_pole = |corner: &Vec3| corner.x.abs() < POLE_EPSILON && corner.z.abs() < POLE_EPSILON;
+	let mut uvs = corners.map(equirectangular_uv);
+
+	let (min_u, max_u) = corners
+		.iter()
+		.zip(&uvs)
+		.filter(|(corner, _)| !is_pole(corner))
+		.fold((f32::MAX, f32::MIN), |(min, max), (_, uv)| {
+			(min.min(uv.x), max.max(uv.x))
+		});
+	if max_u - min_u > 0.5 {
+		// the triangle crosses the seam, move all corners to its centroid's side
+		let centroid_u = equirectangular_uv(corners[0] + corners[1] + corners[2]).x;
+		for (corner, uv) in corners.iter().zip(&mut uvs) {
+			if is_pole(corner) {
+				continue;
+			}
+			if centroid_u >= 0.5 && uv.x < 0.5 {
+				uv.x += 1.0;
+			} else if centroid_u < 0.5 && uv.x >= 0.5 {
+				uv.x -= 1.0;
+			}
+		}
+	}
+
+	// longitude is undefined at a pole, take the one of the opposite edge
+	let others: Vec<f32> = corners
+		.iter()
+		.zip(&uvs)
+		.filter(|(corner, _)| !is_pole(corner))
+		.map(|(_, uv)| uv.x)
+		.collect();
+	let pole_u = others.iter().sum::<f32>() / others.len().max(1) as f32;
+	for (corner, uv) in corners.iter().zip(&mut uvs) {
+		if is_pole(corner) {
+			uv.x = pole_u;
+		}
+	}
+	uvs
+}
+/// Projects the corners onto the cube face the triangle faces most.
+fn cube_face_uvs(corners: [Vec3; 3]) -> [Vec2; 3] {
+	let centroid = corners[0] + corners[1] + corners[2];
+	let faces = cube_faces();
+	let face = (0..faces.len())
+		.max_by(|a, b| {
+			let a = centroid.dot(faces[*a].0);
+			let b = centroid.dot(faces[*b].0);
+			a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+		})
+		.unwrap_or(0);
+	let (normal, right, up) = faces[face];
+	let tile = Vec2::new((face % 3) as f32, (face / 3) as f32);
+	corners.map(|corner| {
+		let depth = corner.dot(normal);
+		let local = Vec2::new(
+			0.5 + 0.5 * corner.dot(right) / depth,
+			0.5 - 0.5 * corner.dot(up) / depth,
+		);
+		(tile + local) / Vec2::new(3.0, 2.0)
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn spheres() -> Vec<(String, MeshGenerator)> {
+		let mut spheres = vec![];
+		for uvs in [SphereUvs::Equirectangular, SphereUvs::CubeFaces] {
+			for subdivisions in 0..=3 {
+				let mut generator = MeshGenerator::default();
+				generator.icosphere(1.0, subdivisions, uvs);
+				spheres.push((format!("icosphere {} {:?}", subdivisions, uvs), generator));
+			}
+			for mapping in [CubeSphereMapping::Normalized, CubeSphereMapping::Spherified] {
+				for segments in [1, 4] {
+					let mut generator = MeshGenerator::default();
+					generator.cube_sphere(1.0, segments, mapping, uvs);
+					spheres.push((
+						format!("cube_sphere {} {:?} {:?}", segments, mapping, uvs),
+						generator,
+					));
+				}
+			}
+		}
+		spheres
+	}
+
+	/// The vertices at each distinct position.
+	fn copies(generator: &MeshGenerator) -> HashMap<[u32; 3], Vec<&Vertex>> {
+		let mut copies: HashMap<[u32; 3], Vec<&Vertex>> = HashMap::new();
+		for vertex in &generator.vertices {
+			let key = vertex.position.map(|x| (x + 0.0).to_bits());
+			copies.entry(key).or_default().push(vertex);
+		}
+		copies
+	}
+
+	#[test]
+	fn faces_match_normals() {
+		for (name, generator) in spheres() {
+			super::super::assert_faces_match_normals(&name, &generator);
+			assert!(
+				generator.validate().is_valid(),
+				"{}: {}",
+				name,
+				generator.validate()
+			);
+		}
+	}
+
+	#[test]
+	fn positions_are_welded() {
+		for subdivisions in 0..=3 {
+			let mut generator = MeshGenerator::default();
+			generator.icosphere(1.0, subdivisions, SphereUvs::CubeFaces);
+			assert_eq!(
+				copies(&generator).len(),
+				10 * 4usize.pow(subdivisions as u32) + 2
+			);
+		}
+		for segments in [1, 4] {
+			let mut generator = MeshGenerator::default();
+			generator.cube_sphere(
+				1.0,
+				segments,
+				CubeSphereMapping::Spherified,
+				SphereUvs::Equirectangular,
+			);
+			assert_eq!(copies(&generator).len(), 6 * segments * segments + 2);
+		}
+	}
+
+	#[test]
+	fn only_seam_vertices_are_copied() {
+		for (name, generator) in spheres() {
+			for vertices in copies(&generator).values() {
+				for (i, a) in vertices.iter().enumerate() {
+					for b in &vertices[i + 1..] {
+						// copies exist only for their different uvs
+						assert_ne!(a.uv, b.uv, "{}: duplicate vertex {:?}", name, a.position);
+					}
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn equirectangular_copies_wrap_around() {
+		for (name, generator) in spheres() {
+			if !name.ends_with("Equirectangular") {
+				continue;
+			}
+			for vertices in copies(&generator).values() {
+				let [x, _, z] = vertices[0].position;
+				if x.abs() < POLE_EPSILON && z.abs() < POLE_EPSILON {
+					continue;
+				}
+				// away from the poles the copies are a whole turn apart
+				for vertex in &vertices[1..] {
+					assert_eq!(vertex.uv[1], vertices[0].uv[1], "{}", name);
+					let turns = vertex.uv[0] - vertices[0].uv[0];
+					assert!((turns.abs() - 1.0).abs() < 1e-5, "{}: {}", name, turns);
+				}
+			}
+		}
+	}
+}
@@ -0,0 +1,409 @@
+// File: mesh/terrain.rs
+
+use super::{MeshData, MeshGenerator, ShouldDraw};
+use crate::vertex::Vertex;
+
+use bevy::asset::Assets;
+use bevy::ecs::entity::Entity;
+use bevy::ecs::system::Commands;
+use bevy::math::{Vec2, Vec3};
+use bevy::transform::components::{GlobalTransform, Transform};
+
+use std::ops::RangeInclusive;
+
+/// A grid of height samples, 0 to 1 for heightmap images.
+#[derive(Clone, Debug)]
+pub struct Heightmap {
+	width: usize,
+	depth: usize,
+	heights: Vec<f32>,
+}
+impl Heightmap {
+	/// Uses the luminance of `image`, black is 0 and white 1.
+	pub fn from_image(image: &image::DynamicImage) -> Self {
+		let luma = image.to_luma16();
+		Self {
+			width: luma.width() as usize,
+			depth: luma.height() as usize,
+			heights: luma
+				.pixels()
+				.map(|pixel| pixel.0[0] as f32 / u16::MAX as f32)
+				.collect(),
+		}
+	}
+	/// Samples `height(x, z)` on a `width` by `depth` grid.
+	pub fn from_fn(
+		width: usize,
+		depth: usize,
+		mut height: impl FnMut(usize, usize) -> f32,
+	) -> Self {
+		let mut heights = Vec::with_capacity(width * depth);
+		for z in 0..depth {
+			for x in 0..width {
+				heights.push(height(x, z));
+			}
+		}
+		Self {
+			width,
+			depth,
+			heights,
+		}
+	}
+	pub fn width(&self) -> usize {
+		self.width
+	}
+	pub fn depth(&self) -> usize {
+		self.depth
+	}
+	/// The sample at `(x, z)`, clamped to the grid.
+	pub fn height(&self, x: usize, z: usize) -> f32 {
+		let x = x.min(self.width - 1);
+		let z = z.min(self.depth - 1);
+		self.heights[z * self.width + x]
+	}
+}
+
+/// How a `Heightmap` is turned into meshes.
+#[derive(Copy, Clone, Debug)]
+pub struct TerrainSettings {
+	/// World size of the whole terrain in x and z, centered on the origin.
+	pub size: Vec2,
+	/// World height of a sample of 1.
+	pub height_scale: f32,
+	/// Quads along each side of a chunk. The default of 64 keeps chunks small
+	/// enough for 16 bit indices.
+	pub chunk_segments: usize,
+	/// How far the skirts around chunks reach below their edges.
+	pub skirt_depth: f32,
+}
+impl Default for TerrainSettings {
+	fn default() -> Self {
+		Self {
+			size: Vec2::new(100.0, 100.0),
+			height_scale: 10.0,
+			chunk_segments: 64,
+			skirt_depth: 1.0,
+		}
+	}
+}
+
+/// A piece of a terrain. `mesh` is relative to `translation`, the chunk's
+/// center at height 0.
+pub struct TerrainChunk {
+	pub x: usize,
+	pub z: usize,
+	pub translation: Vec3,
+	pub mesh: MeshGenerator,
+}
+impl TerrainChunk {
+	/// Spawns the chunk as its own drawable entity.
+	pub fn spawn(self, commands: &mut Commands, mesh_assets: &mut Assets<MeshData>) -> Entity {
+		commands
+			.spawn()
+			.insert(mesh_assets.add(MeshData::from(self.mesh)))
+			.insert(Transform::from_translation(self.translation))
+			.insert(GlobalTransform::default())
+			.insert(ShouldDraw)
+			.id()
+	}
+}
+
+impl MeshGenerator {
+	/// The whole heightmap as one grid facing +y.
+	pub fn terrain(&mut self, heightmap: &Heightmap, settings: &TerrainSettings) -> &mut Self {
+		assert!(
+			heightmap.width >= 2 && heightmap.depth >= 2,
+			"a terrain needs at least 2 by 2 samples"
+		);
+		self.clear();
+		self.add_terrain_grid(
+			heightmap,
+			settings,
+			0..=heightmap.width - 1,
+			0..=heightmap.depth - 1,
+			Vec3::ZERO,
+		);
+		self.compute_angle_weighted_tangents();
+		self
+	}
+	/// Splits the heightmap into chunks of `settings.chunk_segments` quads per
+	/// side. Normals are computed from the whole heightmap so lighting matches
+	/// across chunks, and skirts hanging from the chunk edges hide cracks where
+	/// neighbors are drawn at different detail.
+	pub fn terrain_chunks(heightmap: &Heightmap, settings: &TerrainSettings) -> Vec<TerrainChunk> {
+		assert!(
+			heightmap.width >= 2 && heightmap.depth >= 2,
+			"a terrain needs at least 2 by 2 samples"
+		);
+		assert!(
+			settings.chunk_segments > 0,
+			"a terrain chunk needs at least one segment"
+		);
+		let segments = settings.chunk_segments;
+		let chunks_x = (heightmap.width - 2) / segments + 1;
+		let chunks_z = (heightmap.depth - 2) / segments + 1;
+
+		let mut chunks = Vec::with_capacity(chunks_x * chunks_z);
+		for z in 0..chunks_z {
+			for x in 0..chunks_x {
+				let range_x = x * segments..=((x + 1) * segments).min(heightmap.width - 1);
+				let range_z = z * segments..=((z + 1) * segments).min(heightmap.depth - 1);
+				let min = terrain_position(heightmap, settings, *range_x.start(), *range_z.start());
+				let max = terrain_position(heightmap, settings, *range_x.end(), *range_z.end());
+				let translation = Vec3::new((min.x + max.x) * 0.5, 0.0, (min.y + max.y) * 0.5);
+
+				let mut mesh = MeshGenerator::default();
+				let columns =
+					mesh.add_terrain_grid(heightmap, settings, range_x, range_z, translation);
+				mesh.add_terrain_skirt(columns, settings.skirt_depth);
+				mesh.compute_angle_weighted_tangents();
+				chunks.push(TerrainChunk {
+					x,
+					z,
+					translation,
+					mesh,
+				});
+			}
+		}
+		chunks
+	}
+
+	/// Adds the samples in the given ranges, row by row along x, relative to
+	/// `origin`. Returns the number of vertices per row.
+	fn add_terrain_grid(
+		&mut self,
+		heightmap: &Heightmap,
+		settings: &TerrainSettings,
+		range_x: RangeInclusive<usize>,
+		range_z: RangeInclusive<usize>,
+		origin: Vec3,
+	) -> usize {
+		let first = self.vertices.len() as u32;
+		let columns = range_x.end() - range_x.start() + 1;
+		for z in range_z.clone() {
+			for x in range_x.clone() {
+				let xz = terrain_position(heightmap, settings, x, z);
+				let position =
+					Vec3::new(xz.x, heightmap.height(x, z) * settings.height_scale, xz.y);
+				self.add_vertex(Vertex {
+					position: (position - origin).into(),
+					color: [1.0, 1.0, 1.0],
+					uv: [
+						x as f32 / (heightmap.width - 1) as f32,
+						z as f32 / (heightmap.depth - 1) as f32,
+					],
+					normal: terrain_normal(heightmap, settings, x, z).into(),
+					..Default::default()
+				});
+			}
+		}
+
+		let row_length = columns as u32;
+		let rows = (range_z.end() - range_z.start()) as u32;
+		for row in 0..rows {
+			for column in 0..row_length - 1 {
+				let a = first + row * row_length + column;
+				let b = a + row_length;
+				self.add_quad(a, b, b + 1, a + 1);
+			}
+		}
+		columns
+	}
+	/// Hangs a wall of `depth` below the outline of the grid added last, which
+	/// has `columns` vertices per row. The walls keep the normals of their edge,
+	/// they only show through cracks and should be lit like the terrain around
+	/// them rather than stand out as vertical faces.
+	fn add_terrain_skirt(&mut self, columns: usize, depth: f32) {
+		let rows = self.vertices.len() / columns;
+		// counter clockwise seen from above, so the walls face outwards
+		let mut outline = Vec::with_capacity(2 * (columns + rows));
+		outline.extend((0..rows - 1).map(|row| row * columns));
+		outline.extend((0..columns - 1).map(|column| (rows - 1) * columns + column));
+		outline.extend((1..rows).rev().map(|row| row * columns + columns - 1));
+		outline.extend((1..columns).rev());
+
+		let first_skirt = self.vertices.len() as u32;
+		for edge in &outline {
+			let mut vertex = self.vertices[*edge];
+			vertex.position[1] -= depth;
+			self.add_vertex(vertex);
+		}
+		for k in 0..outline.len() {
+			let next = (k + 1) % outline.len();
+			let (edge, next_edge) = (outline[k] as u32, outline[next] as u32);
+			let (skirt, next_skirt) = (first_skirt + k as u32, first_skirt + next as u32);
+			self.add_triangle(edge, skirt, next_skirt);
+			self.add_triangle(edge, next_skirt, next_edge);
+		}
+	}
+}
+
+/// World x and z of a sample.
+fn terrain_position(heightmap: &Heightmap, settings: &TerrainSettings, x: usize, z: usize) -> Vec2 {
+	let step =
+		settings.size / Vec2::new((heightmap.width - 1) as f32, (heightmap.depth - 1) as f32);
+	Vec2::new(x as f32, z as f32) * step - settings.size * 0.5
+}
+
+/// From the slope between the neighboring samples.
+fn terrain_normal(heightmap: &Heightmap, settings: &TerrainSettings, x: usize, z: usize) -> Vec3 {
+	let (left, right) = (x.saturating_sub(1), (x + 1).min(heightmap.width - 1));
+	let (back, front) = (z.saturating_sub(1), (z + 1).min(heightmap.depth - 1));
+	let run = terrain_position(heightmap, settings, right, front)
+		- terrain_position(heightmap, settings, left, back);
+	let slope_x =
+		(heightmap.height(right, z) - heightmap.height(left, z)) * settings.height_scale / run.x;
+	let slope_z =
+		(heightmap.height(x, front) - heightmap.height(x, back)) * settings.height_scale / run.y;
+	Vec3::new(-slope_x, 1.0, -slope_z).normalize()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::mesh::assert_faces_match_normals;
+
+	fn hills() -> Heightmap {
+		Heightmap::from_fn(10, 7, |x, z| {
+			((x as f32 * 0.7).sin() + (z as f32 * 0.5).cos()) * 0.25 + 0.5
+		})
+	}
+
+	fn settings() -> TerrainSettings {
+		TerrainSettings {
+			size: Vec2::new(9.0, 12.0),
+			height_scale: 2.0,
+			chunk_segments: 4,
+			skirt_depth: 0.5,
+		}
+	}
+
+	/// Columns and rows of the samples in `chunk`.
+	fn chunk_grid(heightmap: &Heightmap, chunk: &TerrainChunk) -> (usize, usize) {
+		let segments = settings().chunk_segments;
+		let end_x = ((chunk.x + 1) * segments).min(heightmap.width() - 1);
+		let end_z = ((chunk.z + 1) * segments).min(heightmap.depth() - 1);
+		(
+			end_x - chunk.x * segments + 1,
+			end_z - chunk.z * segments + 1,
+		)
+	}
+
+	#[test]
+	fn heightmap_from_image_uses_luminance() {
+		let image = image::GrayImage::from_raw(2, 2, vec![0, 255, 51, 255]).unwrap();
+		let heightmap = Heightmap::from_image(&image::DynamicImage::ImageLuma8(image));
+		assert_eq!((heightmap.width(), heightmap.depth()), (2, 2));
+		assert_eq!(heightmap.height(0, 0), 0.0);
+		assert_eq!(heightmap.height(1, 0), 1.0);
+		assert!((heightmap.height(0, 1) - 0.2).abs() < 1e-6);
+		// samples outside the grid are clamped to its border
+		assert_eq!(heightmap.height(5, 0), heightmap.height(1, 0));
+		assert_eq!(heightmap.height(0, 9), heightmap.height(0, 1));
+	}
+
+	#[test]
+	fn whole_terrain_counts_and_winding() {
+		let heightmap = hills();
+		let mut generator = MeshGenerator::default();
+		generator.terrain(&heightmap, &settings());
+		assert_eq!(generator.vertices.len(), 10 * 7);
+		assert_eq!(generator.indices.len(), 9 * 6 * 6);
+		assert_faces_match_normals("terrain", &generator);
+		assert!(generator.validate().is_valid());
+
+		let corner = Vec3::from(generator.vertices[0].position);
+		let expected = Vec3::new(-4.5, heightmap.height(0, 0) * 2.0, -6.0);
+		assert!(corner.distance(expected) < 1e-6);
+	}
+
+	#[test]
+	fn chunk_and_vertex_counts() {
+		let heightmap = hills();
+		let chunks = MeshGenerator::terrain_chunks(&heightmap, &settings());
+		// 9 by 6 quads in chunks of 4, the last column and row are smaller
+		assert_eq!(chunks.len(), 3 * 2);
+		let grids: Vec<_> = chunks
+			.iter()
+			.map(|chunk| chunk_grid(&heightmap, chunk))
+			.collect();
+		assert_eq!(grids, [(5, 5), (5, 5), (2, 5), (5, 3), (5, 3), (2, 3)]);
+
+		for (chunk, (columns, rows)) in chunks.iter().zip(grids) {
+			let outline = 2 * (columns + rows) - 4;
+			assert_eq!(chunk.mesh.vertices.len(), columns * rows + outline);
+			assert_eq!(
+				chunk.mesh.indices.len(),
+				(columns - 1) * (rows - 1) * 6 + outline * 6
+			);
+		}
+	}
+
+	#[test]
+	fn chunk_samples_match_the_whole_terrain() {
+		let heightmap = hills();
+		let settings = settings();
+		let mut whole = MeshGenerator::default();
+		whole.terrain(&heightmap, &settings);
+
+		// samples on shared edges belong to every chunk touching them
+		let mut seen = vec![0; heightmap.width() * heightmap.depth()];
+		for chunk in MeshGenerator::terrain_chunks(&heightmap, &settings) {
+			let (columns, rows) = chunk_grid(&heightmap, &chunk);
+			for vertex in &chunk.mesh.vertices[..columns * rows] {
+				let x = (vertex.uv[0] * (heightmap.width() - 1) as f32).round() as usize;
+				let z = (vertex.uv[1] * (heightmap.depth() - 1) as f32).round() as usize;
+				let expected = &whole.vertices[z * heightmap.width() + x];
+				let position = Vec3::from(vertex.position) + chunk.translation;
+				assert!(position.distance(expected.position.into()) < 1e-5);
+				assert_eq!(vertex.normal, expected.normal);
+				seen[z * heightmap.width() + x] += 1;
+			}
+		}
+		assert!(seen.iter().all(|count| *count >= 1));
+		// (4, 4) is a corner of four chunks, (4, 0) is on the edge of two
+		assert_eq!(seen[4 * heightmap.width() + 4], 4);
+		assert_eq!(seen[4], 2);
+		assert_eq!(seen[1], 1);
+	}
+
+	#[test]
+	fn skirts_face_outwards() {
+		let heightmap = hills();
+		for chunk in MeshGenerator::terrain_chunks(&heightmap, &settings()) {
+			let mesh = &chunk.mesh;
+			let report = mesh.validate();
+			assert!(
+				report.is_valid(),
+				"chunk {} {}: {}",
+				chunk.x,
+				chunk.z,
+				report
+			);
+
+			let (columns, rows) = chunk_grid(&heightmap, &chunk);
+			let grid_indices = (columns - 1) * (rows - 1) * 6;
+			let grid = MeshGenerator {
+				vertices: mesh.vertices.clone(),
+				indices: mesh.indices[..grid_indices].to_vec(),
+			};
+			assert_faces_match_normals("terrain chunk", &grid);
+
+			for triangle in mesh.indices[grid_indices..].chunks_exact(3) {
+				let corners = [0, 1, 2].map(|k| triangle[k] as usize);
+				let normal = mesh.face_normal(corners).normalize();
+				let center = corners.iter().fold(Vec3::ZERO, |sum, corner| {
+					sum + Vec3::from(mesh.vertices[*corner].position)
+				}) / 3.0;
+				// the chunk's mesh is centered on its translation
+				assert!(normal.y.abs() < 1e-5);
+				assert!(normal.x * center.x + normal.z * center.z > 0.0);
+			}
+
+			// skirt vertices hang `skirt_depth` below their edge
+			let skirt = &mesh.vertices[columns * rows..];
+			assert_eq!(skirt[0].position[1], mesh.vertices[0].position[1] - 0.5);
+		}
+	}
+}
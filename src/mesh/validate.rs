@@ -0,0 +1,348 @@
+// File: mesh/validate.rs
+
+use super::MeshGenerator;
+use crate::vertex::Vertex;
+
+use bevy::math::Vec3;
+
+use std::collections::HashMap;
+use std::fmt;
+
+/// How far a normal's length may be from 1.
+const NORMAL_TOLERANCE: f32 = 1e-3;
+
+/// Problems found by `MeshGenerator::validate`. Triangles are numbered in index
+/// order. Edges are pairs of vertex indices, vertices split along seams count
+/// as one where their positions are equal.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MeshReport {
+	/// Indices left over after the last full triangle.
+	pub trailing_indices: usize,
+	/// Triangles using an index past the end of the vertices.
+	pub out_of_range_indices: Vec<usize>,
+	/// Vertices with a NaN or infinite position.
+	pub non_finite_positions: Vec<usize>,
+	/// Triangles with two equal corners or no area.
+	pub degenerate_triangles: Vec<usize>,
+	/// Triangles using the same corners as an earlier one, in any order.
+	pub duplicate_triangles: Vec<usize>,
+	/// Edges shared by more than two triangles.
+	pub non_manifold_edges: Vec<[u32; 2]>,
+	/// Edges two triangles run along in the same direction, so one of them is
+	/// wound the other way.
+	pub inconsistent_winding: Vec<[u32; 2]>,
+	/// Vertices whose normal is not unit length.
+	pub unnormalized_normals: Vec<usize>,
+}
+impl MeshReport {
+	pub fn is_valid(&self) -> bool {
+		self == &Self::default()
+	}
+}
+impl fmt::Display for MeshReport {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if self.is_valid() {
+			return write!(f, "no problems");
+		}
+		let counts = [
+			(self.trailing_indices, "trailing indices"),
+			(
+				self.out_of_range_indices.len(),
+				"triangles with out of range indices",
+			),
+			(self.non_finite_positions.len(), "non finite positions"),
+			(self.degenerate_triangles.len(), "degenerate triangles"),
+			(self.duplicate_triangles.len(), "duplicate triangles"),
+			(self.non_manifold_edges.len(), "non manifold edges"),
+			(
+				self.inconsistent_winding.len(),
+				"edges with inconsistent winding",
+			),
+			(self.unnormalized_normals.len(), "unnormalized normals"),
+		];
+		let problems: Vec<String> = counts
+			.iter()
+			.filter(|(count, _)| *count > 0)
+			.map(|(count, problem)| format!("{} {}", count, problem))
+			.collect();
+		write!(f, "{}", problems.join(", "))
+	}
+}
+
+impl MeshGenerator {
+	/// Checks the generated geometry for data that draws as garbage or breaks
+	/// other passes. Triangles with out of range indices or non finite corners
+	/// are left out of the later checks.
+	pub fn validate(&self) -> MeshReport {
+		validate(&self.vertices, &self.indices)
+	}
+}
+
+pub(super) fn validate(vertices: &[Vertex], indices: &[u32]) -> MeshReport {
+	let mut report = MeshReport {
+		trailing_indices: indices.len() % 3,
+		..MeshReport::default()
+	};
+
+	// the first vertex at each position stands in for the others
+	let mut first_at: HashMap<[u32; 3], u32> = HashMap::new();
+	let mut welded = Vec::with_capacity(vertices.len());
+	for (index, vertex) in vertices.iter().enumerate() {
+		if !vertex.position.iter().all(|x| x.is_finite()) {
+			report.non_finite_positions.push(index);
+		}
+		let length = Vec3::from(vertex.normal).length();
+		if (length - 1.0).abs() > NORMAL_TOLERANCE {
+			report.unnormalized_normals.push(index);
+		}
+		let key = vertex.position.map(|x| (x + 0.0).to_bits());
+		welded.push(*first_at.entry(key).or_insert(index as u32));
+	}
+
+	let mut triangles: HashMap<[u32; 3], usize> = HashMap::new();
+	let mut edges: HashMap<[u32; 2], usize> = HashMap::new();
+	let mut directed_edges: HashMap<[u32; 2], usize> = HashMap::new();
+	for (number, triangle) in indices.chunks_exact(3).enumerate() {
+		if triangle
+			.iter()
+			.any(|index| *index as usize >= vertices.len())
+		{
+			report.out_of_range_indices.push(number);
+			continue;
+		}
+		let corners = [0, 1, 2].map(|k| welded[triangle[k] as usize]);
+		let positions = corners.map(|corner| Vec3::from(vertices[corner as usize].position));
+		if !positions.iter().all(|position| position.is_finite()) {
+			continue;
+		}
+		let area = (positions[1] - positions[0])
+			.cross(positions[2] - positions[0])
+			.length_squared();
+		if corners[0] == corners[1]
+			|| corners[1] == corners[2]
+			|| corners[2] == corners[0]
+			|| area == 0.0
+		{
+			report.degenerate_triangles.push(number);
+			continue;
+		}
+
+		let mut sorted = corners;
+		sorted.sort_unstable();
+		if triangles.insert(sorted, number).is_some() {
+			report.duplicate_triangles.push(number);
+			continue;
+		}
+		for k in 0..3 {
+			let (a, b) = (corners[k], corners[(k + 1) % 3]);
+			*edges.entry([a.min(b), a.max(b)]).or_default() += 1;
+			*directed_edges.entry([a, b]).or_default() += 1;
+		}
+	}
+
+	report.non_manifold_edges = edges
+		.into_iter()
+		.filter(|(_, count)| *count > 2)
+		.map(|(edge, _)| edge)
+		.collect();
+	report.inconsistent_winding = directed_edges
+		.into_iter()
+		.filter(|(_, count)| *count > 1)
+		.map(|(edge, _)| edge)
+		.collect();
+	report.non_manifold_edges.sort_unstable();
+	report.inconsistent_winding.sort_unstable();
+	report
+}
+
+/// Logs what `validate` finds in debug builds, called before uploading.
+pub(super) fn debug_validate(vertices: &[Vertex], indices: &[u32], label: Option<&str>) {
+	if cfg!(debug_assertions) {
+		let report = validate(vertices, indices);
+		if !report.is_valid() {
+			log::warn!("mesh {}: {}", label.unwrap_or("without label"), report);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::mesh::SphereUvs;
+
+	/// A closed tetrahedron wound counter clockwise from outside.
+	fn tetrahedron() -> MeshGenerator {
+		let vertex = |position: [f32; 3]| Vertex {
+			position,
+			normal: Vec3::from(position).normalize_or_zero().into(),
+			..Vertex::default()
+		};
+		let mut generator = MeshGenerator {
+			vertices: vec![
+				vertex([0.0, 0.0, 0.0]),
+				vertex([1.0, 0.0, 0.0]),
+				vertex([0.0, 1.0, 0.0]),
+				vertex([0.0, 0.0, 1.0]),
+			],
+			indices: vec![0, 2, 1, 0, 1, 3, 0, 3, 2, 1, 2, 3],
+		};
+		// the corner at the origin has no direction, any unit normal will do
+		generator.vertices[0].normal = [0.0, 1.0, 0.0];
+		generator
+	}
+	fn with_vertex(mut generator: MeshGenerator, position: [f32; 3]) -> MeshGenerator {
+		let mut vertex = generator.vertices[0];
+		vertex.position = position;
+		generator.vertices.push(vertex);
+		generator
+	}
+	fn with_triangle(mut generator: MeshGenerator, triangle: [u32; 3]) -> MeshGenerator {
+		generator.indices.extend_from_slice(&triangle);
+		generator
+	}
+
+	#[test]
+	fn tetrahedron_is_valid() {
+		let report = tetrahedron().validate();
+		assert!(report.is_valid(), "{}", report);
+		assert_eq!(report.to_string(), "no problems");
+	}
+
+	#[test]
+	fn generated_shapes_are_valid() {
+		let mut generator = MeshGenerator::default();
+		generator.cuboid(1.0, 2.0, 3.0);
+		assert!(generator.validate().is_valid(), "{}", generator.validate());
+		for uvs in [SphereUvs::Equirectangular, SphereUvs::CubeFaces] {
+			generator.icosphere(1.0, 2, uvs);
+			assert!(generator.validate().is_valid(), "{}", generator.validate());
+		}
+	}
+
+	#[test]
+	fn trailing_indices() {
+		let mut generator = tetrahedron();
+		generator.indices.extend_from_slice(&[0, 1]);
+		let report = generator.validate();
+		assert_eq!(report.trailing_indices, 2);
+		assert_eq!(
+			MeshReport {
+				trailing_indices: 0,
+				..report
+			},
+			MeshReport::default()
+		);
+	}
+
+	#[test]
+	fn out_of_range_indices() {
+		let report = with_triangle(tetrahedron(), [0, 1, 4]).validate();
+		assert_eq!(
+			report,
+			MeshReport {
+				out_of_range_indices: vec![4],
+				..MeshReport::default()
+			}
+		);
+	}
+
+	#[test]
+	fn non_finite_positions() {
+		let generator = with_vertex(tetrahedron(), [f32::NAN, 0.0, 0.0]);
+		let report = with_triangle(generator, [0, 1, 4]).validate();
+		// the triangle is left out of the other checks
+		assert_eq!(
+			report,
+			MeshReport {
+				non_finite_positions: vec![4],
+				..MeshReport::default()
+			}
+		);
+	}
+
+	#[test]
+	fn degenerate_triangles() {
+		let generator = with_vertex(tetrahedron(), [2.0, 0.0, 0.0]);
+		let generator = with_triangle(generator, [0, 0, 1]);
+		// on one line
+		let report = with_triangle(generator, [0, 1, 4]).validate();
+		assert_eq!(
+			report,
+			MeshReport {
+				degenerate_triangles: vec![4, 5],
+				..MeshReport::default()
+			}
+		);
+	}
+
+	#[test]
+	fn duplicate_triangles() {
+		let report = with_triangle(tetrahedron(), [2, 1, 0]).validate();
+		assert_eq!(
+			report,
+			MeshReport {
+				duplicate_triangles: vec![4],
+				..MeshReport::default()
+			}
+		);
+	}
+
+	#[test]
+	fn non_manifold_edges() {
+		let generator = with_vertex(tetrahedron(), [1.0, -1.0, 0.5]);
+		let report = with_triangle(generator, [1, 0, 4]).validate();
+		// a third triangle runs along the edge like one of the others
+		assert_eq!(
+			report,
+			MeshReport {
+				non_manifold_edges: vec![[0, 1]],
+				inconsistent_winding: vec![[1, 0]],
+				..MeshReport::default()
+			}
+		);
+	}
+
+	#[test]
+	fn flipped_triangle() {
+		let mut generator = tetrahedron();
+		generator.indices[9..].copy_from_slice(&[1, 3, 2]);
+		let report = generator.validate();
+		assert_eq!(
+			report,
+			MeshReport {
+				inconsistent_winding: vec![[1, 3], [2, 1], [3, 2]],
+				..MeshReport::default()
+			}
+		);
+	}
+
+	#[test]
+	fn seam_copies_count_as_one_vertex() {
+		// the last triangle uses a copy of vertex 3 with other uvs
+		let mut generator = with_vertex(tetrahedron(), [0.0, 0.0, 1.0]);
+		generator.vertices[4].uv = [1.0, 1.0];
+		generator.indices[11] = 4;
+		let report = generator.validate();
+		assert!(report.is_valid(), "{}", report);
+
+		// flipping it is still found
+		generator.indices[9..].copy_from_slice(&[1, 4, 2]);
+		assert_eq!(generator.validate().inconsistent_winding.len(), 3);
+	}
+
+	#[test]
+	fn unnormalized_normals() {
+		let mut generator = tetrahedron();
+		generator.vertices[1].normal = [2.0, 0.0, 0.0];
+		generator.vertices[3].normal = [0.0; 3];
+		let report = generator.validate();
+		assert_eq!(
+			report,
+			MeshReport {
+				unnormalized_normals: vec![1, 3],
+				..MeshReport::default()
+			}
+		);
+		assert_eq!(report.to_string(), "2 unnormalized normals");
+	}
+}
@@ -0,0 +1,105 @@
+// File: model.rs
+
+use bevy::transform::components::GlobalTransform;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ModelUniform {
+	model_matrix: [[f32; 4]; 4],
+	normal_matrix: [[f32; 4]; 4],
+}
+impl ModelUniform {
+	pub fn new(transform: &GlobalTransform) -> Self {
+		let model_matrix = transform.compute_matrix();
+		let normal_matrix = model_matrix.inverse().transpose();
+		Self {
+			model_matrix: model_matrix.to_cols_array_2d(),
+			normal_matrix: normal_matrix.to_cols_array_2d(),
+		}
+	}
+}
+
+/// One `ModelUniform` per drawn mesh, selected with a dynamic offset.
+pub struct ModelBindGroup {
+	pub buffer: wgpu::Buffer,
+	pub layout: wgpu::BindGroupLayout,
+	pub bind_group: wgpu::BindGroup,
+	/// Distance in bytes between two uniforms, padded to the offset alignment.
+	pub stride: wgpu::BufferAddress,
+	pub capacity: usize,
+}
+impl ModelBindGroup {
+	pub fn new(device: &wgpu::Device, capacity: usize) -> Self {
+		let alignment = device.limits().min_uniform_buffer_offset_alignment as wgpu::BufferAddress;
+		let size = std::mem::size_of::<ModelUniform>() as wgpu::BufferAddress;
+		let stride = size.div_ceil(alignment) * alignment;
+		let capacity = capacity.max(1);
+
+		let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("model buffer"),
+			size: stride * capacity as wgpu::BufferAddress,
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+
+		let layout = Self::create_layout(device);
+
+		let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			layout: &layout,
+			entries: &[wgpu::BindGroupEntry {
+				binding: 0,
+				resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+					buffer: &buffer,
+					offset: 0,
+					size: wgpu::BufferSize::new(size),
+				}),
+			}],
+			label: Some("model_bind_group"),
+		});
+
+		ModelBindGroup {
+			buffer,
+			layout,
+			bind_group,
+			stride,
+			capacity,
+		}
+	}
+	pub fn create_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+		device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			entries: &[wgpu::BindGroupLayoutEntry {
+				binding: 0,
+				visibility: wgpu::ShaderStages::VERTEX,
+				ty: wgpu::BindingType::Buffer {
+					ty: wgpu::BufferBindingType::Uniform,
+					has_dynamic_offset: true,
+					min_binding_size: wgpu::BufferSize::new(
+						std::mem::size_of::<ModelUniform>() as wgpu::BufferAddress
+					),
+				},
+				count: None,
+			}],
+			label: Some("model_layout"),
+		})
+	}
+	/// Uploads `uniforms`, growing the buffer when it is too small.
+	pub fn write(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, uniforms: &[ModelUniform]) {
+		if uniforms.is_empty() {
+			return;
+		}
+		if uniforms.len() > self.capacity {
+			*self = Self::new(device, uniforms.len().next_power_of_two());
+		}
+
+		let stride = self.stride as usize;
+		let mut bytes = vec![0u8; stride * uniforms.len()];
+		for (uniform, chunk) in uniforms.iter().zip(bytes.chunks_mut(stride)) {
+			let uniform = bytemuck::bytes_of(uniform);
+			chunk[..uniform.len()].copy_from_slice(uniform);
+		}
+		queue.write_buffer(&self.buffer, 0, &bytes);
+	}
+	pub fn offset(&self, index: usize) -> wgpu::DynamicOffset {
+		(self.stride * index as wgpu::BufferAddress) as wgpu::DynamicOffset
+	}
+}
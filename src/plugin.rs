@@ -0,0 +1,414 @@
+// File: plugin.rs
+
+use crate::camera::{Camera, CameraBindGroup, CameraController, PrimaryCamera};
+use crate::culling::{CullingStats, FrustumVisibility};
+use crate::error::{RendererError, SurfaceOutOfMemory};
+use crate::instance::{InstanceBuffer, InstancedMesh};
+use crate::material::{GpuMaterials, Material, MaterialBindGroup};
+use crate::mesh::{GpuMeshes, Lod, Mesh, MeshData, ShouldDraw};
+use crate::render_state::{MeshDraw, RenderState};
+use crate::screenshot::ScreenshotRequest;
+use crate::settings::RendererSettings;
+
+use bevy::{
+	app::{App, Plugin},
+	ecs::system::Commands,
+	input::keyboard::KeyCode,
+	prelude::*,
+	transform::TransformSystem,
+	window::WindowResized,
+	winit::WinitWindows,
+};
+
+/// Creates the `RenderState` resource at startup and draws every `Mesh` or
+/// `Handle<MeshData>` tagged with `ShouldDraw` from the point of view of the
+/// `PrimaryCamera`. Meshes are placed by their `GlobalTransform`, add bevy's
+/// `TransformPlugin` to have it follow `Transform`. Entities with an
+/// `InstancedMesh` draw all of their instances with one draw call. A
+/// `Handle<Material>` selects the material a mesh is drawn with. Meshes outside
+/// the camera's frustum are skipped, see `CullingStats`. Entities with a `Lod`
+/// draw the level matching their size on screen.
+///
+/// `Material` and `MeshData` are assets, so bevy's `CorePlugin` and
+/// `AssetPlugin` have to be added before this plugin.
+///
+/// The renderer is inserted in `StartupStage::PreStartup`, so regular startup
+/// systems can use `Res<RenderState>` to build meshes. Cameras only need a
+/// `Camera` component, their `CameraBindGroup` is created automatically.
+#[derive(Default)]
+pub struct WgpuRendererPlugin;
+impl Plugin for WgpuRendererPlugin {
+	fn build(&self, app: &mut App) {
+		app.init_resource::<RendererSettings>()
+			.init_resource::<GpuMaterials>()
+			.init_resource::<GpuMeshes>()
+			.init_resource::<CullingStats>()
+			.add_asset::<Material>()
+			.add_asset::<MeshData>()
+			.add_event::<ScreenshotRequest>()
+			.add_event::<SurfaceOutOfMemory>()
+			.add_startup_system_to_stage(StartupStage::PreStartup, init_renderer.system())
+			.add_system(prepare_cameras.system())
+			.add_system(prepare_instance_buffers.system())
+			.add_system(prepare_materials.system())
+			.add_system(prepare_meshes.system())
+			.add_system(window_resize.system())
+			.add_system(camera_controls.system())
+			.add_system_to_stage(
+				CoreStage::PostUpdate,
+				cull_meshes
+					.system()
+					.after(TransformSystem::TransformPropagate),
+			)
+			.add_system_to_stage(CoreStage::Last, render.system());
+	}
+}
+
+fn init_renderer(
+	windows: Res<WinitWindows>,
+	settings: Res<RendererSettings>,
+	mut commands: Commands,
+) {
+	let (_id, window) = windows.as_ref().windows.iter().next().unwrap();
+	let renderer = pollster::block_on(RenderState::new(window, &settings))
+		.unwrap_or_else(|err| panic!("failed to create the renderer: {}", err));
+	commands.insert_resource(renderer);
+}
+fn prepare_cameras(
+	renderer: Res<RenderState>,
+	camera_query: Query<(Entity, &Camera), Without<CameraBindGroup>>,
+	mut commands: Commands,
+) {
+	for (entity, camera) in camera_query.iter() {
+		let mut camera = *camera;
+		camera.set_aspect(renderer.size.width, renderer.size.height);
+		let camera_bind_group =
+			CameraBindGroup::new(&renderer.device, Some("camera_bind_group"), &camera);
+		commands
+			.entity(entity)
+			.insert(camera)
+			.insert(camera_bind_group);
+	}
+}
+fn prepare_instance_buffers(
+	renderer: Res<RenderState>,
+	mut instance_query: Query<
+		(Entity, &InstancedMesh, Option<&mut InstanceBuffer>),
+		Changed<InstancedMesh>,
+	>,
+	mut commands: Commands,
+) {
+	for (entity, instanced_mesh, instance_buffer) in instance_query.iter_mut() {
+		match instance_buffer {
+			Some(mut instance_buffer) => {
+				instance_buffer.write(&renderer.device, &renderer.queue, &instanced_mesh.instances)
+			}
+			None => {
+				let mut instance_buffer =
+					InstanceBuffer::new(&renderer.device, instanced_mesh.instances.len());
+				instance_buffer.write(&renderer.device, &renderer.queue, &instanced_mesh.instances);
+				commands.entity(entity).insert(instance_buffer);
+			}
+		}
+	}
+}
+fn prepare_materials(
+	renderer: Res<RenderState>,
+	materials: Res<Assets<Material>>,
+	mut events: EventReader<AssetEvent<Material>>,
+	mut gpu_materials: ResMut<GpuMaterials>,
+) {
+	for event in events.iter() {
+		match event {
+			AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
+				let material = match materials.get(handle) {
+					Some(material) => material,
+					None => continue,
+				};
+				match MaterialBindGroup::new(
+					&renderer.device,
+					&renderer.queue,
+					material,
+					Some("material_bind_group"),
+				) {
+					Ok(bind_group) => {
+						gpu_materials.bind_groups.insert(handle.id, bind_group);
+					}
+					Err(err) => log::error!("failed to prepare material: {}", err),
+				}
+			}
+			AssetEvent::Removed { handle } => {
+				gpu_materials.bind_groups.remove(&handle.id);
+			}
+		}
+	}
+}
+/// Uploads `MeshData` the first time a drawn entity uses it, all levels of a
+/// `Lod` at once. Modified assets are uploaded again, dynamic ones into their
+/// existing buffers. Removed ones are freed.
+#[allow(clippy::type_complexity)]
+fn prepare_meshes(
+	renderer: Res<RenderState>,
+	mesh_data: Res<Assets<MeshData>>,
+	mut events: EventReader<AssetEvent<MeshData>>,
+	mut gpu_meshes: ResMut<GpuMeshes>,
+	mesh_query: Query<(Option<&Handle<MeshData>>, Option<&Lod>), With<ShouldDraw>>,
+) {
+	for event in events.iter() {
+		match event {
+			AssetEvent::Modified { handle } => {
+				match (gpu_meshes.meshes.get_mut(&handle.id), mesh_data.get(handle)) {
+					(Some(mesh), Some(data)) if mesh.dynamic && data.dynamic => mesh.set_geometry(
+						&renderer.device,
+						&renderer.queue,
+						&data.vertices,
+						&data.indices,
+					),
+					_ => {
+						gpu_meshes.meshes.remove(&handle.id);
+					}
+				}
+			}
+			AssetEvent::Removed { handle } => {
+				gpu_meshes.meshes.remove(&handle.id);
+			}
+			AssetEvent::Created { .. } => {}
+		}
+	}
+	let handles = mesh_query.iter().flat_map(|(handle, lod)| {
+		let levels = lod.map(|lod| lod.levels.iter()).into_iter().flatten();
+		handle.into_iter().chain(levels)
+	});
+	for handle in handles {
+		if gpu_meshes.meshes.contains_key(&handle.id) {
+			continue;
+		}
+		if let Some(data) = mesh_data.get(handle) {
+			let mesh = data.build(&renderer.device, &renderer.queue, Some("mesh_data"));
+			gpu_meshes.meshes.insert(handle.id, mesh);
+		}
+	}
+}
+/// Tests the bounds of drawn entities against the primary camera's frustum.
+/// Instanced meshes are never culled, their bounds do not cover the instances.
+/// Also picks the level of `Lod`s from their first level's bounds.
+#[allow(clippy::type_complexity)]
+fn cull_meshes(
+	gpu_meshes: Res<GpuMeshes>,
+	camera_query: Query<&Camera, With<PrimaryCamera>>,
+	mut mesh_query: Query<
+		(
+			Entity,
+			Option<&Mesh>,
+			Option<&Handle<MeshData>>,
+			&GlobalTransform,
+			Option<&mut FrustumVisibility>,
+			Option<&mut Lod>,
+		),
+		(With<ShouldDraw>, Without<InstancedMesh>),
+	>,
+	mut stats: ResMut<CullingStats>,
+	mut commands: Commands,
+) {
+	let camera = match camera_query.iter().next() {
+		Some(camera) => camera,
+		None => return,
+	};
+	let frustum = camera.frustum();
+
+	stats.culled = 0;
+	for (entity, mesh, mesh_data, transform, visibility, lod) in mesh_query.iter_mut() {
+		let mesh = match &lod {
+			Some(lod) => lod
+				.levels
+				.first()
+				.and_then(|handle| gpu_meshes.meshes.get(&handle.id)),
+			None => mesh.or_else(|| mesh_data.and_then(|handle| gpu_meshes.meshes.get(&handle.id))),
+		};
+		let bounds = mesh.and_then(|mesh| mesh.bounds);
+		// meshes without bounds, or not uploaded yet, are kept
+		let visible = match bounds {
+			Some(bounds) => {
+				let matrix = transform.compute_matrix();
+				let sphere = bounds.sphere.transformed(&matrix);
+				if let Some(mut lod) = lod {
+					let level = lod.select(camera.screen_size(&sphere));
+					if lod.current != level {
+						lod.current = level;
+					}
+				}
+				// the sphere test is cheaper, the box is tighter
+				frustum.intersects_sphere(&sphere)
+					&& frustum.intersects_aabb(&bounds.aabb.transformed(&matrix))
+			}
+			None => true,
+		};
+		if !visible {
+			stats.culled += 1;
+		}
+		match visibility {
+			Some(mut visibility) => {
+				if visibility.visible != visible {
+					visibility.visible = visible;
+				}
+			}
+			None => {
+				commands
+					.entity(entity)
+					.insert(FrustumVisibility { visible });
+			}
+		}
+	}
+}
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn render(
+	mut renderer: ResMut<RenderState>,
+	gpu_materials: Res<GpuMaterials>,
+	gpu_meshes: Res<GpuMeshes>,
+	mut culling_stats: ResMut<CullingStats>,
+	camera_query: Query<&CameraBindGroup, With<PrimaryCamera>>,
+	draw_query: Query<
+		(
+			Option<&Mesh>,
+			Option<&Handle<MeshData>>,
+			&GlobalTransform,
+			Option<&InstancedMesh>,
+			Option<&InstanceBuffer>,
+			Option<&Handle<Material>>,
+			Option<&FrustumVisibility>,
+			Option<&Lod>,
+		),
+		With<ShouldDraw>,
+	>,
+	mut screenshot_requests: EventReader<ScreenshotRequest>,
+	mut out_of_memory: EventWriter<SurfaceOutOfMemory>,
+) {
+	let camera_bind_group = match camera_query.iter().next() {
+		Some(camera_bind_group) => camera_bind_group,
+		None => return,
+	};
+
+	// Materials that are not prepared yet fall back to the default material.
+	let material = |handle: Option<&Handle<Material>>| {
+		handle.and_then(|handle| gpu_materials.bind_groups.get(&handle.id))
+	};
+	let draws: Vec<MeshDraw> = draw_query
+		.iter()
+		.filter_map(
+			|(
+				mesh,
+				mesh_data,
+				transform,
+				instanced_mesh,
+				instances,
+				material_handle,
+				visibility,
+				lod,
+			)| {
+				if let Some(FrustumVisibility { visible: false }) = visibility {
+					return None;
+				}
+				// Mesh assets and instance buffers are skipped until they are uploaded.
+				let mesh = match lod {
+					Some(lod) => gpu_meshes.meshes.get(&lod.current_level().id),
+					None => mesh
+						.or_else(|| mesh_data.and_then(|handle| gpu_meshes.meshes.get(&handle.id))),
+				}?;
+				if instanced_mesh.is_some() && instances.is_none() {
+					return None;
+				}
+				Some(MeshDraw {
+					mesh,
+					transform,
+					instances,
+					material: material(material_handle),
+				})
+			},
+		)
+		.collect();
+	culling_stats.drawn = draws.len();
+
+	let requests: Vec<&ScreenshotRequest> = screenshot_requests.iter().collect();
+	let result = if requests.is_empty() {
+		renderer
+			.render(camera_bind_group, draws.into_iter())
+			.map_err(RendererError::from)
+	} else {
+		renderer
+			.render_and_capture(camera_bind_group, draws.into_iter())
+			.map(|image| {
+				for request in requests {
+					match image.save(&request.path) {
+						Ok(()) => log::info!("saved screenshot to {}", request.path.display()),
+						Err(err) => log::error!(
+							"failed to save screenshot to {}: {}",
+							request.path.display(),
+							err
+						),
+					}
+				}
+			})
+	};
+
+	match result {
+		Ok(()) => {}
+		Err(RendererError::Surface(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated)) => {
+			renderer.reconfigure()
+		}
+		Err(RendererError::Surface(wgpu::SurfaceError::Timeout)) => {
+			log::warn!("timed out acquiring a frame, skipping it")
+		}
+		Err(RendererError::Surface(wgpu::SurfaceError::OutOfMemory)) => {
+			log::error!("surface is out of memory");
+			out_of_memory.send(SurfaceOutOfMemory);
+		}
+		Err(err) => log::error!("{}", err),
+	}
+}
+fn window_resize(
+	mut reader: EventReader<WindowResized>,
+	mut renderer: ResMut<RenderState>,
+	mut camera_query: Query<(&mut Camera, &mut CameraBindGroup), With<PrimaryCamera>>,
+) {
+	if let Some(event) = reader.iter().next() {
+		let (width, height) = (event.width as u32, event.height as u32);
+		renderer.as_mut().resize(width, height);
+
+		if let Some((mut camera, mut camera_bind_group)) = camera_query.iter_mut().next() {
+			camera.set_aspect(width, height);
+			camera_bind_group.uniform.set_view_proj(&camera);
+			renderer.queue.write_buffer(
+				&camera_bind_group.buffer,
+				0,
+				bytemuck::cast_slice(&[camera_bind_group.uniform]),
+			);
+		}
+	}
+}
+pub fn camera_controls(
+	keys: Res<Input<KeyCode>>,
+	renderer: Res<RenderState>,
+	mut camera_query: Query<
+		(&mut Camera, &mut CameraController, &mut CameraBindGroup),
+		With<PrimaryCamera>,
+	>,
+) {
+	let (mut camera, mut camera_controller, mut camera_bind_group) =
+		match camera_query.iter_mut().next() {
+			Some(camera) => camera,
+			None => return,
+		};
+
+	let changed = camera_controller.process_events(keys);
+	if changed {
+		camera_controller.update_camera(&mut camera);
+		camera.update_view_matrix();
+
+		camera_bind_group.uniform.set_view_proj(&camera);
+		renderer.queue.write_buffer(
+			&camera_bind_group.buffer,
+			0,
+			bytemuck::cast_slice(&[camera_bind_group.uniform]),
+		);
+	}
+}
@@ -1,72 +1,158 @@
 // File: render_state.rs
 
+use crate::adapter::*;
 use crate::camera::*;
+use crate::error::RendererError;
+use crate::instance::*;
+use crate::material::*;
 use crate::mesh::*;
+use crate::model::*;
+use crate::screenshot::*;
+use crate::settings::RendererSettings;
 use crate::texture::*;
 use crate::vertex::*;
+use crate::vertex_layout::*;
+
+use bevy::transform::components::GlobalTransform;
+
+use std::collections::HashMap;
 
 use winit::window::Window;
 
-use wgpu::DeviceType;
+pub enum RenderTarget {
+	Surface(wgpu::Surface),
+	Offscreen(Texture),
+}
+
+/// A mesh to draw this frame. With `instances` set it is drawn once per
+/// instance, each relative to `transform`. Without a `material` the renderer's
+/// default material is used.
+pub struct MeshDraw<'a> {
+	pub mesh: &'a Mesh,
+	pub transform: &'a GlobalTransform,
+	pub instances: Option<&'a InstanceBuffer>,
+	pub material: Option<&'a MaterialBindGroup>,
+}
 
 pub struct RenderState {
-	pub surface: wgpu::Surface,
+	pub target: RenderTarget,
 	pub device: wgpu::Device,
 	pub queue: wgpu::Queue,
 	pub config: wgpu::SurfaceConfiguration,
 	pub size: winit::dpi::PhysicalSize<u32>,
 
+	pub clear_color: wgpu::Color,
+	sample_count: u32,
+
 	depth_texture: Texture,
+	multisampled_texture: Option<Texture>,
 
-	// diffuse_texture: Texture,
-	diffuse_bind_group: TextureBindGroup,
+	default_material: MaterialBindGroup,
+	model_bind_group: ModelBindGroup,
 
-	render_pipeline: wgpu::RenderPipeline,
+	shader: wgpu::ShaderModule,
+	pipeline_layout: wgpu::PipelineLayout,
+	vertex_defaults: VertexDefaults,
+	pipelines: Vec<wgpu::RenderPipeline>,
+	/// Indices into `pipelines` per vertex layout, without and with instancing.
+	/// Pipelines are created the first time a layout is drawn.
+	pipeline_ids: HashMap<VertexLayout, [Option<usize>; 2]>,
 }
 impl RenderState {
-	pub async fn new(window: &Window) -> Self {
+	pub async fn new(window: &Window, settings: &RendererSettings) -> Result<Self, RendererError> {
 		let size = window.inner_size();
+		if size.width == 0 || size.height == 0 {
+			return Err(RendererError::ZeroSized {
+				width: size.width,
+				height: size.height,
+			});
+		}
 
-		assert!(size.width != 0);
-		assert!(size.height != 0);
-
-		let instance = wgpu::Instance::new(wgpu::Backends::VULKAN);
+		let backends = settings.effective_backends();
+		let instance = wgpu::Instance::new(backends);
 		let surface = unsafe { instance.create_surface(window) };
 
-		println!("* * * ADAPTERS * * *");
-		let adapter = instance
-			.enumerate_adapters(wgpu::Backends::all())
-			.fold(None, |acc, adapter| {
-				println!("{:?}", adapter);
-				println!("{:?}\n", adapter.get_info());
-
-				// If the adapter doesn't support the surface continue
-				let supports_surface = surface.get_preferred_format(&adapter).is_some();
-				if !supports_surface {
-					return acc;
-				}
-				match acc {
-					// Anything is better than nothing
-					None => Some(adapter),
-					Some(current) => {
-						// Prefere discrete GPUs
-						match (
-							current.get_info().device_type,
-							adapter.get_info().device_type,
-						) {
-							(_, DeviceType::DiscreteGpu) => Some(adapter),
-							_ => Some(current),
-						}
-					}
-				}
-			})
-			.unwrap();
+		let adapter = select_adapter(
+			&instance,
+			backends,
+			&settings.effective_adapter(),
+			Some(&surface),
+		)?;
 
-		println!("SELECTED ADAPTER");
-		println!("{:?}", adapter);
-		println!("{:?}\n", adapter.get_info());
+		log_adapter(&adapter);
+
+		let format = surface.get_preferred_format(&adapter).ok_or_else(|| {
+			RendererError::UnsupportedSurface {
+				adapter: adapter.get_info().name,
+			}
+		})?;
 
-		let (device, queue) = adapter
+		let (device, queue) = Self::request_device(&adapter).await?;
+
+		let config = wgpu::SurfaceConfiguration {
+			// surfaces are only guaranteed to be render targets, captures render
+			// into a texture of their own
+			usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+			format,
+			width: size.width,
+			height: size.height,
+			present_mode: settings.present_mode,
+		};
+		surface.configure(&device, &config);
+
+		Self::from_device(
+			device,
+			queue,
+			config,
+			size,
+			RenderTarget::Surface(surface),
+			settings,
+		)
+	}
+	/// Creates a renderer without a window that draws into an owned color texture.
+	/// Select `AdapterSelection::SoftwareFallback` in `settings` to render on the
+	/// cpu, e.g. in CI.
+	pub async fn new_offscreen(
+		width: u32,
+		height: u32,
+		format: wgpu::TextureFormat,
+		settings: &RendererSettings,
+	) -> Result<Self, RendererError> {
+		if width == 0 || height == 0 {
+			return Err(RendererError::ZeroSized { width, height });
+		}
+		let size = winit::dpi::PhysicalSize::new(width, height);
+
+		let backends = settings.effective_backends();
+		let instance = wgpu::Instance::new(backends);
+		let adapter = select_adapter(&instance, backends, &settings.effective_adapter(), None)?;
+
+		log_adapter(&adapter);
+
+		let (device, queue) = Self::request_device(&adapter).await?;
+
+		let config = wgpu::SurfaceConfiguration {
+			usage: Texture::COLOR_TARGET_USAGES,
+			format,
+			width,
+			height,
+			present_mode: wgpu::PresentMode::Fifo,
+		};
+		let color_texture = Texture::create_color_texture(&device, &config, "color_texture");
+
+		Self::from_device(
+			device,
+			queue,
+			config,
+			size,
+			RenderTarget::Offscreen(color_texture),
+			settings,
+		)
+	}
+	async fn request_device(
+		adapter: &wgpu::Adapter,
+	) -> Result<(wgpu::Device, wgpu::Queue), wgpu::RequestDeviceError> {
+		adapter
 			.request_device(
 				&wgpu::DeviceDescriptor {
 					features: wgpu::Features::empty(),
@@ -76,30 +162,56 @@ impl RenderState {
 				None,
 			)
 			.await
-			.unwrap();
-
-		let config = wgpu::SurfaceConfiguration {
-			usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-			format: surface.get_preferred_format(&adapter).unwrap(),
-			width: size.width,
-			height: size.height,
-			present_mode: wgpu::PresentMode::Fifo,
+	}
+	fn from_device(
+		device: wgpu::Device,
+		queue: wgpu::Queue,
+		config: wgpu::SurfaceConfiguration,
+		size: winit::dpi::PhysicalSize<u32>,
+		target: RenderTarget,
+		settings: &RendererSettings,
+	) -> Result<Self, RendererError> {
+		let sample_count = match settings.msaa_samples {
+			1 | 4 => settings.msaa_samples,
+			samples => return Err(RendererError::UnsupportedSampleCount { samples }),
 		};
-		surface.configure(&device, &config);
 
 		// create depth texture
-		let depth_texture = Texture::create_depth_texture(&device, &config, "depth_texture");
+		let depth_texture =
+			Texture::create_depth_texture(&device, &config, sample_count, "depth_texture");
+		let multisampled_texture =
+			Self::create_multisampled_texture(&device, &config, sample_count);
 
 		// create diffuse texture
 		let diffuse_bytes = include_bytes!("../assets/images/earth.png");
-		let diffuse_texture =
-			crate::texture::Texture::from_bytes(&device, &queue, diffuse_bytes, "earth_png")
-				.unwrap();
-		let diffuse_bind_group =
-			TextureBindGroup::new(&device, Some("diffuse_bind_group"), &diffuse_texture);
+		let diffuse_image = image::load_from_memory(diffuse_bytes)
+			.map_err(|err| RendererError::Texture(err.into()))?;
+		// repeating lets equirectangular uvs leave 0..1 where triangles cross the seam
+		let diffuse_texture = Texture::from_image_with(
+			&device,
+			&queue,
+			&diffuse_image,
+			Some("earth_png"),
+			wgpu::TextureFormat::Rgba8UnormSrgb,
+			&SamplerSettings {
+				address_mode: wgpu::AddressMode::Repeat,
+				..Default::default()
+			},
+		)
+		.map_err(RendererError::Texture)?;
+		let default_material = MaterialBindGroup::with_base_color_texture(
+			&device,
+			&queue,
+			&Material::default(),
+			diffuse_texture,
+			Some("default_material"),
+		)
+		.map_err(RendererError::Texture)?;
 
 		// create render pipeline
 		let camera_bind_group_layout = CameraBindGroup::create_layout(&device);
+		let material_bind_group_layout = MaterialBindGroup::create_layout(&device);
+		let model_bind_group = ModelBindGroup::new(&device, 64);
 
 		let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
 			label: Some("shader"),
@@ -109,23 +221,108 @@ impl RenderState {
 		let render_pipeline_layout =
 			device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
 				label: Some("render_pipeline_layout"),
-				bind_group_layouts: &[&camera_bind_group_layout, &diffuse_bind_group.layout],
+				bind_group_layouts: &[
+					&camera_bind_group_layout,
+					&material_bind_group_layout,
+					&model_bind_group.layout,
+				],
 				push_constant_ranges: &[],
 			});
 
-		let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-			label: Some("render_pipeline"),
-			layout: Some(&render_pipeline_layout),
+		let vertex_defaults = VertexDefaults::new(&device);
+
+		let mut render_state = RenderState {
+			target,
+			device,
+			queue,
+			config,
+			size,
+
+			clear_color: settings.clear_color,
+			sample_count,
+
+			depth_texture,
+			multisampled_texture,
+
+			default_material,
+			model_bind_group,
+
+			shader,
+			pipeline_layout: render_pipeline_layout,
+			vertex_defaults,
+			pipelines: vec![],
+			pipeline_ids: HashMap::new(),
+		};
+		// the common layout is ready before the first frame
+		render_state.pipeline_id(&Vertex::layout(), false);
+		render_state.pipeline_id(&Vertex::layout(), true);
+		Ok(render_state)
+	}
+	/// The pipeline drawing meshes with `layout`, created on first use. Shader
+	/// inputs missing from `layout` are read from the vertex defaults buffer,
+	/// bound right after the mesh's own streams.
+	fn pipeline_id(&mut self, layout: &VertexLayout, instanced: bool) -> usize {
+		if let Some(id) = self
+			.pipeline_ids
+			.get(layout)
+			.and_then(|ids| ids[instanced as usize])
+		{
+			return id;
+		}
+
+		let mut streams = layout.streams.clone();
+		streams.extend(VertexDefaults::stream_for(layout));
+		let attributes: Vec<Vec<wgpu::VertexAttribute>> =
+			streams.iter().map(VertexStream::wgpu_attributes).collect();
+		let mut buffers: Vec<wgpu::VertexBufferLayout> = streams
+			.iter()
+			.zip(&attributes)
+			.map(|(stream, attributes)| wgpu::VertexBufferLayout {
+				array_stride: stream.stride,
+				step_mode: wgpu::VertexStepMode::Vertex,
+				attributes,
+			})
+			.collect();
+		if instanced {
+			buffers.push(InstanceRaw::desc());
+		}
+
+		let pipeline = Self::create_pipeline(
+			&self.device,
+			&self.pipeline_layout,
+			&self.shader,
+			if instanced { "vs_instanced" } else { "vs_main" },
+			&buffers,
+			self.config.format,
+			self.sample_count,
+		);
+		let id = self.pipelines.len();
+		self.pipelines.push(pipeline);
+		self.pipeline_ids.entry(layout.clone()).or_default()[instanced as usize] = Some(id);
+		id
+	}
+	fn create_pipeline(
+		device: &wgpu::Device,
+		layout: &wgpu::PipelineLayout,
+		shader: &wgpu::ShaderModule,
+		vertex_entry_point: &str,
+		buffers: &[wgpu::VertexBufferLayout],
+		format: wgpu::TextureFormat,
+		sample_count: u32,
+	) -> wgpu::RenderPipeline {
+		device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+			label: Some(vertex_entry_point),
+			layout: Some(layout),
 			vertex: wgpu::VertexState {
-				module: &shader,
-				entry_point: "vs_main",
-				buffers: &[Vertex::desc()],
+				module: shader,
+				entry_point: vertex_entry_point,
+				buffers,
 			},
 			fragment: Some(wgpu::FragmentState {
-				module: &shader,
+				module: shader,
 				entry_point: "fs_main",
 				targets: &[wgpu::ColorTargetState {
-					format: config.format,
+					format,
 					blend: Some(wgpu::BlendState::REPLACE),
 					write_mask: wgpu::ColorWrites::ALL,
 				}],
@@ -147,47 +344,164 @@ impl RenderState {
 				bias: wgpu::DepthBiasState::default(),
 			}),
 			multisample: wgpu::MultisampleState {
-				count: 1,
+				count: sample_count,
 				mask: !0,
 				alpha_to_coverage_enabled: false,
 			},
 			multiview: None,
-		});
-
-		RenderState {
-			surface,
-			device,
-			queue,
-			config,
-			size,
-
-			depth_texture,
-
-			// diffuse_texture,
-			diffuse_bind_group,
-
-			render_pipeline,
-		}
+		})
 	}
 	pub fn resize(&mut self, width: u32, height: u32) {
 		if width > 0 && height > 0 {
 			self.size = winit::dpi::PhysicalSize::new(width, height);
 			self.config.width = width;
 			self.config.height = height;
-			self.depth_texture =
-				Texture::create_depth_texture(&self.device, &self.config, "depth_texture");
-			self.surface.configure(&self.device, &self.config);
+			self.depth_texture = Texture::create_depth_texture(
+				&self.device,
+				&self.config,
+				self.sample_count,
+				"depth_texture",
+			);
+			self.multisampled_texture =
+				Self::create_multisampled_texture(&self.device, &self.config, self.sample_count);
+			match &mut self.target {
+				RenderTarget::Surface(surface) => surface.configure(&self.device, &self.config),
+				RenderTarget::Offscreen(texture) => {
+					*texture =
+						Texture::create_color_texture(&self.device, &self.config, "color_texture")
+				}
+			}
+		}
+	}
+	/// Reconfigures the surface at its current size, e.g. after it was lost.
+	pub fn reconfigure(&mut self) {
+		self.resize(self.size.width, self.size.height);
+	}
+	fn create_multisampled_texture(
+		device: &wgpu::Device,
+		config: &wgpu::SurfaceConfiguration,
+		sample_count: u32,
+	) -> Option<Texture> {
+		if sample_count > 1 {
+			Some(Texture::create_multisampled_texture(
+				device,
+				config,
+				sample_count,
+				"multisampled_texture",
+			))
+		} else {
+			None
+		}
+	}
+	/// Replaces the default material by `material`.
+	pub fn set_default_material(&mut self, material: &Material) -> anyhow::Result<()> {
+		self.default_material = MaterialBindGroup::new(
+			&self.device,
+			&self.queue,
+			material,
+			Some("default_material"),
+		)?;
+		Ok(())
+	}
+	/// The texture an offscreen renderer draws into, `None` when rendering to a window.
+	pub fn offscreen_texture(&self) -> Option<&Texture> {
+		match &self.target {
+			RenderTarget::Surface(_) => None,
+			RenderTarget::Offscreen(texture) => Some(texture),
 		}
 	}
 	pub fn render<'a>(
-		&self,
+		&mut self,
 		camera_bind_group: &CameraBindGroup,
-		meshes: impl Iterator<Item = &'a Mesh>,
+		draws: impl Iterator<Item = MeshDraw<'a>>,
 	) -> Result<(), wgpu::SurfaceError> {
-		let output = self.surface.get_current_texture()?;
-		let view = output
-			.texture
-			.create_view(&wgpu::TextureViewDescriptor::default());
+		self.render_frame(camera_bind_group, draws, false)
+			.map(|_| ())
+	}
+	/// Renders a frame like `render` and reads it back. Window frames are drawn a
+	/// second time into a texture that can be copied.
+	pub fn render_and_capture<'a>(
+		&mut self,
+		camera_bind_group: &CameraBindGroup,
+		draws: impl Iterator<Item = MeshDraw<'a>>,
+	) -> Result<image::RgbaImage, RendererError> {
+		let readback = self
+			.render_frame(camera_bind_group, draws, true)?
+			.expect("capture was requested");
+		readback
+			.into_image(&self.device)
+			.map_err(RendererError::Readback)
+	}
+	/// Reads back the last frame drawn by an offscreen renderer. Window surfaces
+	/// can only be captured while rendering, see `render_and_capture`.
+	pub fn capture_frame(&self) -> Result<image::RgbaImage, RendererError> {
+		let texture = self.offscreen_texture().ok_or_else(|| {
+			RendererError::Readback(anyhow::anyhow!(
+				"window frames can only be captured with render_and_capture"
+			))
+		})?;
+
+		let mut encoder = self
+			.device
+			.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+				label: Some("capture_encoder"),
+			});
+		let readback = FrameReadback::copy_from_texture(
+			&self.device,
+			&mut encoder,
+			&texture.texture,
+			self.config.width,
+			self.config.height,
+			self.config.format,
+		);
+		self.queue.submit(std::iter::once(encoder.finish()));
+
+		readback
+			.into_image(&self.device)
+			.map_err(RendererError::Readback)
+	}
+	fn render_frame<'a>(
+		&mut self,
+		camera_bind_group: &CameraBindGroup,
+		draws: impl Iterator<Item = MeshDraw<'a>>,
+		capture: bool,
+	) -> Result<Option<FrameReadback>, wgpu::SurfaceError> {
+		// Grouping draws by pipeline and material keeps rebinding to a minimum.
+		let mut draws: Vec<(usize, MeshDraw)> = draws
+			.map(|draw| {
+				let pipeline_id = self.pipeline_id(&draw.mesh.layout, draw.instances.is_some());
+				(pipeline_id, draw)
+			})
+			.collect();
+		draws.sort_by_key(|(pipeline_id, draw)| {
+			(
+				*pipeline_id,
+				draw.material
+					.map(|material| material as *const MaterialBindGroup as usize),
+			)
+		});
+		let model_uniforms: Vec<ModelUniform> = draws
+			.iter()
+			.map(|(_, draw)| ModelUniform::new(draw.transform))
+			.collect();
+		self.model_bind_group
+			.write(&self.device, &self.queue, &model_uniforms);
+
+		let (output, view) = match &self.target {
+			RenderTarget::Surface(surface) => {
+				let output = surface.get_current_texture()?;
+				let view = output
+					.texture
+					.create_view(&wgpu::TextureViewDescriptor::default());
+				(Some(output), view)
+			}
+			RenderTarget::Offscreen(texture) => (
+				None,
+				texture
+					.texture
+					.create_view(&wgpu::TextureViewDescriptor::default()),
+			),
+		};
 
 		let mut render_encoder =
 			self.device
@@ -195,48 +509,128 @@ impl RenderState {
 					label: Some("render_encoder"),
 				});
 
-		{
-			let mut render_pass = render_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-				label: Some("render_pass"),
-				color_attachments: &[wgpu::RenderPassColorAttachment {
-					view: &view,
-					resolve_target: None,
+		self.draw_pass(&mut render_encoder, &view, camera_bind_group, &draws);
+
+		let readback = if capture {
+			let capture_texture;
+			let texture = match &self.target {
+				RenderTarget::Offscreen(texture) => &texture.texture,
+				RenderTarget::Surface(_) => {
+					capture_texture = Texture::create_color_texture(
+						&self.device,
+						&self.config,
+						"capture_texture",
+					);
+					self.draw_pass(
+						&mut render_encoder,
+						&capture_texture.view,
+						camera_bind_group,
+						&draws,
+					);
+					&capture_texture.texture
+				}
+			};
+			Some(FrameReadback::copy_from_texture(
+				&self.device,
+				&mut render_encoder,
+				texture,
+				self.config.width,
+				self.config.height,
+				self.config.format,
+			))
+		} else {
+			None
+		};
+
+		self.queue.submit(std::iter::once(render_encoder.finish()));
+		if let Some(output) = output {
+			output.present();
+		}
+
+		Ok(readback)
+	}
+	/// Records one render pass drawing `draws` into `view`.
+	fn draw_pass(
+		&self,
+		encoder: &mut wgpu::CommandEncoder,
+		view: &wgpu::TextureView,
+		camera_bind_group: &CameraBindGroup,
+		draws: &[(usize, MeshDraw)],
+	) {
+		let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("render_pass"),
+			color_attachments: &[match &self.multisampled_texture {
+				Some(multisampled) => wgpu::RenderPassColorAttachment {
+					view: &multisampled.view,
+					resolve_target: Some(view),
 					ops: wgpu::Operations {
-						load: wgpu::LoadOp::Clear(wgpu::Color {
-							r: 0.1,
-							g: 0.2,
-							b: 0.3,
-							a: 1.0,
-						}),
+						load: wgpu::LoadOp::Clear(self.clear_color),
 						store: true,
 					},
-				}],
-				depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-					view: &self.depth_texture.view,
-					depth_ops: Some(wgpu::Operations {
-						load: wgpu::LoadOp::Clear(1.0),
+				},
+				None => wgpu::RenderPassColorAttachment {
+					view,
+					resolve_target: None,
+					ops: wgpu::Operations {
+						load: wgpu::LoadOp::Clear(self.clear_color),
 						store: true,
-					}),
-					stencil_ops: None,
+					},
+				},
+			}],
+			depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+				view: &self.depth_texture.view,
+				depth_ops: Some(wgpu::Operations {
+					load: wgpu::LoadOp::Clear(1.0),
+					store: true,
 				}),
-			});
-
-			render_pass.set_pipeline(&self.render_pipeline);
+				stencil_ops: None,
+			}),
+		});
 
-			render_pass.set_bind_group(0, &camera_bind_group.bind_group, &[]);
-			render_pass.set_bind_group(1, &self.diffuse_bind_group.bind_group, &[]);
+		render_pass.set_bind_group(0, &camera_bind_group.bind_group, &[]);
 
-			for mesh in meshes {
-				render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
-				render_pass
-					.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-				render_pass.draw_indexed(0..mesh.num_indices, 0, 0..1);
+		let mut pipeline_bound = None;
+		let mut material_bound = None;
+		for (i, (pipeline_id, draw)) in draws.iter().enumerate() {
+			if pipeline_bound != Some(*pipeline_id) {
+				render_pass.set_pipeline(&self.pipelines[*pipeline_id]);
+				pipeline_bound = Some(*pipeline_id);
 			}
-		}
 
-		self.queue.submit(std::iter::once(render_encoder.finish()));
-		output.present();
+			let material = draw.material.unwrap_or(&self.default_material);
+			let material_ptr = material as *const MaterialBindGroup;
+			if material_bound != Some(material_ptr) {
+				render_pass.set_bind_group(1, &material.bind_group.bind_group, &[]);
+				material_bound = Some(material_ptr);
+			}
 
-		Ok(())
+			render_pass.set_bind_group(
+				2,
+				&self.model_bind_group.bind_group,
+				&[self.model_bind_group.offset(i)],
+			);
+			let mesh = draw.mesh;
+			let mut slot = 0;
+			for buffer in &mesh.vertex_buffers {
+				render_pass.set_vertex_buffer(slot, buffer.slice(..));
+				slot += 1;
+			}
+			if !mesh.layout.has_shader_inputs() {
+				render_pass.set_vertex_buffer(slot, self.vertex_defaults.buffer.slice(..));
+				slot += 1;
+			}
+			render_pass.set_index_buffer(mesh.index_buffer.slice(..), mesh.index_format);
+			match draw.instances {
+				Some(instances) => {
+					render_pass.set_vertex_buffer(slot, instances.buffer.slice(..));
+					render_pass.draw_indexed(0..mesh.num_indices, 0, 0..instances.count);
+				}
+				None => render_pass.draw_indexed(0..mesh.num_indices, 0, 0..1),
+			}
+		}
 	}
 }
+
+fn log_adapter(adapter: &wgpu::Adapter) {
+	log::info!("selected adapter {:?}", adapter.get_info());
+}
@@ -0,0 +1,112 @@
+// File: screenshot.rs
+
+use anyhow::*;
+
+use std::path::PathBuf;
+
+/// Sent to ask the `render` system to save the next frame as a PNG.
+#[derive(Clone, Debug)]
+pub struct ScreenshotRequest {
+	pub path: PathBuf,
+}
+
+/// Copies a color texture into a mappable buffer so it can be read on the cpu.
+pub struct FrameReadback {
+	buffer: wgpu::Buffer,
+	width: u32,
+	height: u32,
+	padded_bytes_per_row: u32,
+	format: wgpu::TextureFormat,
+}
+impl FrameReadback {
+	pub const BYTES_PER_PIXEL: u32 = 4;
+
+	/// Records a copy of `texture` into a new readback buffer. The copy is only
+	/// performed once `encoder` has been submitted.
+	pub fn copy_from_texture(
+		device: &wgpu::Device,
+		encoder: &mut wgpu::CommandEncoder,
+		texture: &wgpu::Texture,
+		width: u32,
+		height: u32,
+		format: wgpu::TextureFormat,
+	) -> Self {
+		let padded_bytes_per_row = Self::padded_bytes_per_row(width);
+		let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("frame_readback_buffer"),
+			size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+			usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+			mapped_at_creation: false,
+		});
+
+		encoder.copy_texture_to_buffer(
+			wgpu::ImageCopyTexture {
+				aspect: wgpu::TextureAspect::All,
+				texture,
+				mip_level: 0,
+				origin: wgpu::Origin3d::ZERO,
+			},
+			wgpu::ImageCopyBuffer {
+				buffer: &buffer,
+				layout: wgpu::ImageDataLayout {
+					offset: 0,
+					bytes_per_row: std::num::NonZeroU32::new(padded_bytes_per_row),
+					rows_per_image: std::num::NonZeroU32::new(height),
+				},
+			},
+			wgpu::Extent3d {
+				width,
+				height,
+				depth_or_array_layers: 1,
+			},
+		);
+
+		Self {
+			buffer,
+			width,
+			height,
+			padded_bytes_per_row,
+			format,
+		}
+	}
+
+	/// Rows in a texture to buffer copy must be a multiple of 256 bytes.
+	pub fn padded_bytes_per_row(width: u32) -> u32 {
+		let unpadded = width * Self::BYTES_PER_PIXEL;
+		let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+		unpadded.div_ceil(align) * align
+	}
+
+	/// Blocks until the copy has finished and strips the row padding.
+	pub fn into_image(self, device: &wgpu::Device) -> Result<image::RgbaImage> {
+		let swap_red_blue = match self.format {
+			wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+			wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+			format => bail!("can not read back frames with format {:?}", format),
+		};
+
+		let slice = self.buffer.slice(..);
+		let mapping = slice.map_async(wgpu::MapMode::Read);
+		device.poll(wgpu::Maintain::Wait);
+		pollster::block_on(mapping)?;
+
+		let row_bytes = (self.width * Self::BYTES_PER_PIXEL) as usize;
+		let mut pixels = Vec::with_capacity(row_bytes * self.height as usize);
+		{
+			let data = slice.get_mapped_range();
+			for row in data.chunks(self.padded_bytes_per_row as usize) {
+				pixels.extend_from_slice(&row[..row_bytes]);
+			}
+		}
+		self.buffer.unmap();
+
+		if swap_red_blue {
+			for pixel in pixels.chunks_exact_mut(4) {
+				pixel.swap(0, 2);
+			}
+		}
+
+		image::RgbaImage::from_raw(self.width, self.height, pixels)
+			.ok_or_else(|| anyhow!("readback buffer does not match the frame size"))
+	}
+}
@@ -0,0 +1,47 @@
+// File: settings.rs
+
+use crate::adapter::AdapterSelection;
+
+/// Read once when the renderer is created. Insert it before adding
+/// `WgpuRendererPlugin` to override the defaults.
+#[derive(Clone, Debug)]
+pub struct RendererSettings {
+	pub backends: wgpu::Backends,
+	pub present_mode: wgpu::PresentMode,
+	pub clear_color: wgpu::Color,
+	/// Samples per pixel, 1 disables multisampling. wgpu supports 1 and 4,
+	/// anything else fails renderer creation.
+	pub msaa_samples: u32,
+	pub power_preference: wgpu::PowerPreference,
+	/// Picks the adapter, `None` derives the choice from `power_preference`.
+	pub adapter: Option<AdapterSelection>,
+}
+impl Default for RendererSettings {
+	fn default() -> Self {
+		Self {
+			backends: wgpu::Backends::PRIMARY,
+			present_mode: wgpu::PresentMode::Fifo,
+			clear_color: wgpu::Color {
+				r: 0.1,
+				g: 0.2,
+				b: 0.3,
+				a: 1.0,
+			},
+			msaa_samples: 1,
+			power_preference: wgpu::PowerPreference::HighPerformance,
+			adapter: None,
+		}
+	}
+}
+impl RendererSettings {
+	/// `backends`, overridden by the `WGPU_BACKEND` environment variable.
+	pub fn effective_backends(&self) -> wgpu::Backends {
+		wgpu::util::backend_bits_from_env().unwrap_or(self.backends)
+	}
+	/// `adapter`, overridden by the `WGPU_ADAPTER_NAME` environment variable.
+	pub fn effective_adapter(&self) -> AdapterSelection {
+		AdapterSelection::from_env()
+			.or_else(|| self.adapter.clone())
+			.unwrap_or_else(|| AdapterSelection::from_power_preference(self.power_preference))
+	}
+}
@@ -47,6 +47,110 @@ impl TextureBindGroup {
 		});
 		TextureBindGroup { layout, bind_group }
 	}
+	/// Binds each texture and its sampler at consecutive bindings, followed by
+	/// `uniform` at binding `2 * textures.len()`.
+	pub fn with_uniform(
+		device: &wgpu::Device,
+		label: Option<&str>,
+		textures: &[&Texture],
+		uniform: &wgpu::Buffer,
+	) -> Self {
+		let layout = Self::create_layout_with_uniform(device, textures.len());
+
+		let mut entries = Vec::with_capacity(textures.len() * 2 + 1);
+		for (i, texture) in textures.iter().enumerate() {
+			entries.push(wgpu::BindGroupEntry {
+				binding: (i * 2) as u32,
+				resource: wgpu::BindingResource::TextureView(&texture.view),
+			});
+			entries.push(wgpu::BindGroupEntry {
+				binding: (i * 2 + 1) as u32,
+				resource: wgpu::BindingResource::Sampler(&texture.sampler),
+			});
+		}
+		entries.push(wgpu::BindGroupEntry {
+			binding: (textures.len() * 2) as u32,
+			resource: uniform.as_entire_binding(),
+		});
+
+		let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			layout: &layout,
+			entries: &entries,
+			label,
+		});
+		TextureBindGroup { layout, bind_group }
+	}
+	pub fn create_layout_with_uniform(
+		device: &wgpu::Device,
+		texture_count: usize,
+	) -> wgpu::BindGroupLayout {
+		let mut entries = Vec::with_capacity(texture_count * 2 + 1);
+		for i in 0..texture_count {
+			entries.push(wgpu::BindGroupLayoutEntry {
+				binding: (i * 2) as u32,
+				visibility: wgpu::ShaderStages::FRAGMENT,
+				ty: wgpu::BindingType::Texture {
+					multisampled: false,
+					view_dimension: wgpu::TextureViewDimension::D2,
+					sample_type: wgpu::TextureSampleType::Float { filterable: true },
+				},
+				count: None,
+			});
+			entries.push(wgpu::BindGroupLayoutEntry {
+				binding: (i * 2 + 1) as u32,
+				visibility: wgpu::ShaderStages::FRAGMENT,
+				ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+				count: None,
+			});
+		}
+		entries.push(wgpu::BindGroupLayoutEntry {
+			binding: (texture_count * 2) as u32,
+			visibility: wgpu::ShaderStages::FRAGMENT,
+			ty: wgpu::BindingType::Buffer {
+				ty: wgpu::BufferBindingType::Uniform,
+				has_dynamic_offset: false,
+				min_binding_size: None,
+			},
+			count: None,
+		});
+
+		device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			entries: &entries,
+			label: Some("texture uniform bind group layout"),
+		})
+	}
+}
+
+/// Addressing and filtering used when sampling a texture.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SamplerSettings {
+	pub address_mode: wgpu::AddressMode,
+	pub mag_filter: wgpu::FilterMode,
+	pub min_filter: wgpu::FilterMode,
+	pub mipmap_filter: wgpu::FilterMode,
+}
+impl Default for SamplerSettings {
+	fn default() -> Self {
+		Self {
+			address_mode: wgpu::AddressMode::ClampToEdge,
+			mag_filter: wgpu::FilterMode::Linear,
+			min_filter: wgpu::FilterMode::Nearest,
+			mipmap_filter: wgpu::FilterMode::Nearest,
+		}
+	}
+}
+impl SamplerSettings {
+	pub fn create_sampler(&self, device: &wgpu::Device) -> wgpu::Sampler {
+		device.create_sampler(&wgpu::SamplerDescriptor {
+			address_mode_u: self.address_mode,
+			address_mode_v: self.address_mode,
+			address_mode_w: self.address_mode,
+			mag_filter: self.mag_filter,
+			min_filter: self.min_filter,
+			mipmap_filter: self.mipmap_filter,
+			..Default::default()
+		})
+	}
 }
 pub struct Texture {
 	pub texture: wgpu::Texture,
@@ -56,10 +160,16 @@ pub struct Texture {
 
 impl Texture {
 	pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+	pub const COLOR_TARGET_USAGES: wgpu::TextureUsages = wgpu::TextureUsages::from_bits_truncate(
+		wgpu::TextureUsages::RENDER_ATTACHMENT.bits()
+			| wgpu::TextureUsages::TEXTURE_BINDING.bits()
+			| wgpu::TextureUsages::COPY_SRC.bits(),
+	);
 
 	pub fn create_depth_texture(
 		device: &wgpu::Device,
 		config: &wgpu::SurfaceConfiguration,
+		sample_count: u32,
 		label: &str,
 	) -> Self {
 		let size = wgpu::Extent3d {
@@ -71,7 +181,7 @@ impl Texture {
 			label: Some(label),
 			size,
 			mip_level_count: 1,
-			sample_count: 1,
+			sample_count,
 			dimension: wgpu::TextureDimension::D2,
 			format: Self::DEPTH_FORMAT,
 			usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
@@ -99,6 +209,76 @@ impl Texture {
 		}
 	}
 
+	pub fn create_color_texture(
+		device: &wgpu::Device,
+		config: &wgpu::SurfaceConfiguration,
+		label: &str,
+	) -> Self {
+		let size = wgpu::Extent3d {
+			width: config.width,
+			height: config.height,
+			depth_or_array_layers: 1,
+		};
+		let texture = device.create_texture(&wgpu::TextureDescriptor {
+			label: Some(label),
+			size,
+			mip_level_count: 1,
+			sample_count: 1,
+			dimension: wgpu::TextureDimension::D2,
+			format: config.format,
+			usage: Self::COLOR_TARGET_USAGES,
+		});
+
+		let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+		let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+			address_mode_u: wgpu::AddressMode::ClampToEdge,
+			address_mode_v: wgpu::AddressMode::ClampToEdge,
+			address_mode_w: wgpu::AddressMode::ClampToEdge,
+			mag_filter: wgpu::FilterMode::Linear,
+			min_filter: wgpu::FilterMode::Nearest,
+			mipmap_filter: wgpu::FilterMode::Nearest,
+			..Default::default()
+		});
+
+		Self {
+			texture,
+			view,
+			sampler,
+		}
+	}
+
+	/// Color target that is resolved into the frame when multisampling is enabled.
+	pub fn create_multisampled_texture(
+		device: &wgpu::Device,
+		config: &wgpu::SurfaceConfiguration,
+		sample_count: u32,
+		label: &str,
+	) -> Self {
+		let size = wgpu::Extent3d {
+			width: config.width,
+			height: config.height,
+			depth_or_array_layers: 1,
+		};
+		let texture = device.create_texture(&wgpu::TextureDescriptor {
+			label: Some(label),
+			size,
+			mip_level_count: 1,
+			sample_count,
+			dimension: wgpu::TextureDimension::D2,
+			format: config.format,
+			usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+		});
+
+		let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+		let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+
+		Self {
+			texture,
+			view,
+			sampler,
+		}
+	}
+
 	pub fn from_bytes(
 		device: &wgpu::Device,
 		queue: &wgpu::Queue,
@@ -115,7 +295,27 @@ impl Texture {
 		img: &image::DynamicImage,
 		label: Option<&str>,
 	) -> Result<Self> {
-		let rgba = img.as_rgba8().unwrap();
+		Self::from_image_with(
+			device,
+			queue,
+			img,
+			label,
+			wgpu::TextureFormat::Rgba8UnormSrgb,
+			&SamplerSettings::default(),
+		)
+	}
+
+	/// Like `from_image`, for data that is not srgb color (e.g. normal maps) or
+	/// needs different sampling.
+	pub fn from_image_with(
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		img: &image::DynamicImage,
+		label: Option<&str>,
+		format: wgpu::TextureFormat,
+		sampler: &SamplerSettings,
+	) -> Result<Self> {
+		let rgba = img.to_rgba8();
 		let dimensions = img.dimensions();
 
 		let size = wgpu::Extent3d {
@@ -129,7 +329,7 @@ impl Texture {
 			mip_level_count: 1,
 			sample_count: 1,
 			dimension: wgpu::TextureDimension::D2,
-			format: wgpu::TextureFormat::Rgba8UnormSrgb,
+			format,
 			usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
 		});
 
@@ -140,7 +340,7 @@ impl Texture {
 				mip_level: 0,
 				origin: wgpu::Origin3d::ZERO,
 			},
-			rgba,
+			&rgba,
 			wgpu::ImageDataLayout {
 				offset: 0,
 				bytes_per_row: std::num::NonZeroU32::new(4 * dimensions.0),
@@ -150,15 +350,7 @@ impl Texture {
 		);
 
 		let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-		let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-			address_mode_u: wgpu::AddressMode::ClampToEdge,
-			address_mode_v: wgpu::AddressMode::ClampToEdge,
-			address_mode_w: wgpu::AddressMode::ClampToEdge,
-			mag_filter: wgpu::FilterMode::Linear,
-			min_filter: wgpu::FilterMode::Nearest,
-			mipmap_filter: wgpu::FilterMode::Nearest,
-			..Default::default()
-		});
+		let sampler = sampler.create_sampler(device);
 
 		Ok(Self {
 			texture,
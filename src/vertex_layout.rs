@@ -0,0 +1,332 @@
+// File: vertex_layout.rs
+
+/// What a vertex attribute holds. Every semantic is read from a fixed shader
+/// location, so meshes with different layouts can share one shader.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum VertexSemantic {
+	Position,
+	Color,
+	Uv,
+	Normal,
+	Tangent,
+	/// Indices of the joints skinning a vertex.
+	Joints,
+	/// Weights of `Joints`.
+	Weights,
+}
+impl VertexSemantic {
+	/// Semantics the shader reads. Meshes without one of them get a constant
+	/// default, see `VertexSemantic::default_value`.
+	pub const SHADER_INPUTS: [VertexSemantic; 5] = [
+		VertexSemantic::Position,
+		VertexSemantic::Color,
+		VertexSemantic::Uv,
+		VertexSemantic::Normal,
+		VertexSemantic::Tangent,
+	];
+
+	pub fn shader_location(self) -> wgpu::ShaderLocation {
+		self as wgpu::ShaderLocation
+	}
+	pub fn default_format(self) -> wgpu::VertexFormat {
+		match self {
+			VertexSemantic::Position | VertexSemantic::Color | VertexSemantic::Normal => {
+				wgpu::VertexFormat::Float32x3
+			}
+			VertexSemantic::Uv => wgpu::VertexFormat::Float32x2,
+			VertexSemantic::Tangent | VertexSemantic::Weights => wgpu::VertexFormat::Float32x4,
+			VertexSemantic::Joints => wgpu::VertexFormat::Uint16x4,
+		}
+	}
+	/// Value of a missing attribute, in `default_format`.
+	pub fn default_value(self) -> Vec<u8> {
+		let floats: &[f32] = match self {
+			VertexSemantic::Position => &[0.0, 0.0, 0.0],
+			VertexSemantic::Uv => &[0.0, 0.0],
+			VertexSemantic::Color => &[1.0, 1.0, 1.0],
+			VertexSemantic::Normal => &[0.0, 0.0, 1.0],
+			VertexSemantic::Tangent => &[1.0, 0.0, 0.0, 1.0],
+			VertexSemantic::Weights => &[1.0, 0.0, 0.0, 0.0],
+			VertexSemantic::Joints => return vec![0; 8],
+		};
+		bytemuck::cast_slice(floats).to_vec()
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct VertexAttributeLayout {
+	pub semantic: VertexSemantic,
+	pub format: wgpu::VertexFormat,
+	/// Byte offset inside a vertex of the stream.
+	pub offset: wgpu::BufferAddress,
+}
+
+/// The attributes stored in one vertex buffer.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct VertexStream {
+	pub stride: wgpu::BufferAddress,
+	pub attributes: Vec<VertexAttributeLayout>,
+}
+impl VertexStream {
+	/// Packs `semantics` in order with their default formats.
+	pub fn packed(semantics: &[VertexSemantic]) -> Self {
+		let mut stride = 0;
+		let attributes = semantics
+			.iter()
+			.map(|semantic| {
+				let format = semantic.default_format();
+				let attribute = VertexAttributeLayout {
+					semantic: *semantic,
+					format,
+					offset: stride,
+				};
+				stride += format.size();
+				attribute
+			})
+			.collect();
+		Self { stride, attributes }
+	}
+	pub fn wgpu_attributes(&self) -> Vec<wgpu::VertexAttribute> {
+		self.attributes
+			.iter()
+			.map(|attribute| wgpu::VertexAttribute {
+				format: attribute.format,
+				offset: attribute.offset,
+				shader_location: attribute.semantic.shader_location(),
+			})
+			.collect()
+	}
+}
+
+/// How a mesh stores its vertices: one stream when the attributes are
+/// interleaved, several when they live in separate buffers. Render pipelines
+/// are created per layout.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct VertexLayout {
+	pub streams: Vec<VertexStream>,
+}
+impl VertexLayout {
+	/// Every attribute in one buffer.
+	pub fn interleaved(semantics: &[VertexSemantic]) -> Self {
+		Self {
+			streams: vec![VertexStream::packed(semantics)],
+		}
+	}
+	/// One buffer per attribute.
+	pub fn separate(semantics: &[VertexSemantic]) -> Self {
+		Self {
+			streams: semantics
+				.iter()
+				.map(|semantic| VertexStream::packed(&[*semantic]))
+				.collect(),
+		}
+	}
+	pub fn contains(&self, semantic: VertexSemantic) -> bool {
+		self.streams.iter().any(|stream| {
+			stream
+				.attributes
+				.iter()
+				.any(|attribute| attribute.semantic == semantic)
+		})
+	}
+	pub fn has_shader_inputs(&self) -> bool {
+		VertexSemantic::SHADER_INPUTS
+			.iter()
+			.all(|semantic| self.contains(*semantic))
+	}
+	/// Reads the `Float32x3` positions out of raw vertex `streams`, `None` when
+	/// the layout stores positions differently or not at all.
+	pub fn read_positions(&self, streams: &[&[u8]]) -> Option<Vec<[f32; 3]>> {
+		let (stream, bytes, attribute) =
+			self.streams
+				.iter()
+				.zip(streams)
+				.find_map(|(stream, bytes)| {
+					let attribute = stream
+						.attributes
+						.iter()
+						.find(|attribute| attribute.semantic == VertexSemantic::Position)?;
+					Some((stream, bytes, attribute))
+				})?;
+		if attribute.format != wgpu::VertexFormat::Float32x3 || stream.stride == 0 {
+			return None;
+		}
+		let (stride, offset) = (stream.stride as usize, attribute.offset as usize);
+		Some(
+			bytes
+				.chunks_exact(stride)
+				.map(|vertex| bytemuck::pod_read_unaligned(&vertex[offset..offset + 12]))
+				.collect(),
+		)
+	}
+	/// Shader inputs the layout does not provide.
+	pub fn missing_shader_inputs(&self) -> Vec<VertexSemantic> {
+		VertexSemantic::SHADER_INPUTS
+			.iter()
+			.copied()
+			.filter(|semantic| !self.contains(*semantic))
+			.collect()
+	}
+}
+
+/// Default values of all shader inputs in one buffer. Bound with a stride of 0,
+/// so every vertex reads the same value, for layouts missing some inputs.
+pub struct VertexDefaults {
+	pub buffer: wgpu::Buffer,
+}
+impl VertexDefaults {
+	pub fn new(device: &wgpu::Device) -> Self {
+		use wgpu::util::DeviceExt;
+
+		let contents: Vec<u8> = VertexSemantic::SHADER_INPUTS
+			.iter()
+			.flat_map(|semantic| semantic.default_value())
+			.collect();
+		let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("vertex defaults buffer"),
+			contents: &contents,
+			usage: wgpu::BufferUsages::VERTEX,
+		});
+		Self { buffer }
+	}
+	/// The stream reading `layout`'s missing inputs from the defaults buffer,
+	/// `None` when nothing is missing.
+	pub fn stream_for(layout: &VertexLayout) -> Option<VertexStream> {
+		if layout.has_shader_inputs() {
+			return None;
+		}
+		let missing = layout.missing_shader_inputs();
+		let mut offset = 0;
+		let mut attributes = vec![];
+		for semantic in VertexSemantic::SHADER_INPUTS {
+			let format = semantic.default_format();
+			if missing.contains(&semantic) {
+				attributes.push(VertexAttributeLayout {
+					semantic,
+					format,
+					offset,
+				});
+			}
+			offset += format.size();
+		}
+		Some(VertexStream {
+			stride: 0,
+			attributes,
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use VertexSemantic::*;
+
+	fn offsets(stream: &VertexStream) -> Vec<(VertexSemantic, wgpu::BufferAddress)> {
+		stream
+			.attributes
+			.iter()
+			.map(|attribute| (attribute.semantic, attribute.offset))
+			.collect()
+	}
+
+	#[test]
+	fn packed_offsets_and_stride() {
+		let stream = VertexStream::packed(&VertexSemantic::SHADER_INPUTS);
+		assert_eq!(stream.stride, 60);
+		assert_eq!(
+			offsets(&stream),
+			[
+				(Position, 0),
+				(Color, 12),
+				(Uv, 24),
+				(Normal, 32),
+				(Tangent, 44)
+			]
+		);
+		let locations: Vec<_> = stream
+			.wgpu_attributes()
+			.iter()
+			.map(|attribute| attribute.shader_location)
+			.collect();
+		assert_eq!(locations, [0, 1, 2, 3, 4]);
+
+		let skinned = VertexStream::packed(&[Position, Joints, Weights]);
+		assert_eq!(skinned.stride, 12 + 8 + 16);
+		assert_eq!(
+			offsets(&skinned),
+			[(Position, 0), (Joints, 12), (Weights, 20)]
+		);
+	}
+
+	#[test]
+	fn separate_streams_start_at_zero() {
+		let layout = VertexLayout::separate(&[Position, Uv, Tangent]);
+		let strides: Vec<_> = layout.streams.iter().map(|stream| stream.stride).collect();
+		assert_eq!(strides, [12, 8, 16]);
+		assert!(layout
+			.streams
+			.iter()
+			.all(|stream| stream.attributes.len() == 1 && stream.attributes[0].offset == 0));
+		assert!(layout.contains(Uv));
+		assert!(!layout.contains(Normal));
+		assert_eq!(layout.missing_shader_inputs(), [Color, Normal]);
+	}
+
+	#[test]
+	fn default_streams_read_the_missing_inputs() {
+		let complete = VertexLayout::interleaved(&VertexSemantic::SHADER_INPUTS);
+		assert_eq!(VertexDefaults::stream_for(&complete), None);
+
+		let layout = VertexLayout::interleaved(&[Position, Uv]);
+		let stream = VertexDefaults::stream_for(&layout).unwrap();
+		assert_eq!(stream.stride, 0);
+		assert_eq!(offsets(&stream), [(Color, 12), (Normal, 32), (Tangent, 44)]);
+
+		// the offsets point at the defaults in the buffer `VertexDefaults::new` fills
+		let contents: Vec<u8> = VertexSemantic::SHADER_INPUTS
+			.iter()
+			.flat_map(|semantic| semantic.default_value())
+			.collect();
+		for attribute in &stream.attributes {
+			let start = attribute.offset as usize;
+			let end = start + attribute.format.size() as usize;
+			assert_eq!(contents[start..end], attribute.semantic.default_value());
+		}
+	}
+
+	#[test]
+	fn positions_from_packed_and_separate_streams() {
+		let positions = [[1.0f32, 2.0, 3.0], [4.0, 5.0, 6.0]];
+		let uvs = [[0.25f32, 0.5], [0.75, 1.0]];
+
+		let mut packed = vec![];
+		for (position, uv) in positions.iter().zip(&uvs) {
+			packed.extend_from_slice(bytemuck::cast_slice(&uv[..]));
+			packed.extend_from_slice(bytemuck::cast_slice(&position[..]));
+		}
+		let layout = VertexLayout::interleaved(&[Uv, Position]);
+		assert_eq!(layout.read_positions(&[&packed]), Some(positions.to_vec()));
+
+		let layout = VertexLayout::separate(&[Uv, Position]);
+		let streams: [&[u8]; 2] = [
+			bytemuck::cast_slice(&uvs[..]),
+			bytemuck::cast_slice(&positions[..]),
+		];
+		assert_eq!(layout.read_positions(&streams), Some(positions.to_vec()));
+
+		let without_positions = VertexLayout::interleaved(&[Uv]);
+		assert_eq!(without_positions.read_positions(&[&packed]), None);
+		let half_positions = VertexLayout {
+			streams: vec![VertexStream {
+				stride: 8,
+				attributes: vec![VertexAttributeLayout {
+					semantic: Position,
+					format: wgpu::VertexFormat::Float16x4,
+					offset: 0,
+				}],
+			}],
+		};
+		assert_eq!(half_positions.read_positions(&[&packed]), None);
+	}
+}